@@ -0,0 +1,825 @@
+// src-tauri/bin/iptables.rs
+//! iptables/ip6tables kill switch backend, for machines where `nft` is unavailable
+//! (iptables-legacy setups, container hosts). Rules live in our own chains, jumped to from
+//! OUTPUT, and each table is replaced in one `iptables-restore --noflush` commit.
+
+use std::{
+    io::Write,
+    net::IpAddr,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use serde_json::{Map, Value};
+
+use crate::killswitch_exemption::Exemption;
+use crate::{
+    AllowedRemote, DropStats, Firewall, KillSwitchPolicy, DROP_LOG_PREFIX, LAN_V4, LAN_V6,
+};
+
+const KILLSWITCH_CHAIN: &str = "STELLAR_KILLSWITCH";
+// nat chain of older versions, which redirected DNS; removed on enable and disable.
+const OLD_DNS_NAT_CHAIN: &str = "STELLAR_KILLSWITCH_DNS";
+const IPV6_BLOCK_CHAIN: &str = "STELLAR_IPV6_BLOCK";
+
+// Comments on the accept rules, so `status` can read the policy back from `iptables -S`.
+const TAG_TUNNEL: &str = "stellar-tunnel";
+const TAG_DNS: &str = "stellar-dns";
+const TAG_LAN: &str = "stellar-lan";
+const TAG_REMOTE: &str = "stellar-remote";
+const TAG_EXEMPT: &str = "stellar-exempt";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Family {
+    V4,
+    V6,
+}
+
+impl Family {
+    fn bin(self) -> &'static str {
+        match self {
+            Family::V4 => "iptables",
+            Family::V6 => "ip6tables",
+        }
+    }
+
+    fn restore_bin(self) -> &'static str {
+        match self {
+            Family::V4 => "iptables-restore",
+            Family::V6 => "ip6tables-restore",
+        }
+    }
+
+    fn matches(self, ip: &IpAddr) -> bool {
+        ip.is_ipv6() == (self == Family::V6)
+    }
+
+    fn lan(self) -> &'static [&'static str] {
+        match self {
+            Family::V4 => LAN_V4,
+            Family::V6 => LAN_V6,
+        }
+    }
+}
+
+pub struct Iptables {
+    families: Vec<Family>,
+}
+
+impl Iptables {
+    /// None when `iptables` is missing or unusable.
+    pub fn detect() -> Option<Self> {
+        run(Family::V4, &["-n", "-L", "OUTPUT"]).ok()?;
+        Some(Self::unprobed())
+    }
+
+    /// The families this host needs, without running iptables (for dry runs).
+    pub fn unprobed() -> Self {
+        // With IPv6 enabled an IPv4-only kill switch would leak, so ip6tables is required
+        // then (its errors surface on enable).
+        let mut families = vec![Family::V4];
+        if Path::new("/proc/net/if_inet6").exists() {
+            families.push(Family::V6);
+        }
+        Iptables { families }
+    }
+
+    /// One restore script per family. A dry run (`live` false) does not look up which
+    /// OUTPUT jumps exist and shows the scripts for a firewall without them.
+    fn scripts(&self, p: &KillSwitchPolicy, live: bool) -> Result<Vec<(Family, String)>, String> {
+        let mut out = Vec::new();
+        for &fam in &self.families {
+            let jump = !live || !has_jump(fam, "filter", KILLSWITCH_CHAIN)?;
+            let s = table_script("filter", KILLSWITCH_CHAIN, &filter_rules(fam, p), jump);
+            out.push((fam, s));
+        }
+        Ok(out)
+    }
+}
+
+impl Firewall for Iptables {
+    fn name(&self) -> &'static str {
+        "iptables"
+    }
+
+    fn plan(&self, p: &KillSwitchPolicy) -> Result<Value, String> {
+        let mut m = Map::new();
+        for (fam, s) in self.scripts(p, false)? {
+            m.insert(fam.restore_bin().to_string(), Value::String(s));
+        }
+        Ok(Value::Object(m))
+    }
+
+    fn enable(&self, p: &KillSwitchPolicy) -> Result<(), String> {
+        // One commit per family: if a later one fails, put the earlier ones back as they
+        // were, so IPv4 and IPv6 never run different kill switches.
+        let mut loaded = Vec::new();
+        for (fam, s) in self.scripts(p, true)? {
+            let before = chain_rules(fam, "filter", KILLSWITCH_CHAIN)?;
+            if let Err(e) = restore(fam, &s) {
+                return Err(roll_back(loaded, e));
+            }
+            loaded.push((fam, before));
+        }
+        remove_chain(Family::V4, "nat", OLD_DNS_NAT_CHAIN)?;
+
+        // Same guarantee as the nft path: what the kernel holds is what we meant to load.
+        let drift = self.drift(p)?;
+        if drift.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "iptables accepted the rules but they do not match:\n- {}",
+                drift.join("\n- ")
+            ))
+        }
+    }
+
+    fn set_tunnel(&self, p: &KillSwitchPolicy) -> Result<(), String> {
+        // No sets to refill: rebuild the chains from the plan, which carries the new tunnel.
+        if chain_rules(Family::V4, "filter", KILLSWITCH_CHAIN)?.is_none() {
+            return Err("Kill switch is not active".to_string());
+        }
+        self.enable(p)
+    }
+
+    fn disable(&self) -> Result<(), String> {
+        for &fam in &self.families {
+            remove_chain(fam, "filter", KILLSWITCH_CHAIN)?;
+        }
+        remove_chain(Family::V4, "nat", OLD_DNS_NAT_CHAIN)
+    }
+
+    fn read_policy(&self) -> Result<Option<KillSwitchPolicy>, String> {
+        let mut p = KillSwitchPolicy::default();
+        let mut found = false;
+
+        for &fam in &self.families {
+            let Some(rules) = chain_rules(fam, "filter", KILLSWITCH_CHAIN)? else {
+                continue;
+            };
+            found = true;
+            for line in &rules {
+                read_rule(&mut p, line);
+            }
+        }
+
+        if !found {
+            return Ok(None);
+        }
+        p.normalize();
+        Ok(Some(p))
+    }
+
+    fn drift(&self, p: &KillSwitchPolicy) -> Result<Vec<String>, String> {
+        let mut drift = Vec::new();
+
+        for &fam in &self.families {
+            let chains = [("filter", KILLSWITCH_CHAIN, filter_rules(fam, p))];
+            for (table, chain, want) in chains {
+                let what = format!("{} {table}/{chain}", fam.bin());
+                let Some(live) = chain_rules(fam, table, chain)? else {
+                    drift.push(format!("{what} is missing"));
+                    continue;
+                };
+                if !has_jump(fam, table, chain)? {
+                    drift.push(format!("{what} is not jumped to from OUTPUT"));
+                }
+                drift.extend(chain_drift(&what, &live, &want));
+            }
+        }
+
+        Ok(drift)
+    }
+
+    fn drop_stats(&self) -> Result<Option<DropStats>, String> {
+        // Per-rule counters only: iptables has no per-destination meter we could read back
+        // without root (xt_recent's /proc files).
+        let mut stats = DropStats::default();
+        let mut found = false;
+
+        for &fam in &self.families {
+            let out = match run(
+                fam,
+                &["-t", "filter", "-L", KILLSWITCH_CHAIN, "-v", "-x", "-n"],
+            ) {
+                Ok(out) => out,
+                Err(e) if is_missing(&e) => continue,
+                Err(e) => return Err(e),
+            };
+            found = true;
+
+            // pkts bytes target prot opt in out source destination
+            for line in out.lines().skip(2) {
+                let cols: Vec<&str> = line.split_whitespace().collect();
+                if let [pkts, bytes, "DROP", ..] = cols.as_slice() {
+                    stats.packets += pkts.parse::<u64>().unwrap_or(0);
+                    stats.bytes += bytes.parse::<u64>().unwrap_or(0);
+                }
+            }
+        }
+
+        Ok(found.then_some(stats))
+    }
+
+    fn set_ipv6_block(&self, block: bool, remote: Option<&AllowedRemote>) -> Result<(), String> {
+        // IPv6 disabled in the kernel: nothing can leak.
+        if !self.families.contains(&Family::V6) {
+            return Ok(());
+        }
+        if !block {
+            return remove_chain(Family::V6, "filter", IPV6_BLOCK_CHAIN);
+        }
+        let rules = ipv6_block_rules(remote);
+        let jump = !has_jump(Family::V6, "filter", IPV6_BLOCK_CHAIN)?;
+        restore(
+            Family::V6,
+            &table_script("filter", IPV6_BLOCK_CHAIN, &rules, jump),
+        )
+    }
+
+    fn ipv6_blocked(&self) -> Result<bool, String> {
+        if !self.families.contains(&Family::V6) {
+            return Ok(false);
+        }
+        Ok(chain_rules(Family::V6, "filter", IPV6_BLOCK_CHAIN)?.is_some())
+    }
+}
+
+// Same order as the nftables output chain.
+fn filter_rules(fam: Family, p: &KillSwitchPolicy) -> Vec<String> {
+    let mut rules = vec![
+        "-o lo -j ACCEPT".to_string(),
+        "-m conntrack --ctstate RELATED,ESTABLISHED -j ACCEPT".to_string(),
+    ];
+
+    for dev in &p.tunnel_ifaces {
+        for ip in p.dns.iter().filter(|ip| fam.matches(ip)) {
+            for l4 in ["udp", "tcp"] {
+                rules.push(format!(
+                    "-o {dev} -d {ip} -p {l4} --dport 53 -m comment --comment {TAG_DNS} -j ACCEPT"
+                ));
+            }
+        }
+    }
+    for l4 in ["udp", "tcp"] {
+        rules.push(format!("-p {l4} --dport 53 -j DROP"));
+    }
+
+    for dev in &p.tunnel_ifaces {
+        rules.push(format!(
+            "-o {dev} -m comment --comment {TAG_TUNNEL} -j ACCEPT"
+        ));
+    }
+
+    if p.allow_lan {
+        for cidr in fam.lan() {
+            rules.push(format!(
+                "-d {cidr} -m comment --comment {TAG_LAN} -j ACCEPT"
+            ));
+        }
+    }
+
+    for r in p.remotes.iter().filter(|r| fam.matches(&r.ip)) {
+        rules.push(format!(
+            "-d {} -p {} --dport {} -m comment --comment {TAG_REMOTE} -j ACCEPT",
+            r.ip, r.proto, r.port
+        ));
+    }
+
+    // After the DNS drop, so an exemption never lets plain DNS past the tunnel.
+    for e in p.exemptions.iter().filter(|e| fam.matches(&e.addr)) {
+        let mut spec = format!("-d {}", e.cidr());
+        if let Some(proto) = e.proto {
+            spec.push_str(&format!(" -p {}", proto.as_str()));
+        }
+        if let Some(ports) = e.ports_str() {
+            spec.push_str(&format!(" --dport {}", ports.replace('-', ":")));
+        }
+        rules.push(format!(
+            "{spec} -m comment --comment {TAG_EXEMPT} -j ACCEPT"
+        ));
+    }
+
+    if p.log_drops {
+        rules.push(format!(
+            "-m limit --limit 10/min -j LOG --log-prefix \"{DROP_LOG_PREFIX}\""
+        ));
+    }
+    rules.push("-j DROP".to_string());
+    rules
+}
+
+/// The IPv6 block chain, like the nftables table: loopback and an IPv6 VPN server pass.
+fn ipv6_block_rules(remote: Option<&AllowedRemote>) -> Vec<String> {
+    let mut rules = vec!["-o lo -j ACCEPT".to_string()];
+    if let Some(r) = remote.filter(|r| r.ip.is_ipv6()) {
+        rules.push(format!(
+            "-d {} -p {} --dport {} -j ACCEPT",
+            r.ip, r.proto, r.port
+        ));
+    }
+    rules.push("-j DROP".to_string());
+    rules
+}
+
+/// Adds what one `iptables -S` line of our chain lets through (or logs) to `p`.
+fn read_rule(p: &mut KillSwitchPolicy, line: &str) {
+    if verdict(line) == "LOG" {
+        p.log_drops = true;
+        return;
+    }
+
+    let toks: Vec<&str> = line.split_whitespace().collect();
+    let opt = |name: &str| {
+        toks.iter()
+            .position(|t| *t == name)
+            .and_then(|i| toks.get(i + 1))
+            .map(|v| v.trim_matches('"'))
+    };
+    let addr = || {
+        opt("-d")
+            .and_then(|d| d.split('/').next())
+            .and_then(|d| d.parse::<IpAddr>().ok())
+    };
+
+    match opt("--comment") {
+        Some(TAG_TUNNEL) => p.tunnel_ifaces.extend(opt("-o").map(str::to_string)),
+        Some(TAG_DNS) => p.dns.extend(addr()),
+        Some(TAG_LAN) => p.allow_lan = true,
+        Some(TAG_REMOTE) => p.remotes.extend(addr().map(|ip| AllowedRemote {
+            ip,
+            proto: opt("-p").unwrap_or_default().to_string(),
+            port: opt("--dport").and_then(|v| v.parse().ok()).unwrap_or(0),
+        })),
+        Some(TAG_EXEMPT) => p.exemptions.extend(opt("-d").and_then(|d| {
+            // iptables lists port ranges as `lo:hi`.
+            let ports = opt("--dport").map(|v| v.replace(':', "-"));
+            Exemption::new(d, opt("-p"), ports.as_deref()).ok()
+        })),
+        _ => {}
+    }
+}
+
+/// How the `iptables -S` lines of one of our chains differ from the specs we load, rule by
+/// rule and in order.
+fn chain_drift(what: &str, live: &[String], want: &[String]) -> Vec<String> {
+    if live.len() != want.len() {
+        return vec![format!(
+            "{what} has {} rules, expected {}",
+            live.len(),
+            want.len()
+        )];
+    }
+    let mut drift = Vec::new();
+    for (i, (have, want)) in live.iter().zip(want).enumerate() {
+        let (have, want) = (rule_key(have), rule_key(want));
+        if have != want {
+            drift.push(format!(
+                "{what} rule {} is `{have}`, expected `{want}`",
+                i + 1
+            ));
+        }
+    }
+    drift
+}
+
+/// Canonical text of one of our rule specs or an `iptables -S` line of our chain, the same
+/// for both: without `-A <chain>` and the match module `-p` loads implicitly, with the
+/// prefix length iptables adds to a single address, and with the options sorted.
+fn rule_key(rule: &str) -> String {
+    let mut opts: Vec<String> = Vec::new();
+    let mut quoted = false;
+    let mut tok = String::new();
+    // A quoted `--log-prefix` may hold spaces.
+    for c in rule.chars().chain([' ']) {
+        if c == '"' {
+            quoted = !quoted;
+        }
+        if quoted || !c.is_whitespace() {
+            tok.push(c);
+            continue;
+        }
+        if tok.is_empty() {
+            continue;
+        }
+        match opts.last_mut() {
+            Some(opt) if !tok.starts_with('-') => {
+                opt.push(' ');
+                opt.push_str(&tok);
+            }
+            _ => opts.push(tok.clone()),
+        }
+        tok.clear();
+    }
+
+    let mut opts: Vec<String> = opts
+        .into_iter()
+        .filter(|o| !o.starts_with("-A ") && o != "-m tcp" && o != "-m udp")
+        .map(|o| match o.strip_prefix("-d ").map(str::parse::<IpAddr>) {
+            Some(Ok(IpAddr::V4(ip))) => format!("-d {ip}/32"),
+            Some(Ok(IpAddr::V6(ip))) => format!("-d {ip}/128"),
+            _ => o,
+        })
+        .collect();
+    opts.sort();
+    opts.join(" ")
+}
+
+/// Target of an `iptables -S` line or of one of our rule specs (`ACCEPT`, `DROP`, ...).
+fn verdict(rule: &str) -> &str {
+    rule.rsplit_once("-j ")
+        .and_then(|(_, t)| t.split_whitespace().next())
+        .unwrap_or("")
+}
+
+/// Declaring the chain with `--noflush` creates or empties it, so the whole chain is
+/// replaced when the table commits. The OUTPUT jump is only added when `jump` says it is
+/// missing.
+fn table_script(table: &str, chain: &str, rules: &[String], jump: bool) -> String {
+    let mut s = format!("*{table}\n:{chain} - [0:0]\n");
+    if jump {
+        s.push_str(&format!("-I OUTPUT 1 -j {chain}\n"));
+    }
+    for r in rules {
+        s.push_str(&format!("-A {chain} {r}\n"));
+    }
+    s.push_str("COMMIT\n");
+    s
+}
+
+fn has_jump(fam: Family, table: &str, chain: &str) -> Result<bool, String> {
+    match run(fam, &["-t", table, "-C", "OUTPUT", "-j", chain]) {
+        Ok(_) => Ok(true),
+        Err(e) if is_missing(&e) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// `iptables -S` lines of one of our chains; None when it does not exist.
+fn chain_rules(fam: Family, table: &str, chain: &str) -> Result<Option<Vec<String>>, String> {
+    match run(fam, &["-t", table, "-S", chain]) {
+        Ok(out) => Ok(Some(
+            out.lines()
+                .filter(|l| l.starts_with("-A "))
+                .map(str::to_string)
+                .collect(),
+        )),
+        Err(e) if is_missing(&e) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Puts back the kill switch chains `enable` replaced before failing with `err`, each as
+/// `iptables -S` listed it beforehand (None: it did not exist).
+fn roll_back(loaded: Vec<(Family, Option<Vec<String>>)>, err: String) -> String {
+    for (fam, before) in loaded.into_iter().rev() {
+        let res = match before {
+            None => remove_chain(fam, "filter", KILLSWITCH_CHAIN),
+            Some(rules) => {
+                let prefix = format!("-A {KILLSWITCH_CHAIN} ");
+                let rules: Vec<String> = rules
+                    .iter()
+                    .map(|r| r.strip_prefix(&prefix).unwrap_or(r).to_string())
+                    .collect();
+                restore(
+                    fam,
+                    &table_script("filter", KILLSWITCH_CHAIN, &rules, false),
+                )
+            }
+        };
+        if let Err(e) = res {
+            return format!(
+                "{err}\nRolling back {} failed, so it holds the new kill switch and the other family does not: {e}",
+                fam.bin()
+            );
+        }
+    }
+    err
+}
+
+fn remove_chain(fam: Family, table: &str, chain: &str) -> Result<(), String> {
+    // Duplicate jumps are possible if something else re-added ours; drop them all.
+    while has_jump(fam, table, chain)? {
+        run(fam, &["-t", table, "-D", "OUTPUT", "-j", chain])?;
+    }
+    for op in ["-F", "-X"] {
+        match run(fam, &["-t", table, op, chain]) {
+            Ok(_) => {}
+            Err(e) if is_missing(&e) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+// `-C` on an absent rule, or any command on an absent chain.
+fn is_missing(err: &str) -> bool {
+    err.contains("No chain/target/match")
+        || err.contains("does not exist")
+        || err.contains("Bad rule")
+}
+
+fn run(fam: Family, args: &[&str]) -> Result<String, String> {
+    let bin = fam.bin();
+    let out = Command::new(bin)
+        .arg("-w")
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("Failed to start {bin}: {e}"))?;
+
+    if out.status.success() {
+        return Ok(String::from_utf8_lossy(&out.stdout).to_string());
+    }
+    Err(format!(
+        "{bin} {} failed (exit={}):\n{}",
+        args.join(" "),
+        out.status.code().unwrap_or(-1),
+        String::from_utf8_lossy(&out.stderr)
+    ))
+}
+
+fn restore(fam: Family, script: &str) -> Result<(), String> {
+    let bin = fam.restore_bin();
+    let mut child = Command::new(bin)
+        .args(["-w", "--noflush"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to start {bin}: {e}"))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(script.as_bytes())
+            .map_err(|e| format!("Failed to write to {bin}: {e}"))?;
+    }
+
+    let out = child
+        .wait_with_output()
+        .map_err(|e| format!("Failed to wait for {bin}: {e}"))?;
+    if out.status.success() {
+        return Ok(());
+    }
+    Err(format!(
+        "{bin} failed (exit={}):\n{}",
+        out.status.code().unwrap_or(-1),
+        String::from_utf8_lossy(&out.stderr)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::policy;
+
+    #[test]
+    fn filter_rules_v4() {
+        assert_eq!(
+            filter_rules(Family::V4, &policy()),
+            [
+                "-o lo -j ACCEPT",
+                "-m conntrack --ctstate RELATED,ESTABLISHED -j ACCEPT",
+                "-o tun0 -d 10.8.0.1 -p udp --dport 53 -m comment --comment stellar-dns -j ACCEPT",
+                "-o tun0 -d 10.8.0.1 -p tcp --dport 53 -m comment --comment stellar-dns -j ACCEPT",
+                "-p udp --dport 53 -j DROP",
+                "-p tcp --dport 53 -j DROP",
+                "-o tun0 -m comment --comment stellar-tunnel -j ACCEPT",
+                "-d 198.51.100.7 -p udp --dport 1194 -m comment --comment stellar-remote -j ACCEPT",
+                "-d 192.0.2.0/24 -p tcp --dport 5000:5010 -m comment --comment stellar-exempt -j ACCEPT",
+                "-j DROP",
+            ]
+        );
+    }
+
+    #[test]
+    fn filter_rules_v6() {
+        assert_eq!(
+            filter_rules(Family::V6, &policy()),
+            [
+                "-o lo -j ACCEPT",
+                "-m conntrack --ctstate RELATED,ESTABLISHED -j ACCEPT",
+                "-o tun0 -d fd00::1 -p udp --dport 53 -m comment --comment stellar-dns -j ACCEPT",
+                "-o tun0 -d fd00::1 -p tcp --dport 53 -m comment --comment stellar-dns -j ACCEPT",
+                "-p udp --dport 53 -j DROP",
+                "-p tcp --dport 53 -j DROP",
+                "-o tun0 -m comment --comment stellar-tunnel -j ACCEPT",
+                "-d 2001:db8::7 -p tcp --dport 443 -m comment --comment stellar-remote -j ACCEPT",
+                "-j DROP",
+            ]
+        );
+    }
+
+    #[test]
+    fn filter_rules_before_connect_still_drop_dns() {
+        let mut p = policy();
+        p.tunnel_ifaces.clear();
+        p.dns.clear();
+        let rules = filter_rules(Family::V4, &p);
+        assert!(!rules
+            .iter()
+            .any(|r| r.contains(TAG_DNS) || r.contains(TAG_TUNNEL)));
+        assert!(rules.contains(&"-p udp --dport 53 -j DROP".to_string()));
+    }
+
+    #[test]
+    fn filter_rules_lan_after_dns_drop() {
+        let mut p = policy();
+        let lan = |rules: &[String]| rules.iter().filter(|r| r.contains(TAG_LAN)).count();
+        assert_eq!(lan(&filter_rules(Family::V4, &p)), 0);
+
+        p.allow_lan = true;
+        for fam in [Family::V4, Family::V6] {
+            let rules = filter_rules(fam, &p);
+            assert_eq!(lan(&rules), fam.lan().len());
+            let dns_drop = rules.iter().position(|r| r == "-p tcp --dport 53 -j DROP");
+            let first_lan = rules.iter().position(|r| r.contains(TAG_LAN));
+            assert!(dns_drop < first_lan);
+        }
+        assert!(filter_rules(Family::V4, &p)
+            .contains(&"-d 192.168.0.0/16 -m comment --comment stellar-lan -j ACCEPT".to_string()));
+    }
+
+    #[test]
+    fn filter_rules_exemption_forms() {
+        let mut p = policy();
+        p.exemptions = vec![
+            Exemption::new("203.0.113.9", Some("udp"), Some("5060")).unwrap(),
+            Exemption::new("2001:db8:1::/48", None, None).unwrap(),
+        ];
+        let exempt = |fam| {
+            filter_rules(fam, &p)
+                .into_iter()
+                .filter(|r| r.contains(TAG_EXEMPT))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            exempt(Family::V4),
+            ["-d 203.0.113.9/32 -p udp --dport 5060 -m comment --comment stellar-exempt -j ACCEPT"]
+        );
+        assert_eq!(
+            exempt(Family::V6),
+            ["-d 2001:db8:1::/48 -m comment --comment stellar-exempt -j ACCEPT"]
+        );
+    }
+
+    #[test]
+    fn filter_rules_log_before_final_drop() {
+        let mut p = policy();
+        p.log_drops = true;
+        let rules = filter_rules(Family::V4, &p);
+        let n = rules.len();
+        assert_eq!(verdict(&rules[n - 2]), "LOG");
+        assert!(rules[n - 2].contains(DROP_LOG_PREFIX));
+        assert_eq!(rules[n - 1], "-j DROP");
+    }
+
+    #[test]
+    fn ipv6_block_rules_let_an_ipv6_transport_through() {
+        let p = policy();
+        assert_eq!(
+            ipv6_block_rules(Some(&p.remotes[1])),
+            [
+                "-o lo -j ACCEPT",
+                "-d 2001:db8::7 -p tcp --dport 443 -j ACCEPT",
+                "-j DROP"
+            ]
+        );
+        assert_eq!(
+            ipv6_block_rules(Some(&p.remotes[0])),
+            ["-o lo -j ACCEPT", "-j DROP"]
+        );
+    }
+
+    #[test]
+    fn verdict_of_specs_and_listed_rules() {
+        let cases = [
+            ("-o lo -j ACCEPT", "ACCEPT"),
+            ("-p udp --dport 53 -j DROP", "DROP"),
+            ("-A STELLAR_KILLSWITCH -j DROP", "DROP"),
+            (
+                "-A STELLAR_KILLSWITCH -m limit --limit 10/min -j LOG --log-prefix \"stellar-killswitch drop: \"",
+                "LOG",
+            ),
+            (
+                "-A STELLAR_KILLSWITCH -d 198.51.100.7/32 -p udp -m udp --dport 1194 -m comment --comment stellar-remote -j ACCEPT",
+                "ACCEPT",
+            ),
+            ("-A OUTPUT -j STELLAR_KILLSWITCH", "STELLAR_KILLSWITCH"),
+            ("-N STELLAR_KILLSWITCH", ""),
+            ("-P OUTPUT ACCEPT", ""),
+        ];
+        for (rule, want) in cases {
+            assert_eq!(verdict(rule), want, "{rule}");
+        }
+    }
+
+    // As `iptables -S` / `ip6tables -S` list the rules of `policy()` with log_drops.
+    const LISTED_V4: &[&str] = &[
+        "-A STELLAR_KILLSWITCH -o lo -j ACCEPT",
+        "-A STELLAR_KILLSWITCH -m conntrack --ctstate RELATED,ESTABLISHED -j ACCEPT",
+        "-A STELLAR_KILLSWITCH -d 10.8.0.1/32 -o tun0 -p udp -m udp --dport 53 -m comment --comment stellar-dns -j ACCEPT",
+        "-A STELLAR_KILLSWITCH -d 10.8.0.1/32 -o tun0 -p tcp -m tcp --dport 53 -m comment --comment stellar-dns -j ACCEPT",
+        "-A STELLAR_KILLSWITCH -p udp -m udp --dport 53 -j DROP",
+        "-A STELLAR_KILLSWITCH -p tcp -m tcp --dport 53 -j DROP",
+        "-A STELLAR_KILLSWITCH -o tun0 -m comment --comment stellar-tunnel -j ACCEPT",
+        "-A STELLAR_KILLSWITCH -d 198.51.100.7/32 -p udp -m udp --dport 1194 -m comment --comment stellar-remote -j ACCEPT",
+        "-A STELLAR_KILLSWITCH -d 192.0.2.0/24 -p tcp -m tcp --dport 5000:5010 -m comment --comment stellar-exempt -j ACCEPT",
+        "-A STELLAR_KILLSWITCH -m limit --limit 10/min -j LOG --log-prefix \"stellar-killswitch drop: \"",
+        "-A STELLAR_KILLSWITCH -j DROP",
+    ];
+    const LISTED_V6: &[&str] = &[
+        "-A STELLAR_KILLSWITCH -o lo -j ACCEPT",
+        "-A STELLAR_KILLSWITCH -m conntrack --ctstate RELATED,ESTABLISHED -j ACCEPT",
+        "-A STELLAR_KILLSWITCH -d fd00::1/128 -o tun0 -p udp -m udp --dport 53 -m comment --comment stellar-dns -j ACCEPT",
+        "-A STELLAR_KILLSWITCH -d fd00::1/128 -o tun0 -p tcp -m tcp --dport 53 -m comment --comment stellar-dns -j ACCEPT",
+        "-A STELLAR_KILLSWITCH -p udp -m udp --dport 53 -j DROP",
+        "-A STELLAR_KILLSWITCH -p tcp -m tcp --dport 53 -j DROP",
+        "-A STELLAR_KILLSWITCH -o tun0 -m comment --comment stellar-tunnel -j ACCEPT",
+        "-A STELLAR_KILLSWITCH -d 2001:db8::7/128 -p tcp -m tcp --dport 443 -m comment --comment stellar-remote -j ACCEPT",
+        "-A STELLAR_KILLSWITCH -m limit --limit 10/min -j LOG --log-prefix \"stellar-killswitch drop: \"",
+        "-A STELLAR_KILLSWITCH -j DROP",
+    ];
+
+    #[test]
+    fn listed_rules_read_back_as_the_policy() {
+        let mut want = policy();
+        want.log_drops = true;
+        want.normalize();
+
+        let mut p = KillSwitchPolicy::default();
+        for line in LISTED_V4.iter().chain(LISTED_V6) {
+            read_rule(&mut p, line);
+        }
+        p.normalize();
+        assert_eq!(p, want);
+
+        // The same rules, in the same order, as what `enable` loads.
+        for (fam, listed) in [(Family::V4, LISTED_V4), (Family::V6, LISTED_V6)] {
+            let listed: Vec<String> = listed.iter().map(|r| r.to_string()).collect();
+            assert_eq!(
+                chain_drift("chain", &listed, &filter_rules(fam, &want)),
+                Vec::<String>::new()
+            );
+        }
+    }
+
+    #[test]
+    fn drift_reports_changed_untagged_rules() {
+        let mut want = policy();
+        want.log_drops = true;
+        let want = filter_rules(Family::V4, &want);
+        let listed = |i: usize, rule: &str| {
+            let mut l: Vec<String> = LISTED_V4.iter().map(|r| r.to_string()).collect();
+            l[i] = rule.to_string();
+            l
+        };
+
+        let dns_leak = listed(
+            4,
+            "-A STELLAR_KILLSWITCH -p udp -m udp --dport 5353 -j DROP",
+        );
+        assert_eq!(
+            chain_drift("chain", &dns_leak, &want),
+            ["chain rule 5 is `--dport 5353 -j DROP -p udp`, expected `--dport 53 -j DROP -p udp`"]
+        );
+
+        let any_out = listed(0, "-A STELLAR_KILLSWITCH -o eth0 -j ACCEPT");
+        assert_eq!(chain_drift("chain", &any_out, &want).len(), 1);
+
+        let no_conntrack = listed(
+            1,
+            "-A STELLAR_KILLSWITCH -m conntrack --ctstate NEW -j ACCEPT",
+        );
+        assert_eq!(chain_drift("chain", &no_conntrack, &want).len(), 1);
+
+        assert_eq!(
+            chain_drift("chain", &dns_leak[1..], &want),
+            ["chain has 10 rules, expected 11"]
+        );
+    }
+
+    #[test]
+    fn log_rule_is_not_read_as_an_accept() {
+        let mut p = KillSwitchPolicy::default();
+        read_rule(
+            &mut p,
+            "-A STELLAR_KILLSWITCH -m limit --limit 10/min -j LOG --log-prefix \"stellar-killswitch drop: \"",
+        );
+        assert_eq!(
+            p,
+            KillSwitchPolicy {
+                log_drops: true,
+                ..Default::default()
+            }
+        );
+
+        let mut p = KillSwitchPolicy::default();
+        read_rule(
+            &mut p,
+            "-A STELLAR_KILLSWITCH -p udp -m udp --dport 53 -j DROP",
+        );
+        assert_eq!(p, KillSwitchPolicy::default());
+    }
+}
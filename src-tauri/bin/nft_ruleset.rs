@@ -0,0 +1,950 @@
+// src-tauri/bin/nft_ruleset.rs
+//
+// Typed model of the helper's nftables tables, emitted through nft's JSON API
+// (`nft -j -f -`) instead of hand-built script lines, and compared against what the kernel
+// reports back (`nft -j list table`) after every change.
+
+use std::{
+    io::Write,
+    net::IpAddr,
+    process::{Command, Stdio},
+};
+
+use serde_json::{json, Value};
+
+pub const FAMILY: &str = "inet";
+
+/// A table we own. A partial ruleset (only some sets/chains) can be used to refill just
+/// those, see `refill_commands`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ruleset {
+    pub table: String,
+    pub sets: Vec<Set>,
+    pub chains: Vec<Chain>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Set {
+    pub name: String,
+    /// Element type; several entries make a concatenation (`ipv4_addr . inet_proto . ...`).
+    pub key: Vec<&'static str>,
+    pub interval: bool,
+    pub elements: Vec<Elem>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Elem {
+    Str(String),
+    Num(u64),
+    Prefix(IpAddr, u8),
+    Concat(Vec<Elem>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chain {
+    pub name: String,
+    pub hook: Option<Hook>,
+    pub rules: Vec<Rule>,
+}
+
+/// Base chain attachment: `type <kind> hook <hook> priority <prio>; policy <policy>;`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hook {
+    pub kind: &'static str,
+    pub hook: &'static str,
+    pub prio: i32,
+    pub policy: &'static str,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule(pub Vec<Expr>);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Match {
+        left: Lhs,
+        op: Op,
+        right: Rhs,
+    },
+    /// Anonymous counter; read back from the listing.
+    Counter,
+    /// Per-key packet/byte counters in a dynamic set the kernel fills (`meter`), entries
+    /// expiring after `timeout` seconds. Fails (and ends the rule) once `size` is reached.
+    Meter {
+        name: String,
+        key: Lhs,
+        size: u32,
+        timeout: u32,
+    },
+    /// `limit rate <rate>/<per>`.
+    Limit {
+        rate: u32,
+        per: &'static str,
+    },
+    /// Kernel log line with this prefix.
+    Log(String),
+    Accept,
+    Drop,
+    Jump(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Eq,
+    In,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Lhs {
+    Meta(&'static str),
+    Ct(&'static str),
+    /// `ip daddr`, `ip6 daddr`, `th dport`, ...
+    Payload(&'static str, &'static str),
+    Concat(Vec<Lhs>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rhs {
+    Str(String),
+    Num(u64),
+    Prefix(IpAddr, u8),
+    /// Inclusive range (`10000-20000`).
+    Range(u64, u64),
+    /// Named set lookup (`@name`).
+    SetRef(String),
+    /// Anonymous set (`{ tcp, udp }`).
+    Anon(Vec<Rhs>),
+    /// Flag list (`established,related`).
+    Flags(Vec<&'static str>),
+}
+
+// ---------------- JSON encoding ----------------
+
+impl Elem {
+    fn to_json(&self) -> Value {
+        match self {
+            Elem::Str(s) => json!(s),
+            Elem::Num(n) => json!(n),
+            Elem::Prefix(addr, len) => {
+                json!({ "prefix": { "addr": addr.to_string(), "len": len } })
+            }
+            Elem::Concat(parts) => {
+                json!({ "concat": parts.iter().map(Elem::to_json).collect::<Vec<_>>() })
+            }
+        }
+    }
+}
+
+impl Lhs {
+    fn to_json(&self) -> Value {
+        match self {
+            Lhs::Meta(key) => json!({ "meta": { "key": key } }),
+            Lhs::Ct(key) => json!({ "ct": { "key": key } }),
+            Lhs::Payload(proto, field) => {
+                json!({ "payload": { "protocol": proto, "field": field } })
+            }
+            Lhs::Concat(parts) => {
+                json!({ "concat": parts.iter().map(Lhs::to_json).collect::<Vec<_>>() })
+            }
+        }
+    }
+}
+
+impl Rhs {
+    fn to_json(&self) -> Value {
+        match self {
+            Rhs::Str(s) => json!(s),
+            Rhs::Num(n) => json!(n),
+            Rhs::Prefix(addr, len) => json!({ "prefix": { "addr": addr.to_string(), "len": len } }),
+            Rhs::Range(lo, hi) => json!({ "range": [lo, hi] }),
+            Rhs::SetRef(name) => json!(format!("@{name}")),
+            Rhs::Anon(items) => {
+                json!({ "set": items.iter().map(Rhs::to_json).collect::<Vec<_>>() })
+            }
+            Rhs::Flags(flags) => json!(flags),
+        }
+    }
+}
+
+impl Expr {
+    fn to_json(&self) -> Value {
+        match self {
+            Expr::Match { left, op, right } => {
+                let op = match op {
+                    Op::Eq => "==",
+                    Op::In => "in",
+                };
+                json!({ "match": { "op": op, "left": left.to_json(), "right": right.to_json() } })
+            }
+            Expr::Counter => json!({ "counter": { "packets": 0, "bytes": 0 } }),
+            Expr::Meter {
+                name,
+                key,
+                size,
+                timeout,
+            } => json!({ "meter": {
+                "name": name,
+                "key": { "elem": { "val": key.to_json(), "timeout": timeout } },
+                "size": size,
+                "stmt": { "counter": { "packets": 0, "bytes": 0 } },
+            } }),
+            Expr::Limit { rate, per } => json!({ "limit": { "rate": rate, "per": per } }),
+            Expr::Log(prefix) => json!({ "log": { "prefix": prefix } }),
+            Expr::Accept => json!({ "accept": null }),
+            Expr::Drop => json!({ "drop": null }),
+            Expr::Jump(target) => json!({ "jump": { "target": target } }),
+        }
+    }
+}
+
+impl Rule {
+    fn exprs_json(&self) -> Value {
+        json!(self.0.iter().map(Expr::to_json).collect::<Vec<_>>())
+    }
+}
+
+impl Ruleset {
+    fn table_json(&self) -> Value {
+        json!({ "table": { "family": FAMILY, "name": self.table } })
+    }
+
+    fn set_json(&self, set: &Set) -> Value {
+        let key = match set.key.as_slice() {
+            [one] => json!(one),
+            many => json!(many),
+        };
+        let mut v = json!({ "family": FAMILY, "table": self.table, "name": set.name, "type": key });
+        if set.interval {
+            v["flags"] = json!(["interval"]);
+        }
+        json!({ "set": v })
+    }
+
+    fn elements_json(&self, set: &Set) -> Value {
+        json!({ "element": {
+            "family": FAMILY,
+            "table": self.table,
+            "name": set.name,
+            "elem": set.elements.iter().map(Elem::to_json).collect::<Vec<_>>(),
+        } })
+    }
+
+    fn chain_json(&self, chain: &Chain) -> Value {
+        let mut v = json!({ "family": FAMILY, "table": self.table, "name": chain.name });
+        if let Some(h) = &chain.hook {
+            v["type"] = json!(h.kind);
+            v["hook"] = json!(h.hook);
+            v["prio"] = json!(h.prio);
+            v["policy"] = json!(h.policy);
+        }
+        json!({ "chain": v })
+    }
+
+    fn rule_json(&self, chain: &Chain, rule: &Rule) -> Value {
+        json!({ "rule": {
+            "family": FAMILY,
+            "table": self.table,
+            "chain": chain.name,
+            "expr": rule.exprs_json(),
+        } })
+    }
+
+    /// Output chain that drops everything but loopback, under this ruleset's table name.
+    pub fn fail_closed(table: &str) -> Ruleset {
+        Ruleset {
+            table: table.to_string(),
+            sets: vec![],
+            chains: vec![Chain {
+                name: "output".to_string(),
+                hook: Some(Hook {
+                    kind: "filter",
+                    hook: "output",
+                    prio: 0,
+                    policy: "drop",
+                }),
+                rules: vec![Rule(vec![
+                    Expr::Match {
+                        left: Lhs::Meta("oifname"),
+                        op: Op::Eq,
+                        right: Rhs::Str("lo".to_string()),
+                    },
+                    Expr::Accept,
+                ])],
+            }],
+        }
+    }
+
+    fn fill(&self, cmds: &mut Vec<Value>) {
+        for set in self.sets.iter().filter(|s| !s.elements.is_empty()) {
+            cmds.push(json!({ "add": self.elements_json(set) }));
+        }
+        for chain in &self.chains {
+            for rule in &chain.rules {
+                cmds.push(json!({ "add": self.rule_json(chain, rule) }));
+            }
+        }
+    }
+
+    /// Batch that replaces the whole table in one transaction. Declaring the table first
+    /// makes the delete valid whether or not it exists; if anything later fails nothing is
+    /// committed, so the previous table stays in force.
+    pub fn replace_commands(&self) -> Value {
+        let mut cmds = vec![
+            json!({ "add": self.table_json() }),
+            json!({ "delete": self.table_json() }),
+            json!({ "add": self.table_json() }),
+        ];
+        for set in &self.sets {
+            cmds.push(json!({ "add": self.set_json(set) }));
+        }
+        for chain in &self.chains {
+            cmds.push(json!({ "add": self.chain_json(chain) }));
+        }
+        self.fill(&mut cmds);
+        json!({ "nftables": cmds })
+    }
+
+    /// Batch that empties and refills the listed sets and chains of an existing table.
+    pub fn refill_commands(&self) -> Value {
+        let by_name = |kind: &str, name: &str| json!({ "flush": { kind: { "family": FAMILY, "table": self.table, "name": name } } });
+        let mut cmds = Vec::new();
+        for set in &self.sets {
+            cmds.push(by_name("set", &set.name));
+        }
+        for chain in &self.chains {
+            cmds.push(by_name("chain", &chain.name));
+        }
+        self.fill(&mut cmds);
+        json!({ "nftables": cmds })
+    }
+
+    /// Differences between this (intended) ruleset and `live`, the `nft -j list table`
+    /// output. Checks base chain attachment, each rule's matches and verdict in order, and
+    /// set types and elements. Empty when they match.
+    pub fn drift(&self, live: &Value) -> Vec<String> {
+        let objects: Vec<&Value> = live["nftables"].as_array().into_iter().flatten().collect();
+        let find = |kind: &str, name: &str| {
+            objects.iter().find_map(|o| {
+                let v = o.get(kind)?;
+                (v["table"] == self.table.as_str() && v["name"] == name).then_some(v)
+            })
+        };
+
+        let mut drift = Vec::new();
+
+        for set in &self.sets {
+            let Some(live_set) = find("set", &set.name) else {
+                drift.push(format!("set {} is missing", set.name));
+                continue;
+            };
+            let live_key: Vec<&str> = match &live_set["type"] {
+                Value::String(s) => vec![s.as_str()],
+                Value::Array(a) => a.iter().filter_map(Value::as_str).collect(),
+                _ => vec![],
+            };
+            if live_key != set.key {
+                drift.push(format!(
+                    "set {} has type {:?}, expected {:?}",
+                    set.name, live_key, set.key
+                ));
+            }
+
+            let mut want: Vec<String> = set
+                .elements
+                .iter()
+                .map(Elem::to_json)
+                .map(|e| elem_key(&e))
+                .collect();
+            let mut have: Vec<String> = set_elements(live_set).into_iter().map(elem_key).collect();
+            want.sort();
+            have.sort();
+            if want != have {
+                let missing: Vec<&String> = want.iter().filter(|e| !have.contains(e)).collect();
+                let extra: Vec<&String> = have.iter().filter(|e| !want.contains(e)).collect();
+                drift.push(format!(
+                    "set {} elements differ (missing {missing:?}, unexpected {extra:?})",
+                    set.name
+                ));
+            }
+        }
+
+        for chain in &self.chains {
+            let Some(live_chain) = find("chain", &chain.name) else {
+                drift.push(format!("chain {} is missing", chain.name));
+                continue;
+            };
+            if let Some(h) = &chain.hook {
+                let same = live_chain["type"] == h.kind
+                    && live_chain["hook"] == h.hook
+                    && live_chain["prio"] == h.prio
+                    && live_chain["policy"] == h.policy;
+                if !same {
+                    drift.push(format!("chain {} is not attached as expected", chain.name));
+                }
+            }
+
+            let live_rules: Vec<String> = objects
+                .iter()
+                .filter_map(|o| o.get("rule"))
+                .filter(|r| r["table"] == self.table.as_str() && r["chain"] == chain.name.as_str())
+                .map(|r| rule_key(&r["expr"]))
+                .collect();
+            let want_rules: Vec<String> = chain
+                .rules
+                .iter()
+                .map(|r| rule_key(&r.exprs_json()))
+                .collect();
+            if live_rules.len() != want_rules.len() {
+                drift.push(format!(
+                    "chain {} has {} rules, expected {}",
+                    chain.name,
+                    live_rules.len(),
+                    want_rules.len()
+                ));
+                continue;
+            }
+            for (i, (have, want)) in live_rules.iter().zip(&want_rules).enumerate() {
+                if have != want {
+                    drift.push(format!(
+                        "chain {} rule {} is `{have}`, expected `{want}`",
+                        chain.name,
+                        i + 1
+                    ));
+                }
+            }
+        }
+
+        drift
+    }
+}
+
+/// Canonical text of a rule's matches and verdict, the same for what we load and what nft
+/// lists (`meta:key:oifname == @tunnel_ifaces accept`). Statements that do not decide a
+/// packet's fate (counters, meters, limits, logging) are left out, with the values the
+/// kernel fills into them.
+fn rule_key(expr: &Value) -> String {
+    let exprs: Vec<&Value> = expr.as_array().into_iter().flatten().collect();
+    let mut parts = Vec::new();
+    let mut i = 0;
+    while i < exprs.len() {
+        let e = exprs[i];
+        i += 1;
+        let Some((kind, v)) = e.as_object().and_then(|o| o.iter().next()) else {
+            continue;
+        };
+        match kind.as_str() {
+            "match" => {
+                // nft lists `meta l4proto tcp th dport 80` as `tcp dport 80`.
+                let proto = (v["op"] == "=="
+                    && v.pointer("/left/meta/key") == Some(&json!("l4proto")))
+                .then(|| v["right"].as_str())
+                .flatten()
+                .filter(|p| matches!(*p, "tcp" | "udp"));
+                let next = exprs.get(i).and_then(|n| n.get("match"));
+                let th = next.filter(|n| n.pointer("/left/payload/protocol") == Some(&json!("th")));
+                let (left, v) = match (proto, th) {
+                    (Some(proto), Some(n)) => {
+                        i += 1;
+                        let field = &n["left"]["payload"]["field"];
+                        (
+                            canon(&json!({"payload": {"protocol": proto, "field": field}})),
+                            n,
+                        )
+                    }
+                    _ => (canon(&v["left"]), v),
+                };
+                // nft lists set lookups and flag tests with `==` or `in` interchangeably.
+                let op = match v["op"].as_str() {
+                    Some("in" | "==") => "==",
+                    Some(op) => op,
+                    None => "?",
+                };
+                parts.push(format!("{left} {op} {}", canon(&v["right"])));
+            }
+            "accept" | "drop" | "return" | "continue" => parts.push(kind.clone()),
+            "counter" | "meter" | "limit" | "log" | "set" => {}
+            other => parts.push(format!("{other} {}", canon(v))),
+        }
+    }
+    parts.join(" ")
+}
+
+/// Canonical text of an expression operand: object keys and anonymous set members sorted,
+/// a one-item list the same as the item (nft lists a single flag either way) and a
+/// single-address prefix the same as the address (nft lists `ip daddr 1.2.3.4/32` without
+/// the length).
+fn canon(v: &Value) -> String {
+    match v {
+        Value::String(s) => s.clone(),
+        Value::Array(a) if a.len() == 1 => canon(&a[0]),
+        Value::Array(a) => {
+            let mut items: Vec<String> = a.iter().map(canon).collect();
+            items.sort();
+            items.join(",")
+        }
+        Value::Object(o) => {
+            if let Some(Value::Array(items)) = o.get("set") {
+                let mut items: Vec<String> = items.iter().map(canon).collect();
+                items.sort();
+                format!("{{ {} }}", items.join(", "))
+            } else if let Some(p) = o.get("prefix") {
+                let host = match p["addr"].as_str().and_then(|a| a.parse::<IpAddr>().ok()) {
+                    Some(IpAddr::V4(_)) => 32,
+                    Some(IpAddr::V6(_)) => 128,
+                    None => -1,
+                };
+                if p["len"] == host {
+                    canon(&p["addr"])
+                } else {
+                    format!("{}/{}", canon(&p["addr"]), canon(&p["len"]))
+                }
+            } else if let Some(Value::Array(parts)) = o.get("concat") {
+                parts.iter().map(canon).collect::<Vec<_>>().join(" . ")
+            } else if let Some(Value::Array(r)) = o.get("range") {
+                r.iter().map(canon).collect::<Vec<_>>().join("-")
+            } else if o.len() == 1 {
+                let (k, inner) = o.iter().next().expect("one entry");
+                format!("{k}:{}", canon(inner))
+            } else {
+                let mut fields: Vec<String> =
+                    o.iter().map(|(k, v)| format!("{k}={}", canon(v))).collect();
+                fields.sort();
+                fields.join(",")
+            }
+        }
+        other => other.to_string(),
+    }
+}
+
+/// Set elements in `nft -j` output: plain values, or `{"elem": {"val": ...}}` when they
+/// carry extra attributes.
+pub fn set_elements(set: &Value) -> Vec<&Value> {
+    set.get("elem")
+        .and_then(Value::as_array)
+        .map(|elems| {
+            elems
+                .iter()
+                .map(|e| e.pointer("/elem/val").unwrap_or(e))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Canonical text of an element so intended and listed elements compare equal
+/// (`10.0.0.0/8`, `1.2.3.4 . udp . 1194`, `tun0`).
+pub fn elem_key(v: &Value) -> String {
+    match v {
+        Value::String(s) => s.clone(),
+        Value::Number(n) => n.to_string(),
+        Value::Object(o) => {
+            if let Some(p) = o.get("prefix") {
+                format!("{}/{}", elem_key(&p["addr"]), p["len"])
+            } else if let Some(Value::Array(parts)) = o.get("concat") {
+                parts.iter().map(elem_key).collect::<Vec<_>>().join(" . ")
+            } else if let Some(Value::Array(r)) = o.get("range") {
+                r.iter().map(elem_key).collect::<Vec<_>>().join("-")
+            } else {
+                v.to_string()
+            }
+        }
+        other => other.to_string(),
+    }
+}
+
+// ---------------- nft ----------------
+
+/// Load a JSON batch with `nft -j -f -` (one transaction).
+pub fn apply(batch: &Value) -> Result<(), String> {
+    let mut child = Command::new("nft")
+        .args(["-j", "-f", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to start nft: {e}"))?;
+
+    {
+        let stdin = child.stdin.as_mut().ok_or("Failed to open nft stdin")?;
+        stdin
+            .write_all(batch.to_string().as_bytes())
+            .map_err(|e| format!("Failed writing nft batch: {e}"))?;
+    }
+
+    let out = child
+        .wait_with_output()
+        .map_err(|e| format!("Failed waiting for nft: {e}"))?;
+
+    if out.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "nft failed (exit={}):\n{}",
+            out.status.code().unwrap_or(-1),
+            String::from_utf8_lossy(&out.stderr)
+        ))
+    }
+}
+
+/// `nft -j list table`; None when the table does not exist.
+pub fn list_table(table: &str) -> Result<Option<Value>, String> {
+    let out = Command::new("nft")
+        .args(["-j", "list", "table", FAMILY, table])
+        .output()
+        .map_err(|e| format!("Failed to start nft: {e}"))?;
+
+    if !out.status.success() {
+        let err = String::from_utf8_lossy(&out.stderr).to_string();
+        if err.contains("No such file") || err.contains("does not exist") {
+            return Ok(None);
+        }
+        return Err(format!(
+            "nft list failed (exit={}):\n{err}",
+            out.status.code().unwrap_or(-1)
+        ));
+    }
+
+    serde_json::from_slice(&out.stdout)
+        .map(Some)
+        .map_err(|e| format!("Unexpected nft JSON output: {e}"))
+}
+
+/// Apply `batch`, then read the table back and check it against `intended`. If the kernel
+/// holds something else, the table is replaced by `Ruleset::fail_closed`: rules nobody
+/// verified must not stay in force.
+pub fn apply_verified(batch: &Value, intended: &Ruleset) -> Result<(), String> {
+    apply(batch)?;
+
+    let problem = match list_table(&intended.table) {
+        Ok(Some(live)) => {
+            let drift = intended.drift(&live);
+            if drift.is_empty() {
+                return Ok(());
+            }
+            format!("does not match:\n- {}", drift.join("\n- "))
+        }
+        Ok(None) => "is missing after apply".to_string(),
+        Err(e) => format!("could not be read back: {e}"),
+    };
+
+    let fallback = Ruleset::fail_closed(&intended.table);
+    let outcome = match apply(&fallback.replace_commands()) {
+        Ok(()) => "Failed closed: it now drops all traffic except loopback.".to_string(),
+        Err(e) => format!("Failing closed (drop all but loopback) failed too: {e}"),
+    };
+    Err(format!(
+        "nft accepted the rules but table {} {problem}\n{outcome}",
+        intended.table
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Ruleset {
+        Ruleset {
+            table: "stellartest".to_string(),
+            sets: vec![
+                Set {
+                    name: "tunnel_ifaces".to_string(),
+                    key: vec!["ifname"],
+                    interval: false,
+                    elements: vec![Elem::Str("tun0".to_string())],
+                },
+                Set {
+                    name: "remotes_v4".to_string(),
+                    key: vec!["ipv4_addr", "inet_proto", "inet_service"],
+                    interval: false,
+                    elements: vec![Elem::Concat(vec![
+                        Elem::Str("198.51.100.7".to_string()),
+                        Elem::Str("udp".to_string()),
+                        Elem::Num(1194),
+                    ])],
+                },
+            ],
+            chains: vec![Chain {
+                name: "output".to_string(),
+                hook: Some(Hook {
+                    kind: "filter",
+                    hook: "output",
+                    prio: 0,
+                    policy: "accept",
+                }),
+                rules: vec![
+                    Rule(vec![
+                        Expr::Match {
+                            left: Lhs::Meta("oifname"),
+                            op: Op::Eq,
+                            right: Rhs::SetRef("tunnel_ifaces".to_string()),
+                        },
+                        Expr::Accept,
+                    ]),
+                    Rule(vec![
+                        Expr::Match {
+                            left: Lhs::Ct("state"),
+                            op: Op::In,
+                            right: Rhs::Flags(vec!["established", "related"]),
+                        },
+                        Expr::Accept,
+                    ]),
+                    Rule(vec![
+                        Expr::Match {
+                            left: Lhs::Meta("l4proto"),
+                            op: Op::Eq,
+                            right: Rhs::Anon(vec![
+                                Rhs::Str("tcp".to_string()),
+                                Rhs::Str("udp".to_string()),
+                            ]),
+                        },
+                        Expr::Match {
+                            left: Lhs::Payload("th", "dport"),
+                            op: Op::Eq,
+                            right: Rhs::Num(53),
+                        },
+                        Expr::Counter,
+                        Expr::Drop,
+                    ]),
+                ],
+            }],
+        }
+    }
+
+    /// What `nft -j list table` prints once `rs` is loaded: objects instead of commands,
+    /// elements inside their set, handles, live counters, and some of nft's own spellings.
+    fn listing(rs: &Ruleset) -> Value {
+        let mut out = vec![
+            json!({ "metainfo": { "version": "1.0.9", "json_schema_version": 1 } }),
+            json!({ "table": { "family": FAMILY, "name": rs.table, "handle": 1 } }),
+        ];
+        let batch = rs.replace_commands();
+        for (handle, cmd) in batch["nftables"].as_array().unwrap()[3..]
+            .iter()
+            .enumerate()
+        {
+            let (kind, obj) = cmd["add"].as_object().unwrap().iter().next().unwrap();
+            let mut obj = obj.clone();
+            if kind == "element" {
+                let set = out
+                    .iter_mut()
+                    .find(|o| o.pointer("/set/name") == Some(&obj["name"]))
+                    .unwrap();
+                set["set"]["elem"] = obj["elem"].take();
+                continue;
+            }
+            if kind == "rule" {
+                for e in obj["expr"].as_array_mut().unwrap() {
+                    if e.get("counter").is_some() {
+                        *e = json!({ "counter": { "packets": 12, "bytes": 840 } });
+                    }
+                    // Anonymous sets come back in the kernel's order.
+                    if let Some(Value::Array(items)) = e.pointer_mut("/match/right/set") {
+                        items.reverse();
+                    }
+                }
+            }
+            obj["handle"] = json!(handle + 2);
+            out.push(json!({ kind.clone(): obj }));
+        }
+        json!({ "nftables": out })
+    }
+
+    fn without(live: &Value, mut keep: impl FnMut(&Value) -> bool) -> Value {
+        let objects = live["nftables"].as_array().unwrap();
+        json!({ "nftables": objects.iter().filter(|o| keep(o)).cloned().collect::<Vec<_>>() })
+    }
+
+    #[test]
+    fn replace_commands_swaps_the_table_in_one_batch() {
+        let batch = sample().replace_commands();
+        let cmds = batch["nftables"].as_array().unwrap();
+        let verbs: Vec<&str> = cmds
+            .iter()
+            .map(|c| c.as_object().unwrap().keys().next().unwrap().as_str())
+            .collect();
+        assert_eq!(&verbs[..3], ["add", "delete", "add"]);
+        assert!(verbs[3..].iter().all(|v| *v == "add"));
+        for c in &cmds[..3] {
+            let table = c.as_object().unwrap().values().next().unwrap();
+            assert_eq!(table["table"]["name"], "stellartest");
+        }
+        // 2 sets, 1 chain, 2 element lists, 3 rules
+        assert_eq!(cmds.len(), 3 + 2 + 1 + 2 + 3);
+        assert_eq!(cmds[5]["add"]["chain"]["hook"], "output");
+        assert_eq!(cmds[5]["add"]["chain"]["policy"], "accept");
+    }
+
+    #[test]
+    fn refill_commands_flush_before_adding() {
+        let batch = sample().refill_commands();
+        let cmds = batch["nftables"].as_array().unwrap();
+        assert!(cmds[..3].iter().all(|c| c.get("flush").is_some()));
+        assert!(cmds[3..].iter().all(|c| c.get("add").is_some()));
+    }
+
+    #[test]
+    fn no_drift_for_identical_rules() {
+        let rs = sample();
+        assert_eq!(rs.drift(&listing(&rs)), Vec::<String>::new());
+    }
+
+    #[test]
+    fn drift_reports_missing_chain() {
+        let rs = sample();
+        let live = without(&listing(&rs), |o| o.get("chain").is_none());
+        let drift = rs.drift(&live);
+        assert_eq!(drift, ["chain output is missing"]);
+    }
+
+    #[test]
+    fn drift_reports_changed_set() {
+        let rs = sample();
+        let mut live = listing(&rs);
+        for o in live["nftables"].as_array_mut().unwrap() {
+            if o.pointer("/set/name") == Some(&json!("tunnel_ifaces")) {
+                o["set"]["elem"] = json!(["tun9"]);
+            }
+        }
+        let drift = rs.drift(&live);
+        assert_eq!(drift.len(), 1);
+        assert!(
+            drift[0].starts_with("set tunnel_ifaces elements differ"),
+            "{drift:?}"
+        );
+    }
+
+    #[test]
+    fn drift_reports_changed_rule_match() {
+        let rs = sample();
+        let mut live = listing(&rs);
+        // `oifname @tunnel_ifaces accept` rewritten to a bare `accept`: same verdict.
+        let first = live["nftables"]
+            .as_array_mut()
+            .unwrap()
+            .iter_mut()
+            .find(|o| o.get("rule").is_some())
+            .unwrap();
+        first["rule"]["expr"] = json!([{ "accept": null }]);
+        let drift = rs.drift(&live);
+        assert_eq!(
+            drift,
+            ["chain output rule 1 is `accept`, expected `meta:key:oifname == @tunnel_ifaces accept`"]
+        );
+    }
+
+    #[test]
+    fn drift_reports_missing_rule() {
+        let rs = sample();
+        let mut seen = 0;
+        let live = without(&listing(&rs), |o| {
+            if o.get("rule").is_none() {
+                return true;
+            }
+            seen += 1;
+            seen != 2
+        });
+        assert_eq!(rs.drift(&live), ["chain output has 2 rules, expected 3"]);
+    }
+
+    #[test]
+    fn flags_and_set_lookups_compare_like_nft_lists_them() {
+        let ours = json!([{ "match": { "op": "in", "left": { "ct": { "key": "state" } }, "right": ["related", "established"] } }]);
+        let nft = json!([{ "match": { "op": "==", "left": { "ct": { "key": "state" } }, "right": ["established", "related"] } }]);
+        assert_eq!(rule_key(&ours), rule_key(&nft));
+
+        let one = json!([{ "match": { "op": "in", "left": { "ct": { "key": "state" } }, "right": ["established"] } }]);
+        let plain = json!([{ "match": { "op": "==", "left": { "ct": { "key": "state" } }, "right": "established" } }]);
+        assert_eq!(rule_key(&one), rule_key(&plain));
+
+        let ne = json!([{ "match": { "op": "!=", "left": { "ct": { "key": "state" } }, "right": "established" } }]);
+        assert_ne!(rule_key(&ne), rule_key(&plain));
+    }
+
+    /// Exemption rules written the way the helper builds them.
+    fn exemptions() -> Ruleset {
+        let rule = |addr: &str, len: u8, proto: Option<&str>, port: Option<Rhs>| {
+            let mut r = vec![Expr::Match {
+                left: Lhs::Payload(if len > 32 { "ip6" } else { "ip" }, "daddr"),
+                op: Op::Eq,
+                right: Rhs::Prefix(addr.parse().unwrap(), len),
+            }];
+            if let Some(proto) = proto {
+                r.push(Expr::Match {
+                    left: Lhs::Meta("l4proto"),
+                    op: Op::Eq,
+                    right: Rhs::Str(proto.to_string()),
+                });
+            }
+            if let Some(port) = port {
+                r.push(Expr::Match {
+                    left: Lhs::Payload("th", "dport"),
+                    op: Op::Eq,
+                    right: port,
+                });
+            }
+            r.push(Expr::Accept);
+            Rule(r)
+        };
+        Ruleset {
+            table: "stellartest".to_string(),
+            sets: vec![],
+            chains: vec![Chain {
+                name: "exemptions".to_string(),
+                hook: None,
+                rules: vec![
+                    rule("192.0.2.0", 24, Some("tcp"), Some(Rhs::Range(5000, 5010))),
+                    rule("203.0.113.9", 32, Some("udp"), Some(Rhs::Num(5060))),
+                    rule("198.51.100.0", 24, Some("tcp"), None),
+                    rule("2001:db8:1::", 128, None, None),
+                ],
+            }],
+        }
+    }
+
+    /// `nft -j list table inet stellartest` after loading `exemptions()`.
+    fn exemptions_listed() -> Value {
+        serde_json::from_str(
+            r#"{"nftables": [{"metainfo": {"version": "1.0.9", "release_name": "Old Doc Yak #3", "json_schema_version": 1}}, {"table": {"family": "inet", "name": "stellartest", "handle": 7}}, {"chain": {"family": "inet", "table": "stellartest", "name": "exemptions", "handle": 1}}, {"rule": {"family": "inet", "table": "stellartest", "chain": "exemptions", "handle": 2, "expr": [{"match": {"op": "==", "left": {"payload": {"protocol": "ip", "field": "daddr"}}, "right": {"prefix": {"addr": "192.0.2.0", "len": 24}}}}, {"match": {"op": "==", "left": {"payload": {"protocol": "tcp", "field": "dport"}}, "right": {"range": [5000, 5010]}}}, {"accept": null}]}}, {"rule": {"family": "inet", "table": "stellartest", "chain": "exemptions", "handle": 3, "expr": [{"match": {"op": "==", "left": {"payload": {"protocol": "ip", "field": "daddr"}}, "right": "203.0.113.9"}}, {"match": {"op": "==", "left": {"payload": {"protocol": "udp", "field": "dport"}}, "right": 5060}}, {"accept": null}]}}, {"rule": {"family": "inet", "table": "stellartest", "chain": "exemptions", "handle": 4, "expr": [{"match": {"op": "==", "left": {"payload": {"protocol": "ip", "field": "daddr"}}, "right": {"prefix": {"addr": "198.51.100.0", "len": 24}}}}, {"match": {"op": "==", "left": {"meta": {"key": "l4proto"}}, "right": "tcp"}}, {"accept": null}]}}, {"rule": {"family": "inet", "table": "stellartest", "chain": "exemptions", "handle": 5, "expr": [{"match": {"op": "==", "left": {"payload": {"protocol": "ip6", "field": "daddr"}}, "right": "2001:db8:1::"}}, {"accept": null}]}}]}"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn no_drift_for_exemptions_as_nft_lists_them() {
+        assert_eq!(
+            exemptions().drift(&exemptions_listed()),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn drift_reports_changed_exemption_port() {
+        let mut live = exemptions_listed();
+        live["nftables"][4]["rule"]["expr"][1]["match"]["right"] = json!(5061);
+        let drift = exemptions().drift(&live);
+        assert_eq!(drift.len(), 1);
+        assert!(
+            drift[0].starts_with("chain exemptions rule 2 is"),
+            "{drift:?}"
+        );
+    }
+
+    #[test]
+    fn transport_header_ports_fold_into_the_protocol_only_for_one_protocol() {
+        let th = |proto: Value| {
+            json!([
+                { "match": { "op": "==", "left": { "meta": { "key": "l4proto" } }, "right": proto } },
+                { "match": { "op": "==", "left": { "payload": { "protocol": "th", "field": "dport" } }, "right": 53 } },
+                { "drop": null }
+            ])
+        };
+        let tcp = json!([
+            { "match": { "op": "==", "left": { "payload": { "protocol": "tcp", "field": "dport" } }, "right": 53 } },
+            { "drop": null }
+        ]);
+        assert_eq!(rule_key(&th(json!("tcp"))), rule_key(&tcp));
+        assert_ne!(rule_key(&th(json!("udp"))), rule_key(&tcp));
+        // nft keeps `meta l4proto { tcp, udp } th dport 53` as written.
+        let both = th(json!({ "set": ["tcp", "udp"] }));
+        assert!(rule_key(&both).starts_with("meta:key:l4proto == { tcp, udp } payload:"));
+    }
+}
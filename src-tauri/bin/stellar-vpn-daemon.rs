@@ -0,0 +1,723 @@
+// src-tauri/bin/stellar-vpn-daemon.rs
+//
+// Privileged Linux service (root, socket-activated by systemd via stellar-vpn-daemon.socket)
+// - Speaks the JSON-lines protocol in src/daemon_protocol.rs
+// - Runs the bundled OpenVPN as root, one run at a time, tied to the connection that
+//   started it
+// - Applies kill switch requests through stellar-vpn-helper
+// - Streams OpenVPN logs, status and management events to the app
+//
+// The app never gains privileges itself. Clients are checked by their peer credentials
+// (src/peer_auth.rs): root, the console user, or members of `--allow-group`.
+// Without systemd (development) it binds its own socket:
+//     sudo stellar-vpn-daemon --openvpn /path/to/openvpn --helper /path/to/stellar-vpn-helper
+
+#[path = "../src/daemon_protocol.rs"]
+mod daemon_protocol;
+#[path = "../src/killswitch_exemption.rs"]
+mod killswitch_exemption;
+#[path = "../src/killswitch_request.rs"]
+mod killswitch_request;
+#[path = "../src/openvpn_config.rs"]
+mod openvpn_config;
+#[path = "../src/openvpn_mgmt.rs"]
+mod openvpn_mgmt;
+#[path = "../src/peer_auth.rs"]
+mod peer_auth;
+
+use std::{
+    env, fs,
+    io::Write,
+    os::unix::fs::{OpenOptionsExt, PermissionsExt},
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use clap::Parser;
+use daemon_protocol::{DaemonEvent, DaemonReq, DaemonResp, DAEMON_SOCKET, MAX_REQUEST_BYTES};
+use killswitch_request::{ErrorCode, HelperError, HelperRequest, HelperResponse, Request};
+use openvpn_mgmt::{MgmtClient, MgmtEvent};
+use peer_auth::{Peer, PeerPolicy};
+use serde::Serialize;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{unix::OwnedWriteHalf, UnixListener, UnixStream},
+    process::{Child, Command},
+    sync::{broadcast, Mutex},
+    time,
+};
+
+#[derive(Parser, Debug)]
+struct Args {
+    /// Socket to listen on when not started by systemd
+    #[arg(long, default_value = DAEMON_SOCKET)]
+    socket: String,
+    /// OpenVPN binary to run
+    #[arg(long, default_value = "/usr/lib/stellar-vpn/openvpn")]
+    openvpn: PathBuf,
+    /// Kill switch helper
+    #[arg(long, default_value = "/usr/libexec/stellar-vpn/stellar-vpn-helper")]
+    helper: PathBuf,
+    /// Also accept clients in this group (root and the console user always are)
+    #[arg(long)]
+    allow_group: Option<String>,
+}
+
+/// Root-only: config, credentials and management socket of the current run.
+const RUN_DIR: &str = "/run/stellar-vpn/session";
+const MAX_CONFIG_BYTES: usize = 256 * 1024;
+const MGMT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const STOP_GRACE: Duration = Duration::from_secs(3);
+const MGMT_BYTECOUNT_SECS: u32 = 1;
+
+struct Run {
+    id: u64,
+    child: Child,
+    mgmt: Option<MgmtClient>,
+}
+
+struct Inner {
+    status: &'static str,
+    run: Option<Run>,
+    next_id: u64,
+    /// Run id and uid of the client that started the latest run.
+    owner: Option<(u64, u32)>,
+}
+
+impl Inner {
+    /// Owner of the running OpenVPN, if `peer` may not stop or replace it.
+    fn foreign_owner(&self, peer: &Peer) -> Option<u32> {
+        let id = self.run.as_ref()?.id;
+        match self.owner {
+            Some((run, uid)) if run == id && !peer.may_control(uid) => Some(uid),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Daemon {
+    inner: Arc<Mutex<Inner>>,
+    /// Held while a run starts or stops, so two OpenVPNs never run at once.
+    connecting: Arc<Mutex<()>>,
+    /// (run id, event line) for every run.
+    events: broadcast::Sender<(u64, String)>,
+    policy: Arc<PeerPolicy>,
+    args: Arc<Args>,
+}
+
+impl Daemon {
+    fn emit(&self, run: u64, ev: &DaemonEvent) {
+        if let Ok(line) = serde_json::to_string(ev) {
+            let _ = self.events.send((run, line));
+        }
+    }
+
+    fn log(&self, run: u64, line: impl Into<String>) {
+        self.emit(run, &DaemonEvent::Log { line: line.into() });
+    }
+
+    fn set_status(&self, g: &mut Inner, run: u64, status: &'static str) {
+        if g.status != status {
+            g.status = status;
+            self.emit(
+                run,
+                &DaemonEvent::Status {
+                    status: status.into(),
+                },
+            );
+        }
+    }
+
+    /// A run is over: tell its listeners and remove its files.
+    fn finish_run(&self, g: &mut Inner, id: u64, code: i32) {
+        self.log(id, format!("[daemon] OpenVPN exited (code={code})"));
+        // A newer run may have started while this one was being stopped.
+        if g.run.is_none() {
+            self.set_status(g, id, "disconnected");
+        }
+        self.emit(id, &DaemonEvent::Exited { code });
+        remove_run_files(id);
+    }
+}
+
+fn run_file(id: u64, name: &str) -> PathBuf {
+    Path::new(RUN_DIR).join(format!("{name}-{id}"))
+}
+
+fn remove_run_files(id: u64) {
+    for name in ["config", "auth", "mgmt"] {
+        let _ = fs::remove_file(run_file(id, name));
+    }
+}
+
+/// Starts empty on every daemon start, so nothing from a crashed run lingers.
+fn prepare_run_dir() -> Result<(), String> {
+    let _ = fs::remove_dir_all(RUN_DIR);
+    fs::create_dir_all(RUN_DIR).map_err(|e| format!("Failed to create {RUN_DIR}: {e}"))?;
+    fs::set_permissions(RUN_DIR, fs::Permissions::from_mode(0o700))
+        .map_err(|e| format!("Failed to secure {RUN_DIR}: {e}"))
+}
+
+fn write_private(path: &Path, contents: &str) -> Result<(), String> {
+    let _ = fs::remove_file(path);
+    let mut f = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .custom_flags(libc::O_NOFOLLOW | libc::O_CLOEXEC)
+        .open(path)
+        .map_err(|e| format!("Failed to create {}: {e}", path.display()))?;
+    f.write_all(contents.as_bytes())
+        .map_err(|e| format!("Failed to write {}: {e}", path.display()))
+}
+
+/// The app checks this too, but this is what runs OpenVPN as root.
+fn check_connect(config: &str, username: &str, password: &str) -> Result<(), String> {
+    if config.len() > MAX_CONFIG_BYTES {
+        return Err(format!("Config is larger than {MAX_CONFIG_BYTES} bytes"));
+    }
+    let cfg = openvpn_config::OpenVpnConfig::parse(config)?;
+    cfg.check_allowed()?;
+    cfg.validate()?;
+
+    // One line each in the auth file.
+    let bad = |s: &str| s.trim().is_empty() || s.contains(['\n', '\r', '\0']);
+    if bad(username) || bad(password) {
+        return Err("missing or invalid username/password".to_string());
+    }
+    Ok(())
+}
+
+/// Stop a run taken out of `Inner`: SIGTERM over management first, hard kill as fallback.
+/// Called without the lock held, so status and other clients are not blocked during the
+/// grace period.
+async fn stop_run(d: &Daemon, run: Option<Run>) {
+    let Some(mut run) = run else {
+        return;
+    };
+    let mut exited = None;
+    if let Some(m) = run.mgmt.as_mut() {
+        if m.signal("SIGTERM").await.is_ok() {
+            exited = time::timeout(STOP_GRACE, run.child.wait())
+                .await
+                .ok()
+                .and_then(Result::ok);
+        }
+    }
+    if exited.is_none() {
+        let _ = run.child.kill().await;
+        exited = run.child.wait().await.ok();
+    }
+    let code = exited.and_then(|s| s.code()).unwrap_or(-1);
+    d.finish_run(&mut *d.inner.lock().await, run.id, code);
+}
+
+async fn start_run(
+    d: &Daemon,
+    id: u64,
+    config: &str,
+    username: &str,
+    password: &str,
+) -> Result<(), String> {
+    let cfg_path = run_file(id, "config");
+    let auth_path = run_file(id, "auth");
+    let mgmt_sock = run_file(id, "mgmt");
+    let redirect_gateway = openvpn_config::OpenVpnConfig::parse(config)?.redirect_gateway_args();
+    write_private(&cfg_path, config)?;
+    write_private(&auth_path, &format!("{username}\n{password}\n"))?;
+
+    let mut child = Command::new(&d.args.openvpn)
+        .arg("--config")
+        .arg(&cfg_path)
+        .arg("--auth-user-pass")
+        .arg(&auth_path)
+        .arg("--auth-nocache")
+        .args(redirect_gateway)
+        .arg("--verb")
+        .arg("3")
+        .args(openvpn_mgmt::openvpn_args(&mgmt_sock))
+        .kill_on_drop(true)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to start openvpn: {e}"))?;
+
+    // Output until the management interface takes over (startup/config errors).
+    let attached = Arc::new(AtomicBool::new(false));
+    let stdout_task = child.stdout.take().map(|out| {
+        let (d, attached) = (d.clone(), attached.clone());
+        tokio::spawn(async move {
+            let mut r = BufReader::new(out).lines();
+            while let Ok(Some(l)) = r.next_line().await {
+                if !attached.load(Ordering::Relaxed) {
+                    d.log(id, l);
+                }
+            }
+        })
+    });
+    if let Some(err) = child.stderr.take() {
+        let d = d.clone();
+        tokio::spawn(async move {
+            let mut r = BufReader::new(err).lines();
+            while let Ok(Some(l)) = r.next_line().await {
+                d.log(id, l);
+            }
+        });
+    }
+
+    let attached_mgmt = tokio::select! {
+        r = MgmtClient::connect(&mgmt_sock, MGMT_CONNECT_TIMEOUT) => r,
+        res = child.wait() => {
+            let code = res.ok().and_then(|s| s.code()).unwrap_or(-1);
+            Err(format!("OpenVPN exited before opening its management interface (code={code})."))
+        }
+    };
+    let (mut mgmt, mgmt_rx) = match attached_mgmt {
+        Ok(m) => m,
+        Err(e) => {
+            let _ = child.kill().await;
+            let _ = child.wait().await;
+            // Let the caller see what OpenVPN printed before it gave up.
+            if let Some(t) = stdout_task {
+                let _ = time::timeout(Duration::from_secs(1), t).await;
+            }
+            return Err(e);
+        }
+    };
+    attached.store(true, Ordering::Relaxed);
+
+    let _ = mgmt.subscribe(MGMT_BYTECOUNT_SECS).await;
+    let _ = mgmt.hold_release().await;
+
+    d.inner.lock().await.run = Some(Run {
+        id,
+        child,
+        mgmt: Some(mgmt),
+    });
+    spawn_mgmt_reader(d.clone(), id, mgmt_rx);
+    spawn_child_watcher(d.clone(), id);
+    Ok(())
+}
+
+/// Forward management notifications and track the connection state.
+fn spawn_mgmt_reader(d: Daemon, id: u64, mut rx: tokio::sync::mpsc::UnboundedReceiver<MgmtEvent>) {
+    tokio::spawn(async move {
+        while let Some(ev) = rx.recv().await {
+            match &ev {
+                MgmtEvent::State(st) => {
+                    let mut g = d.inner.lock().await;
+                    if g.run.as_ref().map(|r| r.id) == Some(id) && st.name != "EXITING" {
+                        let status = if st.is_connected() {
+                            "connected"
+                        } else {
+                            "connecting"
+                        };
+                        d.set_status(&mut g, id, status);
+                    }
+                }
+                MgmtEvent::Hold(_) => {
+                    // Restarts re-enter the hold state; release again.
+                    let mut g = d.inner.lock().await;
+                    if let Some(run) = g.run.as_mut().filter(|r| r.id == id) {
+                        if let Some(m) = run.mgmt.as_mut() {
+                            let _ = m.hold_release().await;
+                        }
+                    }
+                }
+                _ => {}
+            }
+            d.emit(id, &DaemonEvent::Mgmt { event: ev });
+        }
+    });
+}
+
+/// Polls with try_wait, so the child stays in `Inner` where disconnect can still kill it.
+fn spawn_child_watcher(d: Daemon, id: u64) {
+    tokio::spawn(async move {
+        loop {
+            {
+                let mut g = d.inner.lock().await;
+                let Some(run) = g.run.as_mut().filter(|r| r.id == id) else {
+                    // Stopped (and reported) by stop_run.
+                    return;
+                };
+                let code = match run.child.try_wait() {
+                    Ok(None) => None,
+                    Ok(Some(status)) => Some(status.code().unwrap_or(-1)),
+                    Err(_) => Some(-1),
+                };
+                if let Some(code) = code {
+                    g.run = None;
+                    d.finish_run(&mut g, id, code);
+                    return;
+                }
+            }
+            time::sleep(Duration::from_millis(200)).await;
+        }
+    });
+}
+
+/// Kill switch requests go to stellar-vpn-helper, which we run as root.
+async fn kill_switch(helper: &Path, request: Request) -> HelperResponse {
+    let failed =
+        |msg: String| HelperResponse::from(Err(HelperError::new(ErrorCode::FirewallFailed, msg)));
+
+    let body = match serde_json::to_vec(&HelperRequest::new(request)) {
+        Ok(b) => b,
+        Err(e) => return failed(format!("Failed to encode helper request: {e}")),
+    };
+    let mut child = match Command::new(helper)
+        .arg("request")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+    {
+        Ok(c) => c,
+        Err(e) => return failed(format!("Failed to start kill switch helper: {e}")),
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        if let Err(e) = stdin.write_all(&body).await {
+            return failed(format!("Failed to send helper request: {e}"));
+        }
+    }
+    let out = match child.wait_with_output().await {
+        Ok(o) => o,
+        Err(e) => return failed(format!("Failed to wait for kill switch helper: {e}")),
+    };
+
+    serde_json::from_slice(&out.stdout).unwrap_or_else(|_| {
+        failed(format!(
+            "Kill switch helper failed (exit={}): {}",
+            out.status.code().unwrap_or(-1),
+            String::from_utf8_lossy(&out.stderr).trim()
+        ))
+    })
+}
+
+async fn write_json(w: &mut OwnedWriteHalf, v: &impl Serialize) -> std::io::Result<()> {
+    let line = serde_json::to_string(v).unwrap_or_else(|_| "{\"ok\":false}".to_string());
+    write_line(w, &line).await
+}
+
+async fn write_line(w: &mut OwnedWriteHalf, line: &str) -> std::io::Result<()> {
+    w.write_all(line.as_bytes()).await?;
+    w.write_all(b"\n").await
+}
+
+fn in_use(uid: u32) -> String {
+    format!("The VPN is in use by another user (uid={uid})")
+}
+
+fn in_use_by(uid: u32) -> DaemonResp {
+    DaemonResp::err(in_use(uid))
+}
+
+async fn handle_conn(stream: UnixStream, d: Daemon) {
+    let peer = match Peer::of(&stream) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("[daemon] cannot read peer credentials: {e}");
+            return;
+        }
+    };
+    let (r, mut w) = stream.into_split();
+    let mut reader = BufReader::new(r);
+
+    let mut line = String::new();
+    match (&mut reader)
+        .take(MAX_REQUEST_BYTES)
+        .read_line(&mut line)
+        .await
+    {
+        Ok(n) if n > 0 => {}
+        _ => return,
+    }
+
+    let req = serde_json::from_str::<DaemonReq>(line.trim());
+    if let Err(why) = d.policy.check(&peer) {
+        eprintln!("[daemon] denied {peer}: {why}");
+        let msg = format!("Not allowed to use the Stellar VPN service: {why}");
+        let _ = match req {
+            Ok(DaemonReq::KillSwitch { .. }) => {
+                let err = HelperError::new(ErrorCode::NotAllowed, msg);
+                write_json(&mut w, &HelperResponse::from(Err(err))).await
+            }
+            _ => write_json(&mut w, &DaemonResp::err(msg)).await,
+        };
+        return;
+    }
+
+    let req = match req {
+        Ok(r) => r,
+        Err(e) => {
+            let _ = write_json(&mut w, &DaemonResp::err(format!("bad request: {e}"))).await;
+            return;
+        }
+    };
+
+    match req {
+        DaemonReq::Status => {
+            let status = d.inner.lock().await.status;
+            let resp = DaemonResp {
+                status: Some(status.into()),
+                ..DaemonResp::ok()
+            };
+            let _ = write_json(&mut w, &resp).await;
+        }
+
+        DaemonReq::Disconnect => {
+            let _stopping = d.connecting.lock().await;
+            let run = {
+                let mut g = d.inner.lock().await;
+                if let Some(uid) = g.foreign_owner(&peer) {
+                    drop(g);
+                    eprintln!("[daemon] denied disconnect from {peer}: run belongs to uid={uid}");
+                    let _ = write_json(&mut w, &in_use_by(uid)).await;
+                    return;
+                }
+                g.run.take()
+            };
+            stop_run(&d, run).await;
+            let _ = write_json(&mut w, &DaemonResp::ok()).await;
+        }
+
+        DaemonReq::KillSwitch { request } => {
+            // While a run is active its kill switch belongs to the run's owner.
+            let foreign = d.inner.lock().await.foreign_owner(&peer);
+            if let Some(uid) = foreign {
+                eprintln!(
+                    "[daemon] denied kill switch request from {peer}: run belongs to uid={uid}"
+                );
+                let err = HelperError::new(ErrorCode::NotAllowed, in_use(uid));
+                let _ = write_json(&mut w, &HelperResponse::from(Err(err))).await;
+                return;
+            }
+            let resp = kill_switch(&d.args.helper, request).await;
+            let _ = write_json(&mut w, &resp).await;
+        }
+
+        DaemonReq::Subscribe => {
+            let mut rx = d.events.subscribe();
+            let status = d.inner.lock().await.status;
+            let ev = DaemonEvent::Status {
+                status: status.into(),
+            };
+            if write_json(&mut w, &ev).await.is_err() {
+                return;
+            }
+            // Only runs this peer may control; looked up once per run.
+            let mut visible: Option<(u64, bool)> = None;
+            loop {
+                match rx.recv().await {
+                    Ok((run, msg)) => {
+                        let allowed = match visible {
+                            Some((r, allowed)) if r == run => allowed,
+                            _ => {
+                                let owner = d.inner.lock().await.owner;
+                                let allowed =
+                                    owner.is_some_and(|(r, uid)| r == run && peer.may_control(uid));
+                                visible = Some((run, allowed));
+                                allowed
+                            }
+                        };
+                        if !allowed {
+                            continue;
+                        }
+                        if write_line(&mut w, &msg).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(_) => break,
+                }
+            }
+        }
+
+        DaemonReq::Connect {
+            config,
+            username,
+            password,
+        } => {
+            if let Err(e) = check_connect(&config, &username, &password) {
+                let _ = write_json(&mut w, &DaemonResp::err(format!("invalid config: {e}"))).await;
+                return;
+            }
+
+            let starting = d.connecting.clone().lock_owned().await;
+            let mut rx = d.events.subscribe();
+            let previous = {
+                let mut g = d.inner.lock().await;
+                if let Some(uid) = g.foreign_owner(&peer) {
+                    drop(g);
+                    eprintln!("[daemon] denied connect from {peer}: run belongs to uid={uid}");
+                    let _ = write_json(&mut w, &in_use_by(uid)).await;
+                    return;
+                }
+                g.run.take()
+            };
+            // No other run can start meanwhile: `starting` is held.
+            stop_run(&d, previous).await;
+            let id = {
+                let mut g = d.inner.lock().await;
+                let id = g.next_id;
+                g.next_id += 1;
+                g.owner = Some((id, peer.uid));
+                d.set_status(&mut g, id, "connecting");
+                id
+            };
+            d.log(id, "[daemon] starting OpenVPN…");
+
+            if let Err(e) = start_run(&d, id, &config, &username, &password).await {
+                d.finish_run(&mut *d.inner.lock().await, id, -1);
+                // Startup/config errors are in OpenVPN's output, which nobody streams yet.
+                let mut msg = e;
+                while let Ok((run, line)) = rx.try_recv() {
+                    if let Ok(DaemonEvent::Log { line }) = serde_json::from_str(&line) {
+                        if run == id && !line.starts_with("[daemon]") {
+                            msg.push('\n');
+                            msg.push_str(&line);
+                        }
+                    }
+                }
+                let _ = write_json(&mut w, &DaemonResp::err(msg)).await;
+                return;
+            }
+            drop(starting);
+
+            if write_json(&mut w, &DaemonResp::ok()).await.is_err() {
+                stop_if_current(&d, id).await;
+                return;
+            }
+
+            // Stream this run's events until it ends; if the app goes away first, so does
+            // the run.
+            let mut scratch = [0u8; 256];
+            loop {
+                tokio::select! {
+                    msg = rx.recv() => match msg {
+                        Ok((run, msg)) if run == id => {
+                            if write_line(&mut w, &msg).await.is_err() {
+                                stop_if_current(&d, id).await;
+                                return;
+                            }
+                            if matches!(
+                                serde_json::from_str::<DaemonEvent>(&msg),
+                                Ok(DaemonEvent::Exited { .. })
+                            ) {
+                                return;
+                            }
+                        }
+                        Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                        Err(_) => return,
+                    },
+                    n = reader.read(&mut scratch) => {
+                        if matches!(n, Ok(0) | Err(_)) {
+                            stop_if_current(&d, id).await;
+                            return;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn stop_if_current(d: &Daemon, id: u64) {
+    let _stopping = d.connecting.lock().await;
+    let run = {
+        let mut g = d.inner.lock().await;
+        if g.run.as_ref().map(|r| r.id) != Some(id) {
+            return;
+        }
+        g.run.take()
+    };
+    d.log(id, "[daemon] client went away, stopping OpenVPN");
+    stop_run(d, run).await;
+}
+
+/// The socket systemd opened for us (socket activation), if started by
+/// stellar-vpn-daemon.socket.
+fn systemd_listener() -> std::io::Result<Option<UnixListener>> {
+    use std::os::fd::FromRawFd;
+
+    // SD_LISTEN_FDS_START
+    const FD: i32 = 3;
+
+    let for_us = env::var("LISTEN_PID")
+        .ok()
+        .and_then(|p| p.parse::<u32>().ok())
+        == Some(std::process::id());
+    let fds = env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|n| n.parse::<i32>().ok())
+        .unwrap_or(0);
+    if !for_us || fds < 1 {
+        return Ok(None);
+    }
+    // Not for OpenVPN or the helper.
+    env::remove_var("LISTEN_PID");
+    env::remove_var("LISTEN_FDS");
+    env::remove_var("LISTEN_FDNAMES");
+    // SAFETY: LISTEN_PID/LISTEN_FDS say systemd passed us a listening socket at fd 3
+    // (sd_listen_fds(3)). Nothing else in the process has opened or adopted that fd yet, so
+    // the listener below becomes its only owner; fcntl on it has no memory effects.
+    unsafe { libc::fcntl(FD, libc::F_SETFD, libc::FD_CLOEXEC) };
+
+    let std = unsafe { std::os::unix::net::UnixListener::from_raw_fd(FD) };
+    std.set_nonblocking(true)?;
+    UnixListener::from_std(std).map(Some)
+}
+
+fn bind_socket(path: &str) -> std::io::Result<UnixListener> {
+    if let Some(dir) = Path::new(path).parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let _ = fs::remove_file(path);
+    let listener = UnixListener::bind(path)?;
+    // Same mode as SocketMode= in stellar-vpn-daemon.socket.
+    fs::set_permissions(path, fs::Permissions::from_mode(0o666))?;
+    Ok(listener)
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    if unsafe { libc::geteuid() } != 0 {
+        eprintln!("stellar-vpn-daemon must run as root.");
+        std::process::exit(1);
+    }
+
+    let args = Args::parse();
+    prepare_run_dir()?;
+
+    let listener = match systemd_listener()? {
+        Some(l) => l,
+        None => bind_socket(&args.socket)?,
+    };
+
+    let (events, _) = broadcast::channel(512);
+    let d = Daemon {
+        inner: Arc::new(Mutex::new(Inner {
+            status: "disconnected",
+            run: None,
+            next_id: 1,
+            owner: None,
+        })),
+        connecting: Arc::new(Mutex::new(())),
+        events,
+        policy: Arc::new(PeerPolicy::new(args.allow_group.clone())),
+        args: Arc::new(args),
+    };
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        tokio::spawn(handle_conn(stream, d.clone()));
+    }
+}
@@ -1,23 +1,42 @@
 // src-tauri/bin/stellar-vpn-helper-macos.rs
 //
 // Privileged macOS helper (runs as root via LaunchDaemon)
-// - Listens on a Unix socket (default: /tmp/stellar-vpn-helper.sock)
-// - Accepts JSON lines: connect / disconnect / subscribe / status
+// - Listens on a Unix socket (default: /var/run/stellar-vpn/stellar-vpn-helper.sock)
+// - Accepts JSON lines: hello / connect / disconnect / subscribe / status
+//   (protocol in src/helper_protocol.rs, shared with the app)
 // - Starts/stops OpenVPN as root
 // - Broadcasts logs + status to all subscribers
 //
 // IMPORTANT FIXES:
-// - Socket permissions are set to 0666 so the non-root GUI app can connect (avoids os error 13).
+// - Socket permissions are set to 0666 so the non-root GUI app can connect (avoids os error 13);
+//   peer credentials decide who may use it (see src/peer_auth.rs).
 // - Child watcher uses try_wait() (does NOT move the child out), so disconnect can still kill it.
+// - Connection state comes from the OpenVPN management interface, not from stdout wording.
+
+#[path = "../src/helper_protocol.rs"]
+mod helper_protocol;
+#[path = "../src/openvpn_config.rs"]
+mod openvpn_config;
+#[path = "../src/openvpn_mgmt.rs"]
+mod openvpn_mgmt;
+#[path = "../src/peer_auth.rs"]
+mod peer_auth;
 
 use std::{
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
     time::Duration,
 };
 
+use helper_protocol::{Event, Req, Request, Resp, HELPER_SOCKET, PROTOCOL_VERSION, VERSION};
+use openvpn_mgmt::{MgmtClient, MgmtEvent};
+use peer_auth::{Peer, PeerPolicy};
+
 use clap::Parser;
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
 use tokio::{
     io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
     net::{UnixListener, UnixStream},
@@ -29,31 +48,11 @@ use tokio::{
 #[derive(Parser, Debug)]
 struct Args {
     /// Unix socket path the helper listens on
-    #[arg(long, default_value = "/tmp/stellar-vpn-helper.sock")]
+    #[arg(long, default_value = HELPER_SOCKET)]
     socket: String,
-}
-
-#[derive(Debug, Serialize)]
-struct Resp {
-    ok: bool,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    error: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    status: Option<String>,
-}
-
-#[derive(Debug, Deserialize)]
-#[serde(tag = "cmd", rename_all = "lowercase")]
-enum Req {
-    Connect {
-        openvpn: String,
-        config: String,
-        username: String,
-        password: String,
-    },
-    Disconnect,
-    Subscribe,
-    Status,
+    /// Also accept clients in this group (root and the console user always are)
+    #[arg(long)]
+    allow_group: Option<String>,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -76,15 +75,23 @@ impl St {
 struct Inner {
     status: St,
     child: Option<tokio::process::Child>,
+    mgmt: Option<MgmtClient>,
+    /// uid of the client that started the latest OpenVPN.
+    owner: Option<u32>,
 }
 
-#[derive(Debug, Serialize)]
-#[serde(tag = "type", rename_all = "lowercase")]
-enum Event {
-    Log { line: String },
-    Status { status: String },
+impl Inner {
+    /// Owner of the running OpenVPN, if `peer` may not stop or replace it.
+    fn foreign_owner(&self, peer: &Peer) -> Option<u32> {
+        self.child.as_ref()?;
+        self.owner.filter(|&uid| !peer.may_control(uid))
+    }
 }
 
+const MGMT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const STOP_GRACE: Duration = Duration::from_secs(3);
+const MGMT_BYTECOUNT_SECS: u32 = 1;
+
 fn is_safe_openvpn_path(p: &str) -> bool {
     let s = p.trim();
     if s.is_empty() {
@@ -116,6 +123,17 @@ fn is_safe_config_path(p: &str) -> bool {
         || s.starts_with("/tmp/")
 }
 
+async fn check_config(p: &str) -> Result<openvpn_config::OpenVpnConfig, String> {
+    let text = tokio::fs::read_to_string(p)
+        .await
+        .map_err(|e| format!("read config failed: {e}"))?;
+    let cfg = openvpn_config::OpenVpnConfig::parse(&text)?;
+    // The app checks this too, but this is what runs OpenVPN as root.
+    cfg.check_allowed()?;
+    cfg.validate()?;
+    Ok(cfg)
+}
+
 async fn write_json(stream: &mut UnixStream, v: &impl Serialize) -> std::io::Result<()> {
     let line = serde_json::to_string(v).unwrap_or_else(|_| "{\"ok\":false}".to_string());
     stream.write_all(line.as_bytes()).await?;
@@ -137,6 +155,131 @@ fn make_auth_path() -> PathBuf {
     PathBuf::from(format!("/tmp/stellar-vpn-desktop/auth-{t}.txt"))
 }
 
+fn make_mgmt_path() -> PathBuf {
+    let t = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    PathBuf::from(format!("/tmp/stellar-vpn-desktop/mgmt-{t}.sock"))
+}
+
+/// Stop the running OpenVPN: SIGTERM over management first, hard kill as fallback.
+async fn stop_child(g: &mut Inner) {
+    let mgmt = g.mgmt.take();
+    if let Some(mut c) = g.child.take() {
+        if let Some(mut m) = mgmt {
+            if m.signal("SIGTERM").await.is_ok()
+                && time::timeout(STOP_GRACE, c.wait()).await.is_ok()
+            {
+                return;
+            }
+        }
+        let _ = c.kill().await;
+        let _ = c.wait().await;
+    }
+}
+
+/// Forward management notifications as helper events and track the connection state.
+/// `routes_ipv6`: OpenVPN was told to redirect IPv6 into the tunnel.
+fn spawn_mgmt_reader(
+    inner: Arc<Mutex<Inner>>,
+    ev_tx: broadcast::Sender<String>,
+    mut rx: tokio::sync::mpsc::UnboundedReceiver<MgmtEvent>,
+    routes_ipv6: bool,
+) {
+    tokio::spawn(async move {
+        while let Some(ev) = rx.recv().await {
+            if ev.is_auth_failure() {
+                send_event(
+                    &ev_tx,
+                    Event::Log {
+                        line: "[mac-helper] AUTH_FAILED detected".into(),
+                    },
+                )
+                .await;
+            }
+
+            match ev {
+                MgmtEvent::Log { message, .. } => {
+                    send_event(&ev_tx, Event::Log { line: message }).await;
+                }
+                MgmtEvent::State(st) => {
+                    send_event(
+                        &ev_tx,
+                        Event::Log {
+                            line: format!("[mac-helper] OpenVPN state: {} {}", st.name, st.desc),
+                        },
+                    )
+                    .await;
+
+                    // IPv6 egress cannot be blocked here (no pf rules yet), so an IPv4-only
+                    // tunnel is ended rather than letting IPv6 bypass it.
+                    if st.is_connected() && !(routes_ipv6 && st.local_ipv6.is_some()) {
+                        send_event(
+                            &ev_tx,
+                            Event::Log {
+                                line: "[mac-helper] The tunnel is IPv4-only and IPv6 cannot be blocked on macOS. Disconnecting so IPv6 traffic cannot bypass the VPN.".into(),
+                            },
+                        )
+                        .await;
+                        let mut g = inner.lock().await;
+                        if let Some(m) = g.mgmt.as_mut() {
+                            let _ = m.signal("SIGTERM").await;
+                        }
+                        continue;
+                    }
+
+                    if st.is_connected() {
+                        let mut g = inner.lock().await;
+                        g.status = St::Connected;
+                    }
+
+                    // Detailed state name (resolving, authenticating, ...) for the app.
+                    if let Some(name) = openvpn_mgmt::ui_state_name(&st.name) {
+                        send_event(
+                            &ev_tx,
+                            Event::Status {
+                                status: name.into(),
+                            },
+                        )
+                        .await;
+                    }
+                }
+                MgmtEvent::Hold(_) => {
+                    // Restarts re-enter the hold state; release again.
+                    let mut g = inner.lock().await;
+                    if let Some(m) = g.mgmt.as_mut() {
+                        let _ = m.hold_release().await;
+                    }
+                }
+                MgmtEvent::ByteCount {
+                    bytes_in,
+                    bytes_out,
+                } => {
+                    send_event(
+                        &ev_tx,
+                        Event::Stats {
+                            bytes_in,
+                            bytes_out,
+                        },
+                    )
+                    .await;
+                }
+                MgmtEvent::Fatal(msg) => {
+                    send_event(
+                        &ev_tx,
+                        Event::Log {
+                            line: format!("[mac-helper] OpenVPN fatal: {msg}"),
+                        },
+                    )
+                    .await;
+                }
+                _ => {}
+            }
+        }
+    });
+}
+
 async fn write_auth_file(path: &Path, username: &str, password: &str) -> Result<(), String> {
     if username.trim().is_empty() || password.trim().is_empty() {
         return Err("missing username/password".into());
@@ -171,12 +314,14 @@ async fn spawn_child_watcher(inner: Arc<Mutex<Inner>>, ev_tx: broadcast::Sender<
                         Ok(Some(status)) => {
                             let code = status.code().unwrap_or(-1);
                             g.child = None;
+                            g.mgmt = None;
                             g.status = St::Disconnected;
                             Some(code)
                         }
                         Ok(None) => None,
                         Err(_) => {
                             g.child = None;
+                            g.mgmt = None;
                             g.status = St::Disconnected;
                             Some(-1)
                         }
@@ -210,37 +355,105 @@ async fn spawn_child_watcher(inner: Arc<Mutex<Inner>>, ev_tx: broadcast::Sender<
     });
 }
 
+fn in_use_by(id: u64, uid: u32) -> Resp {
+    Resp::err(id, format!("The VPN is in use by another user (uid={uid})"))
+}
+
+async fn read_line(reader: &mut BufReader<UnixStream>) -> Option<String> {
+    let mut line = String::new();
+    match reader.read_line(&mut line).await {
+        Ok(n) if n > 0 => Some(line),
+        _ => None,
+    }
+}
+
+/// None after telling the client its line did not parse.
+async fn parse_request(reader: &mut BufReader<UnixStream>, line: &str) -> Option<Request> {
+    match serde_json::from_str(line.trim()) {
+        Ok(r) => Some(r),
+        Err(e) => {
+            let _ = write_json(reader.get_mut(), &Resp::err(0, format!("bad request: {e}"))).await;
+            None
+        }
+    }
+}
+
+/// Answer `hello` with our versions. False when the app speaks another protocol.
+async fn handshake(
+    reader: &mut BufReader<UnixStream>,
+    id: u64,
+    protocol: u32,
+    app_version: &str,
+) -> bool {
+    let mut resp = if protocol == PROTOCOL_VERSION {
+        Resp::ok(id)
+    } else {
+        Resp::err(
+            id,
+            format!(
+                "protocol mismatch: app {app_version} speaks {protocol}, helper {VERSION} speaks {PROTOCOL_VERSION}"
+            ),
+        )
+    };
+    resp.protocol = Some(PROTOCOL_VERSION);
+    resp.helper_version = Some(VERSION.to_string());
+    write_json(reader.get_mut(), &resp).await.is_ok() && resp.ok
+}
+
 async fn handle_conn(
     stream: UnixStream,
     inner: Arc<Mutex<Inner>>,
     ev_tx: broadcast::Sender<String>,
     mut ev_rx: broadcast::Receiver<String>,
+    policy: Arc<PeerPolicy>,
 ) {
+    let peer = match Peer::of(&stream) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("[mac-helper] cannot read peer credentials: {e}");
+            return;
+        }
+    };
     let mut reader = BufReader::new(stream);
-    let mut line = String::new();
+    let Some(line) = read_line(&mut reader).await else {
+        return;
+    };
 
-    if reader
-        .read_line(&mut line)
-        .await
-        .ok()
-        .filter(|n| *n > 0)
-        .is_none()
-    {
+    if let Err(why) = policy.check(&peer) {
+        eprintln!("[mac-helper] denied {peer}: {why}");
+        let msg = format!("Not allowed to use the Stellar VPN helper: {why}");
+        let _ = write_json(reader.get_mut(), &Resp::err(0, msg)).await;
         return;
     }
 
-    let req: Req = match serde_json::from_str(line.trim()) {
-        Ok(r) => r,
-        Err(e) => {
-            let _ = reader
-                .get_mut()
-                .write_all(format!("{{\"ok\":false,\"error\":\"bad json: {e}\"}}\n").as_bytes())
-                .await;
+    let Some(mut request) = parse_request(&mut reader, &line).await else {
+        return;
+    };
+
+    // Handshake first (clients from before it go straight to their request).
+    if let Req::Hello {
+        protocol,
+        app_version,
+    } = &request.req
+    {
+        if !handshake(&mut reader, request.id, *protocol, app_version).await {
             return;
         }
-    };
+        let Some(line) = read_line(&mut reader).await else {
+            return;
+        };
+        let Some(next) = parse_request(&mut reader, &line).await else {
+            return;
+        };
+        request = next;
+    }
+    let id = request.id;
+
+    match request.req {
+        Req::Hello { .. } => {
+            let _ = write_json(reader.get_mut(), &Resp::err(id, "hello must come first")).await;
+        }
 
-    match req {
         Req::Subscribe => {
             // send current status immediately
             let st = { inner.lock().await.status };
@@ -258,10 +471,14 @@ async fn handle_conn(
                 )
                 .await;
 
-            // stream events
+            // stream events, except those of another user's session
             loop {
                 match ev_rx.recv().await {
                     Ok(msg) => {
+                        let owner = { inner.lock().await.owner };
+                        if owner.is_some_and(|uid| !peer.may_control(uid)) {
+                            continue;
+                        }
                         if reader
                             .get_mut()
                             .write_all(format!("{msg}\n").as_bytes())
@@ -282,9 +499,8 @@ async fn handle_conn(
             let _ = write_json(
                 reader.get_mut(),
                 &Resp {
-                    ok: true,
-                    error: None,
                     status: Some(st.as_str().into()),
+                    ..Resp::ok(id)
                 },
             )
             .await;
@@ -293,10 +509,15 @@ async fn handle_conn(
         Req::Disconnect => {
             {
                 let mut g = inner.lock().await;
-                if let Some(mut c) = g.child.take() {
-                    let _ = c.kill().await;
-                    let _ = c.wait().await;
+                if let Some(uid) = g.foreign_owner(&peer) {
+                    drop(g);
+                    eprintln!(
+                        "[mac-helper] denied disconnect from {peer}: session belongs to uid={uid}"
+                    );
+                    let _ = write_json(reader.get_mut(), &in_use_by(id, uid)).await;
+                    return;
                 }
+                stop_child(&mut g).await;
                 g.status = St::Disconnected;
             }
 
@@ -308,15 +529,7 @@ async fn handle_conn(
             )
             .await;
 
-            let _ = write_json(
-                reader.get_mut(),
-                &Resp {
-                    ok: true,
-                    error: None,
-                    status: None,
-                },
-            )
-            .await;
+            let _ = write_json(reader.get_mut(), &Resp::ok(id)).await;
         }
 
         Req::Connect {
@@ -326,39 +539,45 @@ async fn handle_conn(
             password,
         } => {
             if !is_safe_openvpn_path(&openvpn) {
-                let _ = write_json(
-                    reader.get_mut(),
-                    &Resp {
-                        ok: false,
-                        error: Some("unsafe openvpn path".into()),
-                        status: None,
-                    },
-                )
-                .await;
+                let _ = write_json(reader.get_mut(), &Resp::err(id, "unsafe openvpn path")).await;
                 return;
             }
 
             if !is_safe_config_path(&config) || !Path::new(&config).exists() {
                 let _ = write_json(
                     reader.get_mut(),
-                    &Resp {
-                        ok: false,
-                        error: Some("config path not found/unsafe".into()),
-                        status: None,
-                    },
+                    &Resp::err(id, "config path not found/unsafe"),
                 )
                 .await;
                 return;
             }
 
-            // kill existing
+            let cfg = match check_config(&config).await {
+                Ok(cfg) => cfg,
+                Err(e) => {
+                    let _ = write_json(
+                        reader.get_mut(),
+                        &Resp::err(id, format!("invalid config: {e}")),
+                    )
+                    .await;
+                    return;
+                }
+            };
+
+            // stop existing (only our own)
             {
                 let mut g = inner.lock().await;
-                if let Some(mut c) = g.child.take() {
-                    let _ = c.kill().await;
-                    let _ = c.wait().await;
+                if let Some(uid) = g.foreign_owner(&peer) {
+                    drop(g);
+                    eprintln!(
+                        "[mac-helper] denied connect from {peer}: session belongs to uid={uid}"
+                    );
+                    let _ = write_json(reader.get_mut(), &in_use_by(id, uid)).await;
+                    return;
                 }
+                stop_child(&mut g).await;
                 g.status = St::Connecting;
+                g.owner = Some(peer.uid);
             }
 
             send_event(
@@ -393,28 +612,23 @@ async fn handle_conn(
                 )
                 .await;
 
-                let _ = write_json(
-                    reader.get_mut(),
-                    &Resp {
-                        ok: false,
-                        error: Some(e),
-                        status: None,
-                    },
-                )
-                .await;
+                let _ = write_json(reader.get_mut(), &Resp::err(id, e)).await;
                 return;
             }
 
+            let mgmt_sock = make_mgmt_path();
+            let _ = std::fs::remove_file(&mgmt_sock);
+
             let mut cmd = Command::new(PathBuf::from(openvpn));
             cmd.arg("--config")
                 .arg(&config)
                 .arg("--auth-user-pass")
                 .arg(&auth_path)
                 .arg("--auth-nocache")
-                .arg("--redirect-gateway")
-                .arg("def1")
+                .args(cfg.redirect_gateway_args())
                 .arg("--verb")
                 .arg("3")
+                .args(openvpn_mgmt::openvpn_args(&mgmt_sock))
                 .kill_on_drop(true)
                 .stdout(std::process::Stdio::piped())
                 .stderr(std::process::Stdio::piped());
@@ -438,46 +652,24 @@ async fn handle_conn(
 
                     let _ = write_json(
                         reader.get_mut(),
-                        &Resp {
-                            ok: false,
-                            error: Some(format!("Failed to start openvpn: {e}")),
-                            status: None,
-                        },
+                        &Resp::err(id, format!("Failed to start openvpn: {e}")),
                     )
                     .await;
                     return;
                 }
             };
 
-            // pipe logs
+            // pipe logs until the management interface takes over (startup/config errors)
+            let mgmt_attached = Arc::new(AtomicBool::new(false));
+
             if let Some(out) = child.stdout.take() {
                 let tx = ev_tx.clone();
-                let inner2 = inner.clone();
+                let attached = mgmt_attached.clone();
                 tokio::spawn(async move {
                     let mut r = BufReader::new(out).lines();
                     while let Ok(Some(l)) = r.next_line().await {
-                        send_event(&tx, Event::Log { line: l.clone() }).await;
-                        if l.contains("Initialization Sequence Completed") {
-                            {
-                                let mut g = inner2.lock().await;
-                                g.status = St::Connected;
-                            }
-                            send_event(
-                                &tx,
-                                Event::Status {
-                                    status: "connected".into(),
-                                },
-                            )
-                            .await;
-                        }
-                        if l.contains("AUTH_FAILED") || l.contains("auth-failure") {
-                            send_event(
-                                &tx,
-                                Event::Log {
-                                    line: "[mac-helper] AUTH_FAILED detected".into(),
-                                },
-                            )
-                            .await;
+                        if !attached.load(Ordering::Relaxed) {
+                            send_event(&tx, Event::Log { line: l }).await;
                         }
                     }
                 });
@@ -493,11 +685,41 @@ async fn handle_conn(
                 });
             }
 
+            let (mut mgmt, mgmt_rx) =
+                match MgmtClient::connect(&mgmt_sock, MGMT_CONNECT_TIMEOUT).await {
+                    Ok(m) => m,
+                    Err(e) => {
+                        let _ = child.kill().await;
+                        let _ = child.wait().await;
+                        let _ = tokio::fs::remove_file(&auth_path).await;
+                        {
+                            let mut g = inner.lock().await;
+                            g.status = St::Disconnected;
+                        }
+                        send_event(
+                            &ev_tx,
+                            Event::Status {
+                                status: "disconnected".into(),
+                            },
+                        )
+                        .await;
+
+                        let _ = write_json(reader.get_mut(), &Resp::err(id, e)).await;
+                        return;
+                    }
+                };
+            mgmt_attached.store(true, Ordering::Relaxed);
+
+            let _ = mgmt.subscribe(MGMT_BYTECOUNT_SECS).await;
+            let _ = mgmt.hold_release().await;
+
             // store child + start watcher (try_wait based)
             {
                 let mut g = inner.lock().await;
                 g.child = Some(child);
+                g.mgmt = Some(mgmt);
             }
+            spawn_mgmt_reader(inner.clone(), ev_tx.clone(), mgmt_rx, cfg.has_ipv6());
             spawn_child_watcher(inner.clone(), ev_tx.clone()).await;
 
             // delete auth after a small delay (avoid race)
@@ -507,15 +729,7 @@ async fn handle_conn(
                 let _ = tokio::fs::remove_file(&auth_to_delete).await;
             });
 
-            let _ = write_json(
-                reader.get_mut(),
-                &Resp {
-                    ok: true,
-                    error: None,
-                    status: None,
-                },
-            )
-            .await;
+            let _ = write_json(reader.get_mut(), &Resp::ok(id)).await;
         }
     }
 }
@@ -524,7 +738,7 @@ fn set_socket_perms(socket_path: &str) {
     #[cfg(unix)]
     {
         use std::os::unix::fs::PermissionsExt;
-        // 0666 so non-root GUI can connect
+        // 0666 so non-root GUI can connect; handle_conn checks who it is
         let _ = std::fs::set_permissions(socket_path, std::fs::Permissions::from_mode(0o666));
     }
 }
@@ -538,6 +752,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let args = Args::parse();
 
+    // /var/run is emptied at boot: recreate our directory
+    if let Some(dir) = Path::new(&args.socket).parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
     // remove old socket
     let _ = std::fs::remove_file(&args.socket);
 
@@ -552,12 +771,21 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let inner = Arc::new(Mutex::new(Inner {
         status: St::Disconnected,
         child: None,
+        mgmt: None,
+        owner: None,
     }));
+    let policy = Arc::new(PeerPolicy::new(args.allow_group.clone()));
 
     loop {
         let (stream, _) = listener.accept().await?;
         let ev_rx = ev_tx.subscribe();
-        tokio::spawn(handle_conn(stream, inner.clone(), ev_tx.clone(), ev_rx));
+        tokio::spawn(handle_conn(
+            stream,
+            inner.clone(),
+            ev_tx.clone(),
+            ev_rx,
+            policy.clone(),
+        ));
         time::sleep(Duration::from_millis(5)).await;
     }
 }
@@ -1,67 +1,44 @@
 // src-tauri/bin/stellar-vpn-helper.rs
+//
+// Linux kill switch helper. Run as root by stellar-vpn-daemon, and only ever takes a JSON
+// request on stdin (see src/killswitch_request.rs):
+//
+//     stellar-vpn-helper request < request.json
+mod iptables;
+#[path = "../src/killswitch_exemption.rs"]
+mod killswitch_exemption;
+#[path = "../src/killswitch_request.rs"]
+mod killswitch_request;
+mod nft_ruleset;
+
 use std::{
-    env, fs,
-    io::Write,
-    net::{IpAddr, ToSocketAddrs},
-    path::PathBuf,
+    env,
+    io::Read,
+    net::IpAddr,
     process::{Command, Stdio},
 };
 
+use iptables::Iptables;
+use killswitch_exemption::Exemption;
+use killswitch_request::{
+    Endpoint, ErrorCode, HelperError, HelperRequest, HelperResponse, KillSwitchPlan, Request,
+    MAX_REQUEST_BYTES, PROTOCOL_VERSION,
+};
+use nft_ruleset::{Chain, Elem, Expr, Hook, Lhs, Op, Rhs, Rule, Ruleset, Set};
+use serde::{Serialize, Serializer};
+use serde_json::{Map, Value};
+
 fn die(msg: &str) -> ! {
     eprintln!("{msg}");
     std::process::exit(1);
 }
 
-fn resolve_host(host: &str, port: u16) -> Vec<IpAddr> {
-    let addr = format!("{host}:{port}");
-    match addr.to_socket_addrs() {
-        Ok(iter) => iter.map(|sa| sa.ip()).collect(),
-        Err(_) => vec![],
-    }
-}
-
-fn parse_openvpn_remotes(config_text: &str) -> Vec<(String, u16, String)> {
-    let mut proto = "udp".to_string();
-
-    for line in config_text.lines() {
-        let l = line.trim();
-        if l.is_empty() || l.starts_with('#') || l.starts_with(';') {
-            continue;
-        }
-        if l.starts_with("proto ") {
-            let parts: Vec<&str> = l.split_whitespace().collect();
-            if parts.len() >= 2 {
-                proto = parts[1].to_lowercase();
-            }
-        }
-    }
-
-    let mut remotes = vec![];
-    for line in config_text.lines() {
-        let l = line.trim();
-        if l.is_empty() || l.starts_with('#') || l.starts_with(';') {
-            continue;
-        }
-        if l.starts_with("remote ") {
-            let parts: Vec<&str> = l.split_whitespace().collect();
-            if parts.len() >= 2 {
-                let host = parts[1].to_string();
-                let port = if parts.len() >= 3 {
-                    parts[2].parse().unwrap_or(1194)
-                } else {
-                    1194
-                };
-                remotes.push((host, port, proto.clone()));
-            }
-        }
-    }
-
-    remotes
-}
+const KILLSWITCH_TABLE: &str = "stellarkillswitch";
+const IPV6_BLOCK_TABLE: &str = "stellaripv6block";
 
-fn nft_delete_table_strict() -> Result<(), String> {
+fn nft_delete_table_strict(table: &str) -> Result<(), String> {
     let out = Command::new("nft")
-        .args(["delete", "table", "inet", "stellarkillswitch"])
+        .args(["delete", "table", "inet", table])
         .stdout(Stdio::null())
         .stderr(Stdio::piped())
         .output()
@@ -78,207 +55,1158 @@ fn nft_delete_table_strict() -> Result<(), String> {
     }
 
     Err(format!(
-        "Failed to delete kill switch table (exit={}):\n{}",
+        "Failed to delete table {table} (exit={}):\n{}",
         out.status.code().unwrap_or(-1),
         err
     ))
 }
 
-fn run_nft_script(script: &str) -> Result<(), String> {
-    let mut child = Command::new("nft")
-        .arg("-f")
-        .arg("-")
-        .stdin(Stdio::piped())
-        .stdout(Stdio::null())
-        .stderr(Stdio::piped())
-        .spawn()
-        .map_err(|e| format!("Failed to start nft: {e}"))?;
+// Local network destinations exempted with `allow_lan`. Fixed list on purpose: none of these
+// ranges is routed on the internet, so the exemption cannot be used to bypass the tunnel.
+const LAN_V4: &[&str] = &[
+    "10.0.0.0/8",
+    "172.16.0.0/12",
+    "192.168.0.0/16",
+    "169.254.0.0/16",
+    "224.0.0.0/24",
+    "239.255.255.250",
+    "255.255.255.255",
+];
+const LAN_V6: &[&str] = &["fe80::/10", "fc00::/7", "ff02::/16"];
+
+/// What the kill switch lets out besides loopback and established flows. Built from the
+/// request's plan for `enable`/`plan`, and read back from the live rules for `status`.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+struct KillSwitchPolicy {
+    /// VPN servers the handshake may go to.
+    remotes: Vec<AllowedRemote>,
+    /// Only these tunnel interfaces may carry traffic.
+    tunnel_ifaces: Vec<String>,
+    /// Pushed resolvers, reachable through the tunnel only.
+    dns: Vec<IpAddr>,
+    allow_lan: bool,
+    /// Rate-limited kernel log line for every blocked packet.
+    log_drops: bool,
+    /// User-defined destinations reachable outside the tunnel.
+    exemptions: Vec<Exemption>,
+}
 
-    {
-        let stdin = child.stdin.as_mut().ok_or("Failed to open nft stdin")?;
-        stdin
-            .write_all(script.as_bytes())
-            .map_err(|e| format!("Failed writing nft script: {e}"))?;
+// `CIDR[,tcp|udp[,PORT[-PORT]]]`, as in the logs.
+impl Serialize for Exemption {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&self.to_string())
     }
+}
 
-    let out = child
-        .wait_with_output()
-        .map_err(|e| format!("Failed waiting for nft: {e}"))?;
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+struct AllowedRemote {
+    ip: IpAddr,
+    proto: String,
+    port: u16,
+}
 
-    if out.status.success() {
-        Ok(())
-    } else {
-        Err(format!(
-            "nft failed (exit={}):\n{}",
-            out.status.code().unwrap_or(-1),
-            String::from_utf8_lossy(&out.stderr)
-        ))
+impl From<&Endpoint> for AllowedRemote {
+    fn from(e: &Endpoint) -> Self {
+        AllowedRemote {
+            ip: e.ip,
+            proto: e.proto.as_str().to_string(),
+            port: e.port,
+        }
     }
 }
 
-fn is_ip_literal(host: &str) -> bool {
-    host.parse::<std::net::IpAddr>().is_ok()
+impl KillSwitchPolicy {
+    /// Sorted and deduplicated, so policies read back from different backends compare equal.
+    fn normalize(&mut self) {
+        self.remotes.sort();
+        self.remotes.dedup();
+        self.tunnel_ifaces.sort();
+        self.tunnel_ifaces.dedup();
+        self.dns.sort();
+        self.dns.dedup();
+        self.exemptions.sort();
+        self.exemptions.dedup();
+    }
 }
 
-fn build_script(remotes: Vec<(String, u16, String)>) -> Result<String, String> {
-    let mut s = String::new();
+// ---------------- Firewall backends ----------------
+
+/// Where the kill switch rules live. nftables is preferred; iptables/ip6tables covers hosts
+/// without a working `nft` (iptables-legacy setups, containers).
+trait Firewall {
+    /// Reported to the app with every result.
+    fn name(&self) -> &'static str;
+    /// What `enable` would load, without touching the firewall.
+    fn plan(&self, p: &KillSwitchPolicy) -> Result<Value, String>;
+    /// Replaces any previous kill switch rules and checks the result.
+    fn enable(&self, p: &KillSwitchPolicy) -> Result<(), String>;
+    /// Points the active rules at `p`'s tunnel interfaces and DNS.
+    fn set_tunnel(&self, p: &KillSwitchPolicy) -> Result<(), String>;
+    fn disable(&self) -> Result<(), String>;
+    /// None when the kill switch is off.
+    fn read_policy(&self) -> Result<Option<KillSwitchPolicy>, String>;
+    /// How the live rules differ from what `enable` would load for `p` (empty when intact).
+    fn drift(&self, p: &KillSwitchPolicy) -> Result<Vec<String>, String>;
+    /// What the drop rules have blocked so far; None when the kill switch is off.
+    fn drop_stats(&self) -> Result<Option<DropStats>, String>;
+    /// `remote`, the server the connection runs over, stays reachable while blocked.
+    fn set_ipv6_block(&self, block: bool, remote: Option<&AllowedRemote>) -> Result<(), String>;
+    fn ipv6_blocked(&self) -> Result<bool, String>;
+}
+
+struct Nftables;
+
+impl Nftables {
+    /// `nft` is installed and the kernel has nf_tables.
+    fn detect() -> Option<Self> {
+        let ok = Command::new("nft")
+            .args(["list", "tables"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|s| s.success());
+        ok.then_some(Nftables)
+    }
+}
 
-    // Build fresh table every time (we delete before applying).
-    s.push_str("add table inet stellarkillswitch\n");
-    s.push_str("add chain inet stellarkillswitch output { type filter hook output priority 0; policy accept; }\n");
-    s.push_str("flush chain inet stellarkillswitch output\n");
+impl Firewall for Nftables {
+    fn name(&self) -> &'static str {
+        "nftables"
+    }
 
-    // Allow loopback + established
-    s.push_str("add rule inet stellarkillswitch output oifname \"lo\" accept\n");
-    s.push_str("add rule inet stellarkillswitch output ct state established,related accept\n");
+    fn plan(&self, p: &KillSwitchPolicy) -> Result<Value, String> {
+        Ok(build_ruleset(p).replace_commands())
+    }
 
-    // Allow tunnel interfaces
-    s.push_str("add rule inet stellarkillswitch output oifname { \"tun\", \"tun0\", \"tun1\", \"tun2\", \"tun3\", \"tun4\", \"tun5\", \"tun6\", \"tun7\", \"tun8\", \"tun9\", \"tap0\", \"tap1\", \"tap2\", \"tap3\", \"tap4\", \"tap5\", \"tap6\", \"tap7\", \"tap8\", \"tap9\" } accept\n");
+    fn enable(&self, p: &KillSwitchPolicy) -> Result<(), String> {
+        // Replaces any previous table in the same transaction (no unprotected window),
+        // then checks the kernel holds what we meant to load.
+        let rs = build_ruleset(p);
+        nft_ruleset::apply_verified(&rs.replace_commands(), &rs)
+    }
 
-    // DNS (compat). Note: if a system uses DoH/DoT only, DNS might fail; we handle that with fallback below.
-    s.push_str("add rule inet stellarkillswitch output udp dport 53 accept\n");
-    s.push_str("add rule inet stellarkillswitch output tcp dport 53 accept\n");
+    fn set_tunnel(&self, p: &KillSwitchPolicy) -> Result<(), String> {
+        // One batch, so there is no window where the sets are empty or hold stale entries.
+        let rs = tunnel_ruleset(&p.tunnel_ifaces, &p.dns);
+        nft_ruleset::apply_verified(&rs.refill_commands(), &rs)
+    }
 
-    let mut any_allow = false;
+    fn disable(&self) -> Result<(), String> {
+        nft_delete_table_strict(KILLSWITCH_TABLE)
+    }
 
-    // Allow handshake to configured VPN remotes
-    for (host, port, proto) in remotes {
-        let tcp = proto.contains("tcp");
+    fn read_policy(&self) -> Result<Option<KillSwitchPolicy>, String> {
+        read_nft_policy()
+    }
 
-        if is_ip_literal(&host) {
-            // Host is already an IP literal
-            any_allow = true;
-            if tcp {
-                // We don't know if it's v4/v6 here in string, but nft will parse correct family via ip/ip6:
-                // Safer: try parse
-                match host.parse::<IpAddr>() {
-          Ok(IpAddr::V4(v4)) => s.push_str(&format!("add rule inet stellarkillswitch output ip daddr {v4} tcp dport {port} accept\n")),
-          Ok(IpAddr::V6(v6)) => s.push_str(&format!("add rule inet stellarkillswitch output ip6 daddr {v6} tcp dport {port} accept\n")),
-          Err(_) => {}
+    fn drift(&self, p: &KillSwitchPolicy) -> Result<Vec<String>, String> {
+        let Some(live) = nft_ruleset::list_table(KILLSWITCH_TABLE)? else {
+            return Ok(vec![format!("table {KILLSWITCH_TABLE} is missing")]);
+        };
+        let mut drift = build_ruleset(p).drift(&live);
+
+        // Exemptions are rules, not set elements, so compare their matches too.
+        let mut want = p.exemptions.clone();
+        want.sort();
+        want.dedup();
+        let have = nft_exemptions(&live);
+        if have != want {
+            drift.push(format!(
+                "exemptions are {:?}, expected {:?}",
+                have.iter().map(Exemption::to_string).collect::<Vec<_>>(),
+                want.iter().map(Exemption::to_string).collect::<Vec<_>>()
+            ));
         }
-            } else {
-                match host.parse::<IpAddr>() {
-          Ok(IpAddr::V4(v4)) => s.push_str(&format!("add rule inet stellarkillswitch output ip daddr {v4} udp dport {port} accept\n")),
-          Ok(IpAddr::V6(v6)) => s.push_str(&format!("add rule inet stellarkillswitch output ip6 daddr {v6} udp dport {port} accept\n")),
-          Err(_) => {}
+        Ok(drift)
+    }
+
+    fn drop_stats(&self) -> Result<Option<DropStats>, String> {
+        Ok(nft_ruleset::list_table(KILLSWITCH_TABLE)?.map(|v| nft_drop_stats(&v)))
+    }
+
+    fn set_ipv6_block(&self, block: bool, remote: Option<&AllowedRemote>) -> Result<(), String> {
+        if !block {
+            return nft_delete_table_strict(IPV6_BLOCK_TABLE);
         }
-            }
-            continue;
+        let rs = ipv6_block_ruleset(remote);
+        nft_ruleset::apply_verified(&rs.replace_commands(), &rs)
+    }
+
+    fn ipv6_blocked(&self) -> Result<bool, String> {
+        Ok(nft_ruleset::list_table(IPV6_BLOCK_TABLE)?.is_some())
+    }
+}
+
+fn detect_firewall() -> Result<Box<dyn Firewall>, String> {
+    if let Some(nft) = Nftables::detect() {
+        return Ok(Box::new(nft));
+    }
+    if let Some(ipt) = Iptables::detect() {
+        return Ok(Box::new(ipt));
+    }
+    Err("No usable firewall: neither `nft` nor `iptables` works here (not installed, or missing CAP_NET_ADMIN)".to_string())
+}
+
+/// Blocked-traffic counters, for users and support asking what the kill switch is doing.
+#[derive(Debug, Default, Serialize)]
+struct DropStats {
+    packets: u64,
+    bytes: u64,
+    /// Distinct destination/protocol/port combinations blocked recently (None where the
+    /// backend cannot tell).
+    connections: Option<u64>,
+    /// Most blocked first, at most TOP_BLOCKED.
+    top_destinations: Vec<BlockedDestination>,
+}
+
+#[derive(Debug, Serialize)]
+struct BlockedDestination {
+    address: IpAddr,
+    proto: String,
+    port: u16,
+    packets: u64,
+    bytes: u64,
+}
+
+const TOP_BLOCKED: usize = 10;
+
+// ---------------- Ruleset ----------------
+
+// Everything the kill switch drops goes through this chain, which counts it.
+const BLOCKED_CHAIN: &str = "blocked";
+// Per-destination counters of the blocked chain; entries expire after an hour and the set
+// is bounded, so a flood of destinations cannot grow it without limit.
+const BLOCKED_METER_SIZE: u32 = 4096;
+const BLOCKED_METER_TIMEOUT_SECS: u32 = 3600;
+const DROP_LOG_PREFIX: &str = "stellar-killswitch drop: ";
+// User exemptions, one accept rule each.
+const EXEMPTIONS_CHAIN: &str = "exemptions";
+
+const OUTPUT_FILTER: Hook = Hook {
+    kind: "filter",
+    hook: "output",
+    prio: 0,
+    policy: "accept",
+};
+
+fn eq(left: Lhs, right: Rhs) -> Expr {
+    Expr::Match {
+        left,
+        op: Op::Eq,
+        right,
+    }
+}
+
+fn daddr(ip6: bool) -> Lhs {
+    Lhs::Payload(if ip6 { "ip6" } else { "ip" }, "daddr")
+}
+
+fn oifname(right: Rhs) -> Expr {
+    eq(Lhs::Meta("oifname"), right)
+}
+
+fn set_ref(name: &str) -> Rhs {
+    Rhs::SetRef(name.to_string())
+}
+
+fn tcp_or_udp() -> Expr {
+    eq(
+        Lhs::Meta("l4proto"),
+        Rhs::Anon(vec![Rhs::Str("tcp".into()), Rhs::Str("udp".into())]),
+    )
+}
+
+fn dport(port: u64) -> Expr {
+    eq(Lhs::Payload("th", "dport"), Rhs::Num(port))
+}
+
+fn set(name: &str, key: &[&'static str], interval: bool, elements: Vec<Elem>) -> Set {
+    Set {
+        name: name.to_string(),
+        key: key.to_vec(),
+        interval,
+        elements,
+    }
+}
+
+/// `10.0.0.0/8` or a single address.
+fn cidr_elem(s: &str) -> Elem {
+    match s.split_once('/') {
+        Some((addr, len)) => Elem::Prefix(
+            addr.parse().expect("valid LAN prefix"),
+            len.parse().expect("valid LAN prefix length"),
+        ),
+        None => Elem::Str(s.to_string()),
+    }
+}
+
+/// Tunnel interface and the resolvers the server pushed (empty until OpenVPN is up).
+/// Replaced as a unit by `set-tunnel`.
+fn tunnel_ruleset(ifaces: &[String], dns: &[IpAddr]) -> Ruleset {
+    let addrs = |v6: bool| {
+        dns.iter()
+            .filter(|ip| ip.is_ipv6() == v6)
+            .map(|ip| Elem::Str(ip.to_string()))
+            .collect()
+    };
+
+    Ruleset {
+        table: KILLSWITCH_TABLE.to_string(),
+        sets: vec![
+            set(
+                "tunnel_ifaces",
+                &["ifname"],
+                false,
+                ifaces.iter().map(|d| Elem::Str(d.clone())).collect(),
+            ),
+            set("dns_v4", &["ipv4_addr"], false, addrs(false)),
+            set("dns_v6", &["ipv6_addr"], false, addrs(true)),
+        ],
+        chains: vec![],
+    }
+}
+
+fn build_policy(plan: KillSwitchPlan) -> Result<KillSwitchPolicy, String> {
+    let exemptions = plan.validate()?;
+    let remotes = plan.endpoints.iter().map(AllowedRemote::from).collect();
+
+    Ok(KillSwitchPolicy {
+        remotes,
+        tunnel_ifaces: plan.interface.into_iter().collect(),
+        dns: plan.dns,
+        allow_lan: plan.allow_lan,
+        log_drops: plan.log_drops,
+        exemptions,
+    })
+}
+
+// Everything the policy allows lives in named sets, so `status` can read it back.
+fn build_ruleset(p: &KillSwitchPolicy) -> Ruleset {
+    let accept = |mut exprs: Vec<Expr>| {
+        exprs.push(Expr::Accept);
+        Rule(exprs)
+    };
+
+    let mut rs = tunnel_ruleset(&p.tunnel_ifaces, &p.dns);
+
+    // LAN (printers, NAS, casting): private/link-local unicast, link-local multicast
+    // (mDNS 224.0.0.251 / ff02::fb), SSDP and limited broadcast. Empty unless allow_lan.
+    let lan = |list: &[&str]| {
+        if p.allow_lan {
+            list.iter().map(|s| cidr_elem(s)).collect()
+        } else {
+            vec![]
         }
+    };
+    rs.sets
+        .push(set("lan_v4", &["ipv4_addr"], true, lan(LAN_V4)));
+    rs.sets
+        .push(set("lan_v6", &["ipv6_addr"], true, lan(LAN_V6)));
+
+    // Handshake to the configured VPN remotes.
+    let mut v4 = Vec::new();
+    let mut v6 = Vec::new();
+    for r in &p.remotes {
+        let elem = Elem::Concat(vec![
+            Elem::Str(r.ip.to_string()),
+            Elem::Str(r.proto.clone()),
+            Elem::Num(r.port.into()),
+        ]);
+        if r.ip.is_ipv4() { &mut v4 } else { &mut v6 }.push(elem);
+    }
+    rs.sets.push(set(
+        "remotes_v4",
+        &["ipv4_addr", "inet_proto", "inet_service"],
+        false,
+        v4,
+    ));
+    rs.sets.push(set(
+        "remotes_v6",
+        &["ipv6_addr", "inet_proto", "inet_service"],
+        false,
+        v6,
+    ));
+
+    let l4_dport = || vec![Lhs::Meta("l4proto"), Lhs::Payload("th", "dport")];
+    let remote_key = |ip6: bool| Lhs::Concat([vec![daddr(ip6)], l4_dport()].concat());
+    let blocked = || Expr::Jump(BLOCKED_CHAIN.to_string());
+
+    let rules = vec![
+        // Allow loopback + established
+        accept(vec![oifname(Rhs::Str("lo".into()))]),
+        accept(vec![Expr::Match {
+            left: Lhs::Ct("state"),
+            op: Op::In,
+            right: Rhs::Flags(vec!["established", "related"]),
+        }]),
+        // DNS only to the pushed resolvers and only through the tunnel. Nothing else on
+        // port 53 (including LAN resolvers) gets out; remote hostnames are resolved by the
+        // app beforehand.
+        accept(vec![
+            oifname(set_ref("tunnel_ifaces")),
+            eq(daddr(false), set_ref("dns_v4")),
+            tcp_or_udp(),
+            dport(53),
+        ]),
+        accept(vec![
+            oifname(set_ref("tunnel_ifaces")),
+            eq(daddr(true), set_ref("dns_v6")),
+            tcp_or_udp(),
+            dport(53),
+        ]),
+        Rule(vec![tcp_or_udp(), dport(53), blocked()]),
+        // Tunnel device our OpenVPN opened (kept up to date with `set-tunnel`).
+        accept(vec![oifname(set_ref("tunnel_ifaces"))]),
+        accept(vec![eq(daddr(false), set_ref("lan_v4"))]),
+        accept(vec![eq(daddr(true), set_ref("lan_v6"))]),
+        accept(vec![eq(remote_key(false), set_ref("remotes_v4"))]),
+        accept(vec![eq(remote_key(true), set_ref("remotes_v6"))]),
+        Rule(vec![Expr::Jump(EXEMPTIONS_CHAIN.to_string())]),
+        // Default drop
+        Rule(vec![blocked()]),
+    ];
+
+    // The meters are separate rules: a full meter ends its rule, and that must never
+    // skip the drop.
+    let meter = |name: &str, ip6: bool| {
+        Rule(vec![Expr::Meter {
+            name: name.to_string(),
+            key: remote_key(ip6),
+            size: BLOCKED_METER_SIZE,
+            timeout: BLOCKED_METER_TIMEOUT_SECS,
+        }])
+    };
+    let mut blocked_rules = vec![meter("blocked_v4", false), meter("blocked_v6", true)];
+    if p.log_drops {
+        blocked_rules.push(Rule(vec![
+            Expr::Limit {
+                rate: 10,
+                per: "minute",
+            },
+            Expr::Log(DROP_LOG_PREFIX.to_string()),
+        ]));
+    }
+    blocked_rules.push(Rule(vec![Expr::Counter, Expr::Drop]));
+
+    // After the DNS drop, so an exemption never lets plain DNS past the tunnel.
+    let exempt_rules = p
+        .exemptions
+        .iter()
+        .map(|e| {
+            let mut exprs = vec![eq(daddr(e.addr.is_ipv6()), Rhs::Prefix(e.addr, e.prefix))];
+            if let Some(proto) = e.proto {
+                exprs.push(eq(Lhs::Meta("l4proto"), Rhs::Str(proto.as_str().into())));
+            }
+            if let Some((lo, hi)) = e.ports {
+                let right = if lo == hi {
+                    Rhs::Num(lo.into())
+                } else {
+                    Rhs::Range(lo.into(), hi.into())
+                };
+                exprs.push(eq(Lhs::Payload("th", "dport"), right));
+            }
+            accept(exprs)
+        })
+        .collect();
+    rs.chains.push(Chain {
+        name: EXEMPTIONS_CHAIN.to_string(),
+        hook: None,
+        rules: exempt_rules,
+    });
+    rs.chains.push(Chain {
+        name: BLOCKED_CHAIN.to_string(),
+        hook: None,
+        rules: blocked_rules,
+    });
+
+    rs.chains.insert(
+        0,
+        Chain {
+            name: "output".to_string(),
+            hook: Some(OUTPUT_FILTER),
+            rules,
+        },
+    );
+    rs
+}
+
+fn proto_name(v: &Value) -> String {
+    match v {
+        Value::String(s) => s.clone(),
+        Value::Number(n) if n.as_u64() == Some(6) => "tcp".to_string(),
+        Value::Number(n) if n.as_u64() == Some(17) => "udp".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// Read the policy back from the live table (None when the kill switch is off).
+fn read_nft_policy() -> Result<Option<KillSwitchPolicy>, String> {
+    let Some(v) = nft_ruleset::list_table(KILLSWITCH_TABLE)? else {
+        return Ok(None);
+    };
 
-        // Resolve hostname to IPs
-        let ips = resolve_host(&host, port);
-
-        if ips.is_empty() {
-            // Fallback: allow ONLY the remote port/proto to any destination.
-            // This keeps UX working even if DNS is "special" (DoH/DoT etc).
-            // Yes, it's less strict, but it's better than bricking the user offline.
-            any_allow = true;
-            if tcp {
-                s.push_str(&format!(
-                    "add rule inet stellarkillswitch output tcp dport {port} accept\n"
-                ));
-            } else {
-                s.push_str(&format!(
-                    "add rule inet stellarkillswitch output udp dport {port} accept\n"
-                ));
+    let mut p = KillSwitchPolicy::default();
+    let sets = v["nftables"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|o| o.get("set"));
+    for set in sets {
+        let elems = nft_ruleset::set_elements(set);
+        match set["name"].as_str().unwrap_or_default() {
+            "tunnel_ifaces" => p
+                .tunnel_ifaces
+                .extend(elems.iter().filter_map(|e| e.as_str().map(str::to_string))),
+            "dns_v4" | "dns_v6" => p.dns.extend(
+                elems
+                    .iter()
+                    .filter_map(|e| e.as_str()?.parse::<IpAddr>().ok()),
+            ),
+            "lan_v4" | "lan_v6" => p.allow_lan |= !elems.is_empty(),
+            "remotes_v4" | "remotes_v6" => {
+                for e in elems {
+                    let parts = e["concat"].as_array().map(Vec::as_slice).unwrap_or(&[]);
+                    let [ip, proto, port] = parts else {
+                        continue;
+                    };
+                    let Some(ip) = ip.as_str().and_then(|s| s.parse().ok()) else {
+                        continue;
+                    };
+                    p.remotes.push(AllowedRemote {
+                        ip,
+                        proto: proto_name(proto),
+                        port: port.as_u64().and_then(|n| n.try_into().ok()).unwrap_or(0),
+                    });
+                }
             }
-            continue;
+            _ => {}
         }
+    }
+
+    p.exemptions = nft_exemptions(&v);
+    p.log_drops = nft_rules(&v, BLOCKED_CHAIN)
+        .flat_map(|r| r["expr"].as_array().into_iter().flatten())
+        .any(|e| e.get("log").is_some());
+
+    p.normalize();
+    Ok(Some(p))
+}
+
+fn nft_rules<'a>(listing: &'a Value, chain: &'a str) -> impl Iterator<Item = &'a Value> {
+    listing["nftables"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|o| o.get("rule"))
+        .filter(move |r| r["chain"] == chain)
+}
+
+/// Exemptions from the rules of the exemptions chain, sorted.
+fn nft_exemptions(listing: &Value) -> Vec<Exemption> {
+    let mut out: Vec<Exemption> = nft_rules(listing, EXEMPTIONS_CHAIN)
+        .filter_map(|rule| {
+            let (mut cidr, mut proto, mut ports) = (None, None, None);
+            for m in rule["expr"]
+                .as_array()?
+                .iter()
+                .filter_map(|e| e.get("match"))
+            {
+                let (left, right) = (&m["left"], &m["right"]);
+                if left.pointer("/payload/field") == Some(&Value::from("daddr")) {
+                    cidr = Some(match right.get("prefix") {
+                        Some(pfx) => format!("{}/{}", pfx["addr"].as_str()?, pfx["len"]),
+                        None => right.as_str()?.to_string(),
+                    });
+                } else if left.pointer("/meta/key") == Some(&Value::from("l4proto")) {
+                    proto = Some(proto_name(right));
+                } else if left.pointer("/payload/field") == Some(&Value::from("dport")) {
+                    // nft may list `meta l4proto tcp th dport 80` as `tcp dport 80`.
+                    if let Some(p @ ("tcp" | "udp")) =
+                        left.pointer("/payload/protocol").and_then(Value::as_str)
+                    {
+                        proto = Some(p.to_string());
+                    }
+                    ports = Some(match right.get("range") {
+                        Some(r) => format!("{}-{}", r[0], r[1]),
+                        None => right.to_string(),
+                    });
+                }
+            }
+            Exemption::new(&cidr?, proto.as_deref(), ports.as_deref()).ok()
+        })
+        .collect();
+    out.sort();
+    out.dedup();
+    out
+}
+
+/// `{"packets": N, "bytes": M}` of a listed counter.
+fn counter_values(c: &Value) -> (u64, u64) {
+    (
+        c["packets"].as_u64().unwrap_or(0),
+        c["bytes"].as_u64().unwrap_or(0),
+    )
+}
+
+fn nft_drop_stats(listing: &Value) -> DropStats {
+    let mut stats = DropStats::default();
 
-        for ip in ips {
-            any_allow = true;
-            match (ip, tcp) {
-                (IpAddr::V4(v4), true) => s.push_str(&format!(
-          "add rule inet stellarkillswitch output ip daddr {v4} tcp dport {port} accept\n"
-        )),
-                (IpAddr::V4(v4), false) => s.push_str(&format!(
-          "add rule inet stellarkillswitch output ip daddr {v4} udp dport {port} accept\n"
-        )),
-                (IpAddr::V6(v6), true) => s.push_str(&format!(
-          "add rule inet stellarkillswitch output ip6 daddr {v6} tcp dport {port} accept\n"
-        )),
-                (IpAddr::V6(v6), false) => s.push_str(&format!(
-          "add rule inet stellarkillswitch output ip6 daddr {v6} udp dport {port} accept\n"
-        )),
+    for rule in nft_rules(listing, BLOCKED_CHAIN) {
+        for e in rule["expr"].as_array().into_iter().flatten() {
+            if let Some(c) = e.get("counter") {
+                let (packets, bytes) = counter_values(c);
+                stats.packets += packets;
+                stats.bytes += bytes;
             }
         }
     }
 
-    if !any_allow {
-        return Err("No VPN remotes could be allowed. Invalid config?".to_string());
+    let meters = listing["nftables"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|o| o.get("set"))
+        .filter(|s| matches!(s["name"].as_str(), Some("blocked_v4" | "blocked_v6")));
+    let mut dests = Vec::new();
+    for set in meters {
+        // Elements with counters are listed as {"elem": {"val": ..., "counter": ...}}.
+        for e in set["elem"].as_array().into_iter().flatten() {
+            let parts = e
+                .pointer("/elem/val/concat")
+                .and_then(Value::as_array)
+                .map(Vec::as_slice)
+                .unwrap_or(&[]);
+            let [ip, proto, port] = parts else {
+                continue;
+            };
+            let Some(address) = ip.as_str().and_then(|s| s.parse().ok()) else {
+                continue;
+            };
+            let (packets, bytes) = e
+                .pointer("/elem/counter")
+                .map(counter_values)
+                .unwrap_or((0, 0));
+            dests.push(BlockedDestination {
+                address,
+                proto: proto_name(proto),
+                port: port.as_u64().and_then(|n| n.try_into().ok()).unwrap_or(0),
+                packets,
+                bytes,
+            });
+        }
     }
 
-    // Default drop
-    s.push_str("add rule inet stellarkillswitch output drop\n");
-    Ok(s)
+    stats.connections = Some(dests.len() as u64);
+    dests.sort_by_key(|d| std::cmp::Reverse(d.packets));
+    dests.truncate(TOP_BLOCKED);
+    stats.top_destinations = dests;
+    stats
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
+#[derive(Serialize)]
+struct StatusReport {
+    backend: &'static str,
+    active: bool,
+    ipv6_blocked: bool,
+    policy: Option<KillSwitchPolicy>,
+}
 
-    if args.len() < 3 {
-        die(
-            "Usage: stellar-vpn-helper killswitch <enable|disable> [--config /path/to/config.ovpn]",
-        );
+#[derive(Serialize)]
+struct StatsReport {
+    backend: &'static str,
+    active: bool,
+    #[serde(flatten)]
+    drops: DropStats,
+}
+
+#[derive(Serialize)]
+struct VerifyReport {
+    backend: &'static str,
+    drift: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct PlanReport<'a> {
+    policy: &'a KillSwitchPolicy,
+    /// What `enable` would load with each backend: the `nft -j -f` batch and the
+    /// iptables-restore scripts. Finding out which one works here needs root.
+    commands: Map<String, Value>,
+}
+
+fn print_json(v: &impl Serialize) {
+    match serde_json::to_string_pretty(v) {
+        Ok(s) => println!("{s}"),
+        Err(e) => die(&format!("Failed to encode JSON: {e}")),
     }
+}
 
-    if args[1] != "killswitch" {
-        die("Unsupported command");
+// Drops all non-loopback IPv6 egress while the tunnel is IPv4-only, except to the VPN
+// server when the connection itself runs over IPv6. Separate table so it works with and
+// without the kill switch (a packet has to pass every output hook).
+fn ipv6_block_ruleset(remote: Option<&AllowedRemote>) -> Ruleset {
+    let mut rules = vec![Rule(vec![oifname(Rhs::Str("lo".into())), Expr::Accept])];
+    if let Some(r) = remote.filter(|r| r.ip.is_ipv6()) {
+        rules.push(Rule(vec![
+            eq(daddr(true), Rhs::Str(r.ip.to_string())),
+            eq(Lhs::Meta("l4proto"), Rhs::Str(r.proto.clone())),
+            dport(r.port.into()),
+            Expr::Accept,
+        ]));
     }
+    rules.push(Rule(vec![
+        eq(Lhs::Meta("nfproto"), Rhs::Str("ipv6".into())),
+        Expr::Drop,
+    ]));
+    Ruleset {
+        table: IPV6_BLOCK_TABLE.to_string(),
+        sets: vec![],
+        chains: vec![Chain {
+            name: "output".to_string(),
+            hook: Some(OUTPUT_FILTER),
+            rules,
+        }],
+    }
+}
 
-    let action = args[2].as_str();
-    let mut config: Option<PathBuf> = None;
+/// Result of requests that change the firewall.
+#[derive(Serialize)]
+struct DoneReport {
+    backend: &'static str,
+}
 
-    let mut i = 3;
-    while i < args.len() {
-        match args[i].as_str() {
-            "--config" => {
-                i += 1;
-                let p = args.get(i).cloned().unwrap_or_default();
-                if p.trim().is_empty() {
-                    die("--config requires a value");
-                }
-                config = Some(PathBuf::from(p));
-            }
-            _ => die("Unknown arg"),
+fn to_json(v: &impl Serialize) -> Result<Value, HelperError> {
+    serde_json::to_value(v).map_err(|e| {
+        HelperError::new(
+            ErrorCode::FirewallFailed,
+            format!("Failed to encode JSON: {e}"),
+        )
+    })
+}
+
+/// Reads and checks the request; nothing in it is trusted yet.
+fn read_request() -> Result<Request, HelperError> {
+    let invalid = |msg: String| HelperError::new(ErrorCode::InvalidRequest, msg);
+
+    let mut buf = Vec::new();
+    std::io::stdin()
+        .take(MAX_REQUEST_BYTES + 1)
+        .read_to_end(&mut buf)
+        .map_err(|e| invalid(format!("Failed to read request: {e}")))?;
+    if buf.len() as u64 > MAX_REQUEST_BYTES {
+        return Err(invalid(format!(
+            "Request is larger than {MAX_REQUEST_BYTES} bytes"
+        )));
+    }
+
+    // Version first, so an app from another release gets a clear answer rather than a
+    // complaint about fields this helper does not know.
+    let v: Value =
+        serde_json::from_slice(&buf).map_err(|e| invalid(format!("Request is not JSON: {e}")))?;
+    match v.get("version").and_then(Value::as_u64) {
+        Some(n) if n == u64::from(PROTOCOL_VERSION) => {}
+        Some(n) => {
+            return Err(HelperError::new(
+                ErrorCode::UnsupportedVersion,
+                format!("Request version {n} is not supported (helper speaks {PROTOCOL_VERSION})"),
+            ));
         }
-        i += 1;
+        None => return Err(invalid("Request has no version".to_string())),
     }
 
-    match action {
-        "disable" => {
-            if let Err(e) = nft_delete_table_strict() {
-                die(&e);
+    let req: HelperRequest =
+        serde_json::from_value(v).map_err(|e| invalid(format!("Invalid request: {e}")))?;
+    Ok(req.request)
+}
+
+/// Every request is validated before the firewall is touched, so a bad one never
+/// half-applies.
+fn handle(req: Request) -> Result<Value, HelperError> {
+    let invalid = |msg: String| HelperError::new(ErrorCode::InvalidRequest, msg);
+    let failed = |msg: String| HelperError::new(ErrorCode::FirewallFailed, msg);
+    let firewall = || detect_firewall().map_err(|e| HelperError::new(ErrorCode::NoFirewall, e));
+    let done = |fw: &dyn Firewall| to_json(&DoneReport { backend: fw.name() });
+
+    match req {
+        Request::Enable(plan) => {
+            let policy = build_policy(plan).map_err(invalid)?;
+            let fw = firewall()?;
+            fw.enable(&policy).map_err(failed)?;
+            done(fw.as_ref())
+        }
+        // Dry run: show what each backend would load, never touch the firewall (no root needed).
+        Request::Plan(plan) => {
+            let policy = build_policy(plan).map_err(invalid)?;
+            let mut commands = Map::new();
+            for fw in [&Nftables as &dyn Firewall, &Iptables::unprobed()] {
+                commands.insert(fw.name().to_string(), fw.plan(&policy).map_err(failed)?);
             }
-            return;
+            to_json(&PlanReport {
+                policy: &policy,
+                commands,
+            })
+        }
+        // Read-only check for the app's watchdog: are the live rules still ours?
+        Request::Verify(plan) => {
+            let policy = build_policy(plan).map_err(invalid)?;
+            let fw = firewall()?;
+            to_json(&VerifyReport {
+                backend: fw.name(),
+                drift: fw.drift(&policy).map_err(failed)?,
+            })
+        }
+        Request::Disable => {
+            let fw = firewall()?;
+            fw.disable().map_err(failed)?;
+            done(fw.as_ref())
+        }
+        Request::SetTunnel(plan) => {
+            let policy = build_policy(plan).map_err(invalid)?;
+            let fw = firewall()?;
+            fw.set_tunnel(&policy).map_err(failed)?;
+            done(fw.as_ref())
+        }
+        Request::Status => {
+            let fw = firewall()?;
+            let policy = fw.read_policy().map_err(failed)?;
+            to_json(&StatusReport {
+                backend: fw.name(),
+                active: policy.is_some(),
+                ipv6_blocked: fw.ipv6_blocked().map_err(failed)?,
+                policy,
+            })
+        }
+        Request::Stats => {
+            let fw = firewall()?;
+            let drops = fw.drop_stats().map_err(failed)?;
+            to_json(&StatsReport {
+                backend: fw.name(),
+                active: drops.is_some(),
+                drops: drops.unwrap_or_default(),
+            })
         }
-        "enable" => {
-            let cfg = config.unwrap_or_else(|| die("--config is required for enable"));
-            if !cfg.exists() {
-                die("Config file not found");
+        Request::Ipv6 { block, remote } => {
+            if let Some(r) = &remote {
+                r.validate().map_err(invalid)?;
             }
+            let remote = remote.as_ref().map(AllowedRemote::from);
+            let fw = firewall()?;
+            fw.set_ipv6_block(block, remote.as_ref()).map_err(failed)?;
+            done(fw.as_ref())
+        }
+    }
+}
 
-            let cfg_text = fs::read_to_string(&cfg).unwrap_or_default();
-            let remotes = parse_openvpn_remotes(&cfg_text);
-            if remotes.is_empty() {
-                die("No 'remote' entries found in config");
-            }
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 2 || args[1] != "request" {
+        die("Usage: stellar-vpn-helper request < request.json\n\nReads one versioned JSON request on stdin and answers with JSON on stdout.");
+    }
 
-            // Delete old table strictly first, then apply a clean script.
-            if let Err(e) = nft_delete_table_strict() {
-                die(&e);
-            }
+    let res = read_request().and_then(handle);
+    let ok = res.is_ok();
+    print_json(&HelperResponse::from(res));
+    if !ok {
+        std::process::exit(1);
+    }
+}
 
-            let script = match build_script(remotes) {
-                Ok(s) => s,
-                Err(e) => die(&e),
-            };
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use killswitch_exemption::KillSwitchExemption;
+    use killswitch_request::EndpointProto;
 
-            if let Err(e) = run_nft_script(&script) {
-                die(&e);
-            }
+    /// Connected tunnel with one resolver and one remote per family, one exemption, no LAN.
+    pub(crate) fn policy() -> KillSwitchPolicy {
+        KillSwitchPolicy {
+            remotes: vec![
+                AllowedRemote {
+                    ip: "198.51.100.7".parse().unwrap(),
+                    proto: "udp".to_string(),
+                    port: 1194,
+                },
+                AllowedRemote {
+                    ip: "2001:db8::7".parse().unwrap(),
+                    proto: "tcp".to_string(),
+                    port: 443,
+                },
+            ],
+            tunnel_ifaces: vec!["tun0".to_string()],
+            dns: vec!["10.8.0.1".parse().unwrap(), "fd00::1".parse().unwrap()],
+            allow_lan: false,
+            log_drops: false,
+            exemptions: vec![
+                Exemption::new("192.0.2.0/24", Some("tcp"), Some("5000-5010")).unwrap(),
+            ],
         }
-        _ => die("Invalid action"),
+    }
+
+    fn set_elems<'a>(rs: &'a Ruleset, name: &str) -> &'a [Elem] {
+        &rs.sets.iter().find(|s| s.name == name).unwrap().elements
+    }
+
+    fn chain<'a>(rs: &'a Ruleset, name: &str) -> &'a Chain {
+        rs.chains.iter().find(|c| c.name == name).unwrap()
+    }
+
+    fn str_elems(v: &[&str]) -> Vec<Elem> {
+        v.iter().map(|s| Elem::Str(s.to_string())).collect()
+    }
+
+    fn position(rules: &[Rule], rule: &Rule) -> usize {
+        rules
+            .iter()
+            .position(|r| r == rule)
+            .unwrap_or_else(|| panic!("missing rule {rule:?}"))
+    }
+
+    #[test]
+    fn tunnel_ruleset_splits_resolvers_by_family() {
+        let p = policy();
+        let rs = tunnel_ruleset(&p.tunnel_ifaces, &p.dns);
+        assert_eq!(set_elems(&rs, "tunnel_ifaces"), str_elems(&["tun0"]));
+        assert_eq!(set_elems(&rs, "dns_v4"), str_elems(&["10.8.0.1"]));
+        assert_eq!(set_elems(&rs, "dns_v6"), str_elems(&["fd00::1"]));
+        assert!(rs.chains.is_empty());
+    }
+
+    #[test]
+    fn tunnel_ruleset_before_connect_is_empty() {
+        let rs = tunnel_ruleset(&[], &[]);
+        let names: Vec<&str> = rs.sets.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["tunnel_ifaces", "dns_v4", "dns_v6"]);
+        assert!(rs.sets.iter().all(|s| s.elements.is_empty()));
+    }
+
+    #[test]
+    fn build_ruleset_refills_the_tunnel_sets_set_tunnel_replaces() {
+        let p = policy();
+        let full = build_ruleset(&p);
+        for set in tunnel_ruleset(&p.tunnel_ifaces, &p.dns).sets {
+            assert_eq!(set_elems(&full, &set.name), set.elements.as_slice());
+        }
+    }
+
+    #[test]
+    fn build_ruleset_lan_sets_follow_allow_lan() {
+        let mut p = policy();
+        let rs = build_ruleset(&p);
+        assert!(set_elems(&rs, "lan_v4").is_empty());
+        assert!(set_elems(&rs, "lan_v6").is_empty());
+
+        p.allow_lan = true;
+        let rs = build_ruleset(&p);
+        let v4 = set_elems(&rs, "lan_v4");
+        assert_eq!(v4.len(), LAN_V4.len());
+        assert!(v4.contains(&Elem::Prefix("192.168.0.0".parse().unwrap(), 16)));
+        assert!(v4.contains(&Elem::Str("255.255.255.255".to_string())));
+        let v6 = set_elems(&rs, "lan_v6");
+        assert_eq!(v6.len(), LAN_V6.len());
+        assert!(v6.contains(&Elem::Prefix("fe80::".parse().unwrap(), 10)));
+        // Interval sets, so the prefixes can be loaded.
+        assert!(rs
+            .sets
+            .iter()
+            .filter(|s| s.name.starts_with("lan_"))
+            .all(|s| s.interval));
+    }
+
+    #[test]
+    fn build_ruleset_splits_remotes_by_family() {
+        let rs = build_ruleset(&policy());
+        let remote = |ip: &str, proto: &str, port| {
+            vec![Elem::Concat(vec![
+                Elem::Str(ip.to_string()),
+                Elem::Str(proto.to_string()),
+                Elem::Num(port),
+            ])]
+        };
+        assert_eq!(
+            set_elems(&rs, "remotes_v4"),
+            remote("198.51.100.7", "udp", 1194)
+        );
+        assert_eq!(
+            set_elems(&rs, "remotes_v6"),
+            remote("2001:db8::7", "tcp", 443)
+        );
+    }
+
+    #[test]
+    fn build_ruleset_drops_plain_dns_before_anything_else_is_accepted() {
+        let rs = build_ruleset(&policy());
+        let names: Vec<&str> = rs.chains.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["output", "exemptions", "blocked"]);
+
+        let output = chain(&rs, "output");
+        assert_eq!(output.hook, Some(OUTPUT_FILTER));
+        let rules = &output.rules;
+        let blocked = Expr::Jump(BLOCKED_CHAIN.to_string());
+
+        let dns_v4 = Rule(vec![
+            oifname(set_ref("tunnel_ifaces")),
+            eq(daddr(false), set_ref("dns_v4")),
+            tcp_or_udp(),
+            dport(53),
+            Expr::Accept,
+        ]);
+        let dns_drop = Rule(vec![tcp_or_udp(), dport(53), blocked.clone()]);
+        let tunnel = Rule(vec![oifname(set_ref("tunnel_ifaces")), Expr::Accept]);
+        let lan = Rule(vec![eq(daddr(false), set_ref("lan_v4")), Expr::Accept]);
+        let exemptions = Rule(vec![Expr::Jump(EXEMPTIONS_CHAIN.to_string())]);
+
+        assert!(position(rules, &dns_v4) < position(rules, &dns_drop));
+        assert!(position(rules, &dns_drop) < position(rules, &tunnel));
+        assert!(position(rules, &dns_drop) < position(rules, &lan));
+        assert!(position(rules, &dns_drop) < position(rules, &exemptions));
+        assert_eq!(rules.last(), Some(&Rule(vec![blocked])));
+    }
+
+    #[test]
+    fn build_ruleset_has_one_accept_rule_per_exemption() {
+        let mut p = policy();
+        p.exemptions
+            .push(Exemption::new("2001:db8:1::/48", None, None).unwrap());
+        p.exemptions
+            .push(Exemption::new("203.0.113.9", Some("udp"), Some("5060")).unwrap());
+
+        let rs = build_ruleset(&p);
+        let prefix = |ip: &str, len| Rhs::Prefix(ip.parse().unwrap(), len);
+        let l4 = |proto: &str| eq(Lhs::Meta("l4proto"), Rhs::Str(proto.to_string()));
+        let th_dport = |right| eq(Lhs::Payload("th", "dport"), right);
+        assert_eq!(
+            chain(&rs, EXEMPTIONS_CHAIN).rules,
+            [
+                Rule(vec![
+                    eq(daddr(false), prefix("192.0.2.0", 24)),
+                    l4("tcp"),
+                    th_dport(Rhs::Range(5000, 5010)),
+                    Expr::Accept,
+                ]),
+                Rule(vec![
+                    eq(daddr(true), prefix("2001:db8:1::", 48)),
+                    Expr::Accept
+                ]),
+                Rule(vec![
+                    eq(daddr(false), prefix("203.0.113.9", 32)),
+                    l4("udp"),
+                    th_dport(Rhs::Num(5060)),
+                    Expr::Accept,
+                ]),
+            ]
+        );
+    }
+
+    #[test]
+    fn build_ruleset_logs_drops_only_when_asked() {
+        let mut p = policy();
+        let has_log = |rs: &Ruleset| {
+            chain(rs, BLOCKED_CHAIN)
+                .rules
+                .iter()
+                .any(|r| r.0.iter().any(|e| matches!(e, Expr::Log(_))))
+        };
+        assert!(!has_log(&build_ruleset(&p)));
+
+        p.log_drops = true;
+        let rs = build_ruleset(&p);
+        assert!(has_log(&rs));
+        assert_eq!(
+            chain(&rs, BLOCKED_CHAIN).rules.last(),
+            Some(&Rule(vec![Expr::Counter, Expr::Drop]))
+        );
+    }
+
+    #[test]
+    fn ipv6_block_ruleset_keeps_loopback() {
+        let rs = ipv6_block_ruleset(None);
+        assert_eq!(rs.table, IPV6_BLOCK_TABLE);
+        assert_eq!(
+            chain(&rs, "output").rules,
+            [
+                Rule(vec![oifname(Rhs::Str("lo".into())), Expr::Accept]),
+                Rule(vec![
+                    eq(Lhs::Meta("nfproto"), Rhs::Str("ipv6".into())),
+                    Expr::Drop,
+                ]),
+            ]
+        );
+    }
+
+    #[test]
+    fn ipv6_block_ruleset_lets_an_ipv6_transport_through() {
+        let p = policy();
+        let v6 = &p.remotes[1];
+        let rules = ipv6_block_ruleset(Some(v6)).chains[0].rules.clone();
+        assert_eq!(rules.len(), 3);
+        assert_eq!(
+            rules[1],
+            Rule(vec![
+                eq(daddr(true), Rhs::Str("2001:db8::7".into())),
+                eq(Lhs::Meta("l4proto"), Rhs::Str("tcp".into())),
+                dport(443),
+                Expr::Accept,
+            ])
+        );
+        assert_eq!(rules[2].0.last(), Some(&Expr::Drop));
+
+        // An IPv4 transport needs no exception.
+        let v4 = &p.remotes[0];
+        assert_eq!(ipv6_block_ruleset(Some(v4)), ipv6_block_ruleset(None));
+    }
+
+    #[test]
+    fn build_policy_takes_the_plan_as_validated() {
+        let plan = KillSwitchPlan {
+            endpoints: vec![Endpoint {
+                ip: "198.51.100.7".parse().unwrap(),
+                proto: EndpointProto::Udp,
+                port: 1194,
+            }],
+            interface: Some("tun0".to_string()),
+            dns: vec!["10.8.0.1".parse().unwrap()],
+            allow_lan: true,
+            log_drops: false,
+            exemptions: vec![KillSwitchExemption {
+                cidr: "192.0.2.77/24".to_string(),
+                proto: None,
+                ports: None,
+            }],
+        };
+        let p = build_policy(plan.clone()).unwrap();
+        assert_eq!(p.remotes, policy().remotes[..1]);
+        assert_eq!(p.tunnel_ifaces, ["tun0"]);
+        assert!(p.allow_lan);
+        // Host bits cleared.
+        assert_eq!(p.exemptions[0].to_string(), "192.0.2.0/24");
+
+        let no_endpoints = KillSwitchPlan {
+            endpoints: vec![],
+            ..plan
+        };
+        assert!(build_policy(no_endpoints).is_err());
+    }
+
+    #[test]
+    fn plan_shows_both_backends_without_touching_the_firewall() {
+        let plan = KillSwitchPlan {
+            endpoints: vec![Endpoint {
+                ip: "198.51.100.7".parse().unwrap(),
+                proto: EndpointProto::Udp,
+                port: 1194,
+            }],
+            interface: None,
+            dns: vec![],
+            allow_lan: false,
+            log_drops: false,
+            exemptions: vec![],
+        };
+        let report = handle(Request::Plan(plan)).unwrap();
+        let commands = report["commands"].as_object().unwrap();
+        assert_eq!(
+            commands.keys().collect::<Vec<_>>(),
+            ["iptables", "nftables"]
+        );
+        assert!(commands["nftables"]["nftables"].is_array());
+        assert!(commands["iptables"]["iptables-restore"].is_string());
     }
 }
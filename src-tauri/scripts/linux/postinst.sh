@@ -3,7 +3,7 @@ set -eu
 
 OPENVPN="/usr/lib/stellar-vpn/openvpn"
 HELPER="/usr/libexec/stellar-vpn/stellar-vpn-helper"
-POLKIT_POLICY="/usr/share/polkit-1/actions/org.stellarsecurity.vpn.desktop.helper.policy"
+DAEMON="/usr/libexec/stellar-vpn/stellar-vpn-daemon"
 
 log() {
   echo "[stellar-vpn] postinst: $*"
@@ -29,39 +29,34 @@ else
   log "WARNING: helper not found at $HELPER"
 fi
 
-# --- Polkit policy (optional) ---
-if [ -f "$POLKIT_POLICY" ]; then
-  chown root:root "$POLKIT_POLICY" || true
-  chmod 0644 "$POLKIT_POLICY" || true
-  log "polkit policy perms set: $POLKIT_POLICY"
+# --- Daemon binary ---
+if [ -f "$DAEMON" ]; then
+  chown root:root "$DAEMON" || true
+  chmod 0755 "$DAEMON" || true
+  log "daemon perms set: $DAEMON"
 else
-  log "NOTE: polkit policy not found at $POLKIT_POLICY"
+  log "WARNING: daemon not found at $DAEMON"
 fi
 
-# --- Capabilities ---
+# --- Capabilities from older versions ---
+# OpenVPN and the helper now run as root under stellar-vpn-daemon.
 if command -v setcap >/dev/null 2>&1; then
-  if [ -f "$OPENVPN" ]; then
-    if setcap cap_net_admin,cap_net_raw+eip "$OPENVPN" 2>/dev/null; then
-      log "setcap applied to $OPENVPN"
-    else
-      log "WARNING: setcap failed on $OPENVPN"
-    fi
-  fi
-
-  if [ -f "$HELPER" ]; then
-    if setcap cap_net_admin+eip "$HELPER" 2>/dev/null; then
-      log "setcap applied to $HELPER"
-    else
-      log "WARNING: setcap failed on $HELPER"
-    fi
-  fi
+  [ -f "$OPENVPN" ] && setcap -r "$OPENVPN" >/dev/null 2>&1 || true
+  [ -f "$HELPER" ]  && setcap -r "$HELPER"  >/dev/null 2>&1 || true
+fi
 
-  if command -v getcap >/dev/null 2>&1; then
-    [ -f "$OPENVPN" ] && log "caps openvpn: $(getcap "$OPENVPN" 2>/dev/null || true)"
-    [ -f "$HELPER" ]  && log "caps helper:  $(getcap "$HELPER" 2>/dev/null || true)"
+# --- systemd ---
+if [ -d /run/systemd/system ]; then
+  systemctl daemon-reload >/dev/null 2>&1 || true
+  # Restart a running daemon so it is the new version.
+  systemctl try-restart stellar-vpn-daemon.service >/dev/null 2>&1 || true
+  if systemctl enable --now stellar-vpn-daemon.socket >/dev/null 2>&1; then
+    log "stellar-vpn-daemon.socket enabled"
+  else
+    log "WARNING: could not enable stellar-vpn-daemon.socket"
   fi
 else
-  log "WARNING: setcap not found (missing libcap tools)."
+  log "WARNING: systemd not running; start $DAEMON as root to use the VPN."
 fi
 
 # --- TUN device check (non-fatal) ---
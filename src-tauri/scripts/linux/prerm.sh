@@ -1,9 +1,6 @@
 #!/bin/sh
 set -eu
 
-OPENVPN="/usr/lib/stellar-vpn/openvpn"
-HELPER="/usr/libexec/stellar-vpn/stellar-vpn-helper"
-
 log() {
   echo "[stellar-vpn] prerm: $*"
 }
@@ -16,11 +13,11 @@ if command -v nft >/dev/null 2>&1; then
   log "killswitch table removed (best-effort)"
 fi
 
-# Remove capabilities (best-effort).
-if command -v setcap >/dev/null 2>&1; then
-  [ -f "$OPENVPN" ] && setcap -r "$OPENVPN" >/dev/null 2>&1 || true
-  [ -f "$HELPER" ]  && setcap -r "$HELPER"  >/dev/null 2>&1 || true
-  log "capabilities removed (best-effort)"
+# Stop the daemon (and with it any OpenVPN it runs).
+if [ -d /run/systemd/system ]; then
+  systemctl disable --now stellar-vpn-daemon.socket >/dev/null 2>&1 || true
+  systemctl stop stellar-vpn-daemon.service >/dev/null 2>&1 || true
+  log "stellar-vpn-daemon stopped"
 fi
 
 log "done."
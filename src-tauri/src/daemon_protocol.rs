@@ -0,0 +1,81 @@
+// src-tauri/src/daemon_protocol.rs
+//
+// JSON-lines protocol of stellar-vpn-daemon, the root service that runs OpenVPN and the kill
+// switch on Linux. Shared by the daemon and the app (included via #[path]).
+//
+// One request line per connection, one response line back. `connect` and `subscribe` then
+// keep the connection open and stream events. Closing a `connect` connection stops that
+// OpenVPN, so a crashed app never leaves behind a tunnel nobody controls. `kill_switch` is
+// answered with the helper's own response (see killswitch_request.rs).
+
+use serde::{Deserialize, Serialize};
+
+use crate::killswitch_request::Request;
+use crate::openvpn_mgmt::MgmtEvent;
+
+/// Created by stellar-vpn-daemon.socket.
+pub const DAEMON_SOCKET: &str = "/run/stellar-vpn/daemon.sock";
+/// Longest request line the daemon reads (a config is sent inline).
+#[allow(dead_code)]
+pub const MAX_REQUEST_BYTES: u64 = 512 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum DaemonReq {
+    /// Start OpenVPN, replacing any previous run. The config is sent as text: the daemon
+    /// never opens files a client names.
+    Connect {
+        config: String,
+        username: String,
+        password: String,
+    },
+    Disconnect,
+    /// Events of every run, for as long as the connection stays open.
+    Subscribe,
+    Status,
+    KillSwitch {
+        request: Request,
+    },
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DaemonResp {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// "disconnected", "connecting" or "connected".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+}
+
+// Daemon side.
+#[allow(dead_code)]
+impl DaemonResp {
+    pub fn ok() -> Self {
+        DaemonResp {
+            ok: true,
+            ..DaemonResp::default()
+        }
+    }
+
+    pub fn err(msg: impl Into<String>) -> Self {
+        DaemonResp {
+            ok: false,
+            error: Some(msg.into()),
+            status: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DaemonEvent {
+    /// OpenVPN output from before the management interface attached, and daemon messages.
+    Log { line: String },
+    /// "disconnected", "connecting" or "connected".
+    Status { status: String },
+    /// A management interface notification. Holds are already released by the daemon.
+    Mgmt { event: MgmtEvent },
+    /// OpenVPN is gone; the last event of a run.
+    Exited { code: i32 },
+}
@@ -0,0 +1,132 @@
+// src-tauri/src/helper_protocol.rs
+//
+// JSON-lines protocol between the app and the macOS root helper. Shared by both (included
+// via #[path] in the helper), so the two sides cannot drift apart.
+//
+// A client opens a connection, sends `hello` and then one request; every request carries an
+// `id` that its response echoes. `subscribe` is answered with a stream of events instead.
+// `hello` tells each side the other's protocol and build version: the app reinstalls a
+// helper that differs from the one it ships (see macos_installer.rs).
+//
+// Example: {"id": 1, "cmd": "hello", "protocol": 1, "app_version": "0.1.7"}
+//       -> {"id": 1, "ok": true, "protocol": 1, "helper_version": "0.1.7"}
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+/// Bumped on any incompatible change to the types below.
+pub const PROTOCOL_VERSION: u32 = 1;
+/// App and helper are built from the same crate, so this is both their versions.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Where the LaunchDaemon's helper listens (root-owned directory, not /tmp).
+pub const HELPER_SOCKET: &str = "/var/run/stellar-vpn/stellar-vpn-helper.sock";
+/// Overrides `HELPER_SOCKET` in the app, e.g. for a helper started by hand.
+pub const SOCKET_ENV: &str = "STELLAR_VPN_HELPER_SOCKET";
+
+/// The app's side of `HELPER_SOCKET`.
+#[allow(dead_code)]
+pub fn socket_path() -> PathBuf {
+    std::env::var_os(SOCKET_ENV)
+        .filter(|p| !p.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(HELPER_SOCKET))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Request {
+    /// Echoed in the response. Clients from before ids existed send none (0).
+    #[serde(default)]
+    pub id: u64,
+    #[serde(flatten)]
+    pub req: Req,
+}
+
+impl Request {
+    #[allow(dead_code)]
+    pub fn hello(id: u64) -> Self {
+        Request {
+            id,
+            req: Req::Hello {
+                protocol: PROTOCOL_VERSION,
+                app_version: VERSION.to_string(),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "cmd", rename_all = "lowercase")]
+pub enum Req {
+    Hello {
+        protocol: u32,
+        app_version: String,
+    },
+    Connect {
+        openvpn: String,
+        config: String,
+        username: String,
+        password: String,
+    },
+    Disconnect,
+    Subscribe,
+    Status,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Resp {
+    /// Id of the request answered; 0 when the helper could not tell (unparsable request).
+    #[serde(default)]
+    pub id: u64,
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    /// Set in answers to `hello`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protocol: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub helper_version: Option<String>,
+}
+
+// Helper side, except `check_hello`.
+#[allow(dead_code)]
+impl Resp {
+    pub fn ok(id: u64) -> Self {
+        Resp {
+            id,
+            ok: true,
+            ..Resp::default()
+        }
+    }
+
+    pub fn err(id: u64, msg: impl Into<String>) -> Self {
+        Resp {
+            id,
+            ok: false,
+            error: Some(msg.into()),
+            ..Resp::default()
+        }
+    }
+
+    /// Whether a `hello` answer comes from a helper this app can talk to. Helpers from
+    /// before the handshake answer it with a parse error and no protocol.
+    pub fn check_hello(&self) -> Result<(), String> {
+        match self.protocol {
+            Some(p) if p == PROTOCOL_VERSION => Ok(()),
+            Some(p) => Err(format!(
+                "The Stellar VPN helper speaks protocol {p}, this app needs {PROTOCOL_VERSION}"
+            )),
+            None => Err("The Stellar VPN helper is outdated (no version handshake)".to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Event {
+    Log { line: String },
+    Status { status: String },
+    Stats { bytes_in: u64, bytes_out: u64 },
+}
@@ -0,0 +1,332 @@
+// src-tauri/src/killswitch_exemption.rs
+//
+// User-defined kill switch exemptions: destinations (CIDR, optionally narrowed to a protocol
+// and port range) that may be reached outside the tunnel while the kill switch is on, e.g.
+// an on-prem license server. Shared by the app, which validates before saving, and the Linux
+// helper, which validates again because its arguments are untrusted (included via #[path]).
+//
+// An exemption must never be able to switch the kill switch off, so prefixes are bounded
+// (at most a /16 for IPv4 and a /48 for IPv6), and so are their number and the address
+// space they cover together.
+
+use std::{fmt, net::IpAddr};
+
+use serde::{Deserialize, Serialize};
+
+pub const MAX_EXEMPTIONS: usize = 32;
+const MIN_PREFIX_V4: u8 = 16;
+const MIN_PREFIX_V6: u8 = 48;
+// Address space all exemptions of a family may cover together: four of the broadest.
+const MAX_SPAN_V4: u128 = 4 << (32 - MIN_PREFIX_V4);
+const MAX_SPAN_V6: u128 = 4 << (128 - MIN_PREFIX_V6);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ExemptProto {
+    Tcp,
+    Udp,
+}
+
+impl ExemptProto {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ExemptProto::Tcp => "tcp",
+            ExemptProto::Udp => "udp",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Exemption {
+    /// Network address, host bits cleared.
+    pub addr: IpAddr,
+    pub prefix: u8,
+    /// None: any protocol, and then any port.
+    pub proto: Option<ExemptProto>,
+    /// Inclusive destination port range; None: any port.
+    pub ports: Option<(u16, u16)>,
+}
+
+impl Exemption {
+    /// `cidr` is `10.1.2.3`, `10.1.2.0/24` or an IPv6 equivalent; `ports` is `5060` or
+    /// `10000-20000` and needs a protocol.
+    pub fn new(cidr: &str, proto: Option<&str>, ports: Option<&str>) -> Result<Self, String> {
+        let cidr = cidr.trim();
+        let (addr, prefix) = match cidr.split_once('/') {
+            Some((a, p)) => (a, Some(p)),
+            None => (cidr, None),
+        };
+        let addr: IpAddr = addr
+            .parse()
+            .map_err(|_| format!("Invalid exemption address: {cidr}"))?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(p) => p
+                .parse::<u8>()
+                .ok()
+                .filter(|p| *p <= max)
+                .ok_or_else(|| format!("Invalid exemption prefix: {cidr}"))?,
+            None => max,
+        };
+
+        let min = if addr.is_ipv4() {
+            MIN_PREFIX_V4
+        } else {
+            MIN_PREFIX_V6
+        };
+        if prefix < min {
+            return Err(format!(
+                "Exemption {cidr} is too broad (at most /{min} for {} is allowed)",
+                if addr.is_ipv4() { "IPv4" } else { "IPv6" }
+            ));
+        }
+        if addr.is_unspecified() || addr.is_loopback() {
+            return Err(format!("Exemption {cidr} is not a remote destination"));
+        }
+
+        let proto = match proto.map(str::trim).filter(|p| !p.is_empty()) {
+            None => None,
+            Some(p) if p.eq_ignore_ascii_case("any") => None,
+            Some(p) if p.eq_ignore_ascii_case("tcp") => Some(ExemptProto::Tcp),
+            Some(p) if p.eq_ignore_ascii_case("udp") => Some(ExemptProto::Udp),
+            Some(p) => return Err(format!("Invalid exemption protocol: {p}")),
+        };
+
+        let ports = match ports.map(str::trim).filter(|p| !p.is_empty()) {
+            None => None,
+            Some(_) if proto.is_none() => {
+                return Err(format!("Exemption {cidr}: a port needs tcp or udp"));
+            }
+            Some(p) => {
+                let (lo, hi) = p.split_once('-').unwrap_or((p, p));
+                let port = |s: &str| s.trim().parse::<u16>().ok().filter(|n| *n > 0);
+                match (port(lo), port(hi)) {
+                    (Some(lo), Some(hi)) if lo <= hi => Some((lo, hi)),
+                    _ => return Err(format!("Invalid exemption port: {p}")),
+                }
+            }
+        };
+
+        Ok(Exemption {
+            addr: mask(addr, prefix),
+            prefix,
+            proto,
+            ports,
+        })
+    }
+
+    pub fn cidr(&self) -> String {
+        format!("{}/{}", self.addr, self.prefix)
+    }
+
+    /// Number of addresses covered.
+    fn span(&self) -> u128 {
+        let bits = if self.addr.is_ipv4() { 32 } else { 128 };
+        1u128 << (bits - u32::from(self.prefix))
+    }
+
+    /// `5060` or `10000-20000`.
+    pub fn ports_str(&self) -> Option<String> {
+        self.ports.map(|(lo, hi)| {
+            if lo == hi {
+                lo.to_string()
+            } else {
+                format!("{lo}-{hi}")
+            }
+        })
+    }
+}
+
+impl fmt::Display for Exemption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.cidr())?;
+        if let Some(p) = self.proto {
+            write!(f, ",{}", p.as_str())?;
+        }
+        if let Some(ports) = self.ports_str() {
+            write!(f, ",{ports}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks that apply to the list as a whole.
+pub fn validate_all(list: &[Exemption]) -> Result<(), String> {
+    if list.len() > MAX_EXEMPTIONS {
+        return Err(format!(
+            "Too many kill switch exemptions ({}, at most {MAX_EXEMPTIONS})",
+            list.len()
+        ));
+    }
+
+    for (v6, max, family) in [(false, MAX_SPAN_V4, "IPv4"), (true, MAX_SPAN_V6, "IPv6")] {
+        let span: u128 = list
+            .iter()
+            .filter(|e| e.addr.is_ipv6() == v6)
+            .map(Exemption::span)
+            .sum();
+        if span > max {
+            return Err(format!(
+                "Kill switch exemptions cover too many {family} addresses (at most four /{} together)",
+                if v6 { MIN_PREFIX_V6 } else { MIN_PREFIX_V4 }
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn mask(addr: IpAddr, prefix: u8) -> IpAddr {
+    match addr {
+        IpAddr::V4(a) => {
+            let bits = u32::from(a) & u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+            IpAddr::V4(bits.into())
+        }
+        IpAddr::V6(a) => {
+            let bits = u128::from(a) & u128::MAX.checked_shl(128 - u32::from(prefix)).unwrap_or(0);
+            IpAddr::V6(bits.into())
+        }
+    }
+}
+
+/// A kill switch exemption as the UI sends it, `vpn-state.json` stores it and the helper
+/// receives it, e.g. `{"cidr": "10.20.0.5", "proto": "udp", "ports": "5060-5061"}`.
+/// Validated into an `Exemption` before use.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KillSwitchExemption {
+    pub cidr: String,
+    /// "tcp", "udp", or none for any protocol.
+    pub proto: Option<String>,
+    /// "5060" or "10000-20000"; needs a protocol.
+    pub ports: Option<String>,
+}
+
+impl KillSwitchExemption {
+    pub fn parse(&self) -> Result<Exemption, String> {
+        Exemption::new(&self.cidr, self.proto.as_deref(), self.ports.as_deref())
+    }
+}
+
+impl From<&Exemption> for KillSwitchExemption {
+    fn from(e: &Exemption) -> Self {
+        KillSwitchExemption {
+            cidr: e.cidr(),
+            proto: e.proto.map(|p| p.as_str().to_string()),
+            ports: e.ports_str(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cidr(s: &str) -> Result<String, String> {
+        Exemption::new(s, None, None).map(|e| e.cidr())
+    }
+
+    #[test]
+    fn prefix_bounds() {
+        assert_eq!(cidr("10.20.0.0/16"), Ok("10.20.0.0/16".to_string()));
+        assert!(cidr("10.20.0.0/15").is_err());
+        assert!(cidr("10.0.0.0/8").is_err());
+        assert!(cidr("0.0.0.0/0").is_err());
+        assert_eq!(cidr("2001:db8:1::/48"), Ok("2001:db8:1::/48".to_string()));
+        assert!(cidr("2001:db8::/47").is_err());
+        assert!(cidr("2000::/3").is_err());
+        assert!(cidr("::/0").is_err());
+        assert!(cidr("10.0.0.1/33").is_err());
+        assert!(cidr("2001:db8::1/129").is_err());
+    }
+
+    #[test]
+    fn bare_address_is_a_host() {
+        assert_eq!(cidr("203.0.113.9"), Ok("203.0.113.9/32".to_string()));
+        assert_eq!(cidr(" 2001:db8::9 "), Ok("2001:db8::9/128".to_string()));
+    }
+
+    #[test]
+    fn host_bits_are_cleared() {
+        assert_eq!(cidr("10.20.30.40/16"), Ok("10.20.0.0/16".to_string()));
+        assert_eq!(cidr("192.0.2.77/24"), Ok("192.0.2.0/24".to_string()));
+        assert_eq!(cidr("192.0.2.77/32"), Ok("192.0.2.77/32".to_string()));
+        assert_eq!(
+            cidr("2001:db8:1:2:3::5/48"),
+            Ok("2001:db8:1::/48".to_string())
+        );
+        assert_eq!(cidr("2001:db8::ff/127"), Ok("2001:db8::fe/127".to_string()));
+    }
+
+    #[test]
+    fn local_addresses_are_rejected() {
+        for s in [
+            "127.0.0.1",
+            "::1",
+            "0.0.0.0",
+            "::",
+            "not-an-ip",
+            "10.0.0.1/x",
+        ] {
+            assert!(cidr(s).is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn protocol_and_ports() {
+        let e = Exemption::new("203.0.113.9", Some("UDP"), Some("5060")).unwrap();
+        assert_eq!(e.to_string(), "203.0.113.9/32,udp,5060");
+        let e = Exemption::new("203.0.113.9", Some("tcp"), Some("10000-20000")).unwrap();
+        assert_eq!(e.ports, Some((10000, 20000)));
+        assert_eq!(e.to_string(), "203.0.113.9/32,tcp,10000-20000");
+        let e = Exemption::new("203.0.113.9", Some("any"), Some("")).unwrap();
+        assert_eq!((e.proto, e.ports), (None, None));
+
+        assert!(Exemption::new("203.0.113.9", None, Some("53")).is_err());
+        assert!(Exemption::new("203.0.113.9", Some("icmp"), None).is_err());
+        for ports in ["0", "20-10", "1-70000", "a-b"] {
+            assert!(
+                Exemption::new("203.0.113.9", Some("tcp"), Some(ports)).is_err(),
+                "{ports}"
+            );
+        }
+    }
+
+    fn list(cidrs: &[&str]) -> Vec<Exemption> {
+        cidrs
+            .iter()
+            .map(|c| Exemption::new(c, None, None).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn total_address_space_is_capped_per_family() {
+        let v4 = ["10.1.0.0/16", "10.2.0.0/16", "10.3.0.0/16", "10.4.0.0/16"];
+        let v6 = [
+            "2001:db8:1::/48",
+            "2001:db8:2::/48",
+            "2001:db8:3::/48",
+            "2001:db8:4::/48",
+        ];
+        assert_eq!(validate_all(&list(&[&v4[..], &v6[..]].concat())), Ok(()));
+
+        let err = validate_all(&list(&[&v4[..], &["10.5.0.0/16"]].concat())).unwrap_err();
+        assert!(err.contains("IPv4"), "{err}");
+        let err = validate_all(&list(&[&v6[..], &["2001:db8:5::/48"]].concat())).unwrap_err();
+        assert!(err.contains("IPv6"), "{err}");
+
+        // Hosts barely count.
+        let mut hosts = list(&v4);
+        hosts.extend(list(&["192.0.2.1", "192.0.2.0/24"]));
+        assert!(validate_all(&hosts).is_err());
+        assert_eq!(validate_all(&hosts[1..]), Ok(()));
+    }
+
+    #[test]
+    fn number_of_exemptions_is_capped() {
+        let hosts: Vec<String> = (1..=MAX_EXEMPTIONS + 1)
+            .map(|i| format!("192.0.2.{i}"))
+            .collect();
+        let hosts: Vec<&str> = hosts.iter().map(String::as_str).collect();
+        assert_eq!(validate_all(&list(&hosts[..MAX_EXEMPTIONS])), Ok(()));
+        assert!(validate_all(&list(&hosts)).is_err());
+    }
+}
@@ -0,0 +1,269 @@
+// src-tauri/src/killswitch_request.rs
+//
+// The Linux kill switch helper's interface: `stellar-vpn-helper request` reads one versioned
+// JSON request on stdin and writes one JSON response to stdout. Shared by the app and the
+// helper (included via #[path]).
+//
+// The helper runs as root under stellar-vpn-daemon for unprivileged clients, so the request
+// is untrusted: unknown fields are rejected and `KillSwitchPlan::validate` runs before any
+// rule is built. The app turns the OpenVPN config into endpoints itself; the helper never
+// reads user files.
+//
+// Example: {"version": 3, "request": {"enable": {"endpoints": [{"ip": "198.51.100.7",
+// "proto": "udp", "port": 1194}], "interface": "tun0", "allow_lan": true}}}
+
+use std::{fmt, net::IpAddr};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::killswitch_exemption::{self, Exemption, KillSwitchExemption};
+
+/// Bumped on any incompatible change to the types below.
+pub const PROTOCOL_VERSION: u32 = 3;
+#[allow(dead_code)]
+pub const MAX_REQUEST_BYTES: u64 = 64 * 1024;
+pub const MAX_ENDPOINTS: usize = 64;
+pub const MAX_DNS: usize = 8;
+
+/// Built by the daemon, read by the helper.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[allow(dead_code)]
+pub struct HelperRequest {
+    pub version: u32,
+    pub request: Request,
+}
+
+impl HelperRequest {
+    #[allow(dead_code)]
+    pub fn new(request: Request) -> Self {
+        HelperRequest {
+            version: PROTOCOL_VERSION,
+            request,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum Request {
+    /// Replace any kill switch rules with this plan.
+    Enable(KillSwitchPlan),
+    /// What `enable` would load, without touching the firewall.
+    Plan(KillSwitchPlan),
+    /// How the live rules differ from what `enable` would load.
+    Verify(KillSwitchPlan),
+    Disable,
+    /// Pin the kill switch to the plan's tunnel (none once OpenVPN closed it) and the
+    /// resolvers the server pushed. The rest of the plan is what `enable` loaded.
+    SetTunnel(KillSwitchPlan),
+    Status,
+    Stats,
+    /// Drop IPv6 egress while the tunnel is IPv4-only.
+    Ipv6 {
+        block: bool,
+        /// The server the connection runs over, which stays reachable when it is an IPv6
+        /// address (IPv6 transport, IPv4-only tunnel).
+        #[serde(default)]
+        remote: Option<Endpoint>,
+    },
+}
+
+/// Everything the kill switch lets out besides loopback and established flows.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KillSwitchPlan {
+    /// VPN servers the handshake may go to, already resolved and filtered by the app.
+    pub endpoints: Vec<Endpoint>,
+    /// Only this tunnel interface may carry traffic (none until OpenVPN opened it).
+    #[serde(default)]
+    pub interface: Option<String>,
+    /// Pushed resolvers, reachable through the tunnel only.
+    #[serde(default)]
+    pub dns: Vec<IpAddr>,
+    #[serde(default)]
+    pub allow_lan: bool,
+    /// Rate-limited kernel log line for every blocked packet.
+    #[serde(default)]
+    pub log_drops: bool,
+    #[serde(default)]
+    pub exemptions: Vec<KillSwitchExemption>,
+}
+
+impl KillSwitchPlan {
+    /// The checks the helper relies on before building rules; returns the parsed exemptions.
+    /// The app runs them too, the daemon leaves them to the helper.
+    #[allow(dead_code)]
+    pub fn validate(&self) -> Result<Vec<Exemption>, String> {
+        if self.endpoints.is_empty() {
+            return Err("No VPN endpoints to allow".to_string());
+        }
+        if self.endpoints.len() > MAX_ENDPOINTS {
+            return Err(format!(
+                "Too many VPN endpoints ({}, at most {MAX_ENDPOINTS})",
+                self.endpoints.len()
+            ));
+        }
+        for e in &self.endpoints {
+            e.validate()?;
+        }
+        validate_tunnel(self.interface.as_deref(), &self.dns)?;
+
+        let exemptions = self
+            .exemptions
+            .iter()
+            .map(KillSwitchExemption::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        killswitch_exemption::validate_all(&exemptions)?;
+        Ok(exemptions)
+    }
+}
+
+/// Checks for the tunnel fields of a plan, which `set_tunnel` changes.
+fn validate_tunnel(interface: Option<&str>, dns: &[IpAddr]) -> Result<(), String> {
+    if let Some(name) = interface {
+        if !is_valid_ifname(name) {
+            return Err(format!("Invalid interface name: {name:?}"));
+        }
+    }
+    if dns.len() > MAX_DNS {
+        return Err(format!(
+            "Too many DNS servers ({}, at most {MAX_DNS})",
+            dns.len()
+        ));
+    }
+    Ok(())
+}
+
+/// Interface names go into firewall rules verbatim, so only accept what the kernel would
+/// (IFNAMSIZ - 1) from a conservative character set.
+pub fn is_valid_ifname(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 15
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Endpoint {
+    pub ip: IpAddr,
+    pub proto: EndpointProto,
+    pub port: u16,
+}
+
+impl Endpoint {
+    #[allow(dead_code)]
+    pub fn validate(&self) -> Result<(), String> {
+        let ip = self.ip;
+        if self.port == 0 || ip.is_unspecified() || ip.is_loopback() || ip.is_multicast() {
+            return Err(format!(
+                "Invalid VPN endpoint: {} {}/{}",
+                ip,
+                self.port,
+                self.proto.as_str()
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EndpointProto {
+    Tcp,
+    Udp,
+}
+
+impl EndpointProto {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EndpointProto::Tcp => "tcp",
+            EndpointProto::Udp => "udp",
+        }
+    }
+}
+
+// ---------------- Response ----------------
+
+/// `result` depends on the request: `{"backend": ..}` for changes, the status, stats, drift
+/// or plan report otherwise.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HelperResponse {
+    pub version: u32,
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub result: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<HelperError>,
+}
+
+impl HelperResponse {
+    /// For the app; the daemon passes responses through.
+    #[allow(dead_code)]
+    pub fn into_result(self) -> Result<Value, HelperError> {
+        match self.error {
+            Some(e) => Err(e),
+            None if self.ok => Ok(self.result),
+            None => Err(HelperError::new(
+                ErrorCode::Unknown,
+                "Helper failed without an error",
+            )),
+        }
+    }
+}
+
+impl From<Result<Value, HelperError>> for HelperResponse {
+    fn from(r: Result<Value, HelperError>) -> Self {
+        let (ok, result, error) = match r {
+            Ok(v) => (true, v, None),
+            Err(e) => (false, Value::Null, Some(e)),
+        };
+        HelperResponse {
+            version: PROTOCOL_VERSION,
+            ok,
+            result,
+            error,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HelperError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl HelperError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        HelperError {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for HelperError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// Not JSON, unknown fields, or a value that failed validation.
+    InvalidRequest,
+    UnsupportedVersion,
+    /// Neither nftables nor iptables is usable.
+    NoFirewall,
+    /// The firewall rejected the change or could not be read.
+    FirewallFailed,
+    /// stellar-vpn-daemon refused the client (see peer_auth.rs).
+    NotAllowed,
+    /// A code from a newer helper.
+    #[serde(other)]
+    Unknown,
+}
@@ -0,0 +1,106 @@
+// src-tauri/src/linux_daemon.rs
+//
+// Linux client for stellar-vpn-daemon (runs inside the Tauri app). Everything that needs
+// root goes through its socket: running OpenVPN and changing the kill switch. See
+// daemon_protocol.rs for the protocol.
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines},
+    net::UnixStream,
+};
+
+use crate::daemon_protocol::{DaemonEvent, DaemonReq, DaemonResp, DAEMON_SOCKET};
+use crate::killswitch_request::{HelperResponse, Request};
+
+type Reader = Lines<BufReader<UnixStream>>;
+
+async fn send(req: &DaemonReq) -> Result<Reader, String> {
+    let mut s = UnixStream::connect(DAEMON_SOCKET).await.map_err(|e| {
+        format!(
+            "Stellar VPN service is not reachable at {DAEMON_SOCKET} ({e}). Is stellar-vpn-daemon.socket enabled?"
+        )
+    })?;
+
+    let mut line = serde_json::to_string(req).map_err(|e| format!("json encode failed: {e}"))?;
+    line.push('\n');
+    s.write_all(line.as_bytes())
+        .await
+        .map_err(|e| format!("socket write failed: {e}"))?;
+    Ok(BufReader::new(s).lines())
+}
+
+async fn read_reply<T: DeserializeOwned>(r: &mut Reader) -> Result<T, String> {
+    let line = r
+        .next_line()
+        .await
+        .map_err(|e| format!("socket read failed: {e}"))?
+        .ok_or("Stellar VPN service closed the connection")?;
+    serde_json::from_str(&line)
+        .map_err(|e| format!("Unexpected reply from Stellar VPN service: {e}"))
+}
+
+/// Run a kill switch request (as root, by stellar-vpn-helper).
+pub async fn kill_switch(request: &Request) -> Result<Value, String> {
+    let mut r = send(&DaemonReq::KillSwitch {
+        request: request.clone(),
+    })
+    .await?;
+    let resp: HelperResponse = read_reply(&mut r).await?;
+    resp.into_result().map_err(|e| e.to_string())
+}
+
+/// Stop the running OpenVPN; returns once it is gone.
+pub async fn disconnect() -> Result<(), String> {
+    let mut r = send(&DaemonReq::Disconnect).await?;
+    let resp: DaemonResp = read_reply(&mut r).await?;
+    if !resp.ok {
+        return Err(format!(
+            "Disconnect failed: {}",
+            resp.error.unwrap_or_else(|| "unknown error".to_string())
+        ));
+    }
+    Ok(())
+}
+
+/// An OpenVPN run started by the daemon. It lasts as long as this connection: dropping it
+/// stops OpenVPN.
+pub struct DaemonRun {
+    events: Reader,
+}
+
+/// Start OpenVPN with `config` (the text, not a path).
+pub async fn connect(
+    config: String,
+    username: String,
+    password: String,
+) -> Result<DaemonRun, String> {
+    let mut r = send(&DaemonReq::Connect {
+        config,
+        username,
+        password,
+    })
+    .await?;
+    let resp: DaemonResp = read_reply(&mut r).await?;
+    if !resp.ok {
+        return Err(format!(
+            "Stellar VPN service could not start OpenVPN: {}",
+            resp.error.unwrap_or_else(|| "unknown error".to_string())
+        ));
+    }
+    Ok(DaemonRun { events: r })
+}
+
+impl DaemonRun {
+    /// None once the daemon closed the connection. Cancel safe.
+    pub async fn next_event(&mut self) -> Option<DaemonEvent> {
+        loop {
+            let line = self.events.next_line().await.ok()??;
+            if line.trim().is_empty() {
+                continue;
+            }
+            return Some(serde_json::from_str(&line).unwrap_or(DaemonEvent::Log { line }));
+        }
+    }
+}
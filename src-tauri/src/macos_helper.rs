@@ -3,91 +3,100 @@
 // macOS client-side helper bridge (runs inside Tauri app):
 // - connects to privileged helper via Unix socket
 // - sends Connect/Disconnect/Subscribe commands
-// - forwards helper log/status events to UI
+// - forwards helper log events to UI and feeds status events into the app state machine
 //
 // IMPORTANT: Use tauri::async_runtime::spawn (NOT tokio::spawn) from sync contexts.
 
 use std::path::PathBuf;
 use std::time::Duration;
 
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
 use tauri::{AppHandle, Emitter, Runtime};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
 
-const HELPER_SOCK: &str = "/tmp/stellar-vpn-helper.sock";
-
-#[derive(Debug, Serialize)]
-#[serde(tag = "cmd", rename_all = "lowercase")]
-enum HelperReq {
-  Connect {
-    openvpn: String,
-    config: String,
-    username: String,
-    password: String,
-  },
-  Disconnect,
-  Subscribe,
-  Status,
-}
+use crate::helper_protocol::{socket_path, Event, Req, Request, Resp};
+use crate::{record_traffic, set_status, SharedState, UiStatus, RT};
 
-#[derive(Debug, Deserialize)]
-struct HelperResp {
-  ok: bool,
-  #[serde(default)]
-  error: Option<String>,
-  #[serde(default)]
-  status: Option<String>,
+fn emit_log<R: Runtime>(app: &AppHandle<R>, line: &str) {
+  let _ = app.emit("vpn-log", line.to_string());
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(tag = "type", rename_all = "lowercase")]
-enum HelperEvent {
-  Log { line: String },
-  Status { status: String },
+/// One connection to the helper, past the `hello` handshake.
+struct HelperConn {
+  reader: BufReader<UnixStream>,
+  next_id: u64,
 }
 
-fn emit_log<RT: Runtime>(app: &AppHandle<RT>, line: &str) {
-  let _ = app.emit("vpn-log", line.to_string());
-}
+impl HelperConn {
+  async fn open() -> Result<Self, String> {
+    let path = socket_path();
+    let s = UnixStream::connect(&path)
+      .await
+      .map_err(|e| format!("Failed to connect to helper socket {}: {e}", path.display()))?;
+    let mut conn = HelperConn {
+      reader: BufReader::new(s),
+      next_id: 1,
+    };
+
+    let hello = Request::hello(conn.next_id);
+    conn.next_id += 1;
+    conn.write_line(&hello).await?;
+    let resp: Resp = conn.read_json_line().await?;
+    resp.check_hello().map_err(|e| {
+      format!("{e}. Reconnect to upgrade it (administrator password required).")
+    })?;
+    Ok(conn)
+  }
 
-fn emit_status<RT: Runtime>(app: &AppHandle<RT>, status: &str) {
-  let _ = app.emit("vpn-status", status.to_string());
-}
+  /// Send `req` without waiting for an answer; returns its id.
+  async fn send(&mut self, req: Req) -> Result<u64, String> {
+    let id = self.next_id;
+    self.next_id += 1;
+    self.write_line(&Request { id, req }).await?;
+    Ok(id)
+  }
 
-async fn write_json_line(stream: &mut tokio::net::UnixStream, v: &impl Serialize) -> Result<(), String> {
-  use tokio::io::AsyncWriteExt;
+  async fn request(&mut self, req: Req) -> Result<Resp, String> {
+    let id = self.send(req).await?;
+    let resp: Resp = self.read_json_line().await?;
+    // 0: the helper could not tell which request this answers (it did not parse).
+    if resp.id != id && resp.id != 0 {
+      return Err(format!("helper answered request {} instead of {id}", resp.id));
+    }
+    Ok(resp)
+  }
 
-  let s = serde_json::to_string(v).map_err(|e| format!("json encode failed: {e}"))?;
-  stream
-    .write_all(format!("{s}\n").as_bytes())
-    .await
-    .map_err(|e| format!("socket write failed: {e}"))?;
-  Ok(())
-}
+  async fn write_line(&mut self, v: &impl Serialize) -> Result<(), String> {
+    let s = serde_json::to_string(v).map_err(|e| format!("json encode failed: {e}"))?;
+    self
+      .reader
+      .get_mut()
+      .write_all(format!("{s}\n").as_bytes())
+      .await
+      .map_err(|e| format!("socket write failed: {e}"))
+  }
 
-async fn read_json_line<T: for<'de> Deserialize<'de>>(stream: &mut tokio::net::UnixStream) -> Result<T, String> {
-  use tokio::io::{AsyncBufReadExt, BufReader};
-
-  let mut reader = BufReader::new(stream);
-  let mut line = String::new();
-  let n = reader
-    .read_line(&mut line)
-    .await
-    .map_err(|e| format!("socket read failed: {e}"))?;
-  if n == 0 {
-    return Err("socket closed".to_string());
+  /// None once the helper closed the connection.
+  async fn read_line(&mut self) -> Result<Option<String>, String> {
+    let mut line = String::new();
+    let n = self
+      .reader
+      .read_line(&mut line)
+      .await
+      .map_err(|e| format!("socket read failed: {e}"))?;
+    Ok((n > 0).then_some(line))
   }
-  serde_json::from_str::<T>(line.trim()).map_err(|e| format!("json decode failed: {e}"))
-}
 
-async fn connect_socket() -> Result<tokio::net::UnixStream, String> {
-  tokio::net::UnixStream::connect(HELPER_SOCK)
-    .await
-    .map_err(|e| format!("Failed to connect to helper socket {HELPER_SOCK}: {e}"))
+  async fn read_json_line<T: serde::de::DeserializeOwned>(&mut self) -> Result<T, String> {
+    let line = self.read_line().await?.ok_or("socket closed")?;
+    serde_json::from_str::<T>(line.trim()).map_err(|e| format!("json decode failed: {e}"))
+  }
 }
 
-pub async fn helper_connect<RT: Runtime>(
+pub async fn helper_connect(
   app: &AppHandle<RT>,
-  _state: &std::sync::Arc<tokio::sync::Mutex<crate::VpnInner>>,
+  _state: &SharedState,
   openvpn_bin: PathBuf,
   cfg_path: PathBuf,
   username: String,
@@ -95,22 +104,15 @@ pub async fn helper_connect<RT: Runtime>(
 ) -> Result<(), String> {
   emit_log(app, "[macos] helper_connect -> using root helper socket");
 
-  let mut s = connect_socket().await?;
-
-  // Send Connect request
-  write_json_line(
-    &mut s,
-    &HelperReq::Connect {
+  let mut conn = HelperConn::open().await?;
+  let resp = conn
+    .request(Req::Connect {
       openvpn: openvpn_bin.to_string_lossy().to_string(),
       config: cfg_path.to_string_lossy().to_string(),
       username,
       password,
-    },
-  )
-  .await?;
-
-  // Read response
-  let resp: HelperResp = read_json_line(&mut s).await?;
+    })
+    .await?;
   if !resp.ok {
     return Err(format!(
       "Helper connect failed: {}",
@@ -121,38 +123,32 @@ pub async fn helper_connect<RT: Runtime>(
   Ok(())
 }
 
-pub async fn helper_disconnect<RT: Runtime>(
-  app: &AppHandle<RT>,
-  _state: &std::sync::Arc<tokio::sync::Mutex<crate::VpnInner>>,
+pub async fn helper_disconnect(
+  _app: &AppHandle<RT>,
+  _state: &SharedState,
 ) -> Result<(), String> {
-  let mut s = connect_socket().await?;
-  write_json_line(&mut s, &HelperReq::Disconnect).await?;
-  let resp: HelperResp = read_json_line(&mut s).await?;
+  let resp = HelperConn::open().await?.request(Req::Disconnect).await?;
   if !resp.ok {
     return Err(format!(
       "Helper disconnect failed: {}",
       resp.error.unwrap_or_else(|| "unknown error".to_string())
     ));
   }
-  emit_status(app, "disconnected");
   Ok(())
 }
 
 /// Spawns a background subscriber that:
 /// - connects to helper
 /// - sends Subscribe
-/// - forwards Event::Log and Event::Status into UI
+/// - forwards Event::Log into UI and Event::Status into the shared status model
 ///
 /// IMPORTANT: this function is called from sync Tauri setup; must use tauri::async_runtime::spawn
-pub fn spawn_helper_subscriber<RT: Runtime>(
-  app: AppHandle<RT>,
-  _state: std::sync::Arc<tokio::sync::Mutex<crate::VpnInner>>,
-) {
+pub fn spawn_helper_subscriber(app: AppHandle<RT>, state: SharedState) {
   tauri::async_runtime::spawn(async move {
     loop {
       // try connect
-      let mut s = match connect_socket().await {
-        Ok(s) => s,
+      let mut conn = match HelperConn::open().await {
+        Ok(c) => c,
         Err(e) => {
           emit_log(&app, &format!("[macos] helper subscribe connect failed: {e}"));
           tokio::time::sleep(Duration::from_millis(800)).await;
@@ -161,39 +157,43 @@ pub fn spawn_helper_subscriber<RT: Runtime>(
       };
 
       // send subscribe
-      if let Err(e) = write_json_line(&mut s, &HelperReq::Subscribe).await {
+      if let Err(e) = conn.send(Req::Subscribe).await {
         emit_log(&app, &format!("[macos] subscribe write failed: {e}"));
         tokio::time::sleep(Duration::from_millis(800)).await;
         continue;
       }
 
       // Now read streaming event lines until it breaks
-      let mut reader = tokio::io::BufReader::new(s);
       loop {
-        let mut line = String::new();
-        let n = match tokio::io::AsyncBufReadExt::read_line(&mut reader, &mut line).await {
-          Ok(n) => n,
+        let line = match conn.read_line().await {
+          Ok(Some(line)) => line,
+          Ok(None) => {
+            emit_log(&app, "[macos] subscribe socket closed");
+            break;
+          }
           Err(e) => {
             emit_log(&app, &format!("[macos] subscribe read failed: {e}"));
             break;
           }
         };
-        if n == 0 {
-          emit_log(&app, "[macos] subscribe socket closed");
-          break;
-        }
 
         let msg = line.trim();
         if msg.is_empty() {
           continue;
         }
 
-        match serde_json::from_str::<HelperEvent>(msg) {
-          Ok(HelperEvent::Log { line }) => {
+        match serde_json::from_str::<Event>(msg) {
+          Ok(Event::Log { line }) => {
             emit_log(&app, &line);
           }
-          Ok(HelperEvent::Status { status }) => {
-            emit_status(&app, &status);
+          Ok(Event::Status { status }) => {
+            match UiStatus::from_str(&status) {
+              Some(st) => set_status(&state, &app, st).await,
+              None => emit_log(&app, &format!("[macos] unknown helper status: {status}")),
+            }
+          }
+          Ok(Event::Stats { bytes_in, bytes_out }) => {
+            record_traffic(&state, &app, bytes_in, bytes_out).await;
           }
           Err(_) => {
             // If helper prints plain text, forward it as log.
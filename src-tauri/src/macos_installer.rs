@@ -5,6 +5,8 @@
 
 use std::{
     fs,
+    io::{BufRead, BufReader, Write},
+    os::unix::net::UnixStream,
     path::{Path, PathBuf},
     process::Command,
     thread,
@@ -13,6 +15,10 @@ use std::{
 
 use tauri::{path::BaseDirectory, AppHandle, Manager, Runtime};
 
+use crate::helper_protocol::{
+    socket_path, Request, Resp, HELPER_SOCKET, PROTOCOL_VERSION, VERSION,
+};
+
 const LABEL: &str = "org.stellarsecurity.vpn.helper";
 
 // Where the root helper binary must live (LaunchDaemon-safe location)
@@ -21,9 +27,6 @@ const HELPER_INSTALL_PATH: &str = "/Library/PrivilegedHelperTools/stellar-vpn-he
 // LaunchDaemon plist location
 const DAEMON_PLIST_PATH: &str = "/Library/LaunchDaemons/org.stellarsecurity.vpn.helper.plist";
 
-// Socket path used by your helper
-pub const SOCKET_PATH: &str = "/tmp/stellar-vpn-helper.sock";
-
 // Helper logs (optional but extremely useful for debugging)
 const STDOUT_LOG: &str = "/var/log/stellar-vpn-helper.log";
 const STDERR_LOG: &str = "/var/log/stellar-vpn-helper.err.log";
@@ -34,23 +37,89 @@ const STDERR_LOG: &str = "/var/log/stellar-vpn-helper.err.log";
 ///   - /Library/PrivilegedHelperTools/stellar-vpn-helper-macos
 ///   - /Library/LaunchDaemons/org.stellarsecurity.vpn.helper.plist
 /// - Bootstraps LaunchDaemon and kickstarts it.
-/// - Waits briefly for the socket to appear.
+/// - Waits briefly for the new helper to answer.
+///
+/// A running helper from another app version is replaced. If the user declines that, an older
+/// helper that still speaks our protocol is kept.
 pub fn ensure_root_helper_installed<RT: Runtime>(app: &AppHandle<RT>) -> Result<(), String> {
-    // 1) Find the helper binary packaged with the app
+    // 1) Ask the running helper (if any) which version it is
+    let outdated = match installed_helper() {
+        Installed::Current => return Ok(()),
+        Installed::Missing => None,
+        Installed::Outdated { protocol, what } => Some((protocol, what)),
+    };
+
+    // 2) Find the helper binary packaged with the app
     let helper_src = resolve_packaged_helper(app)?;
 
-    // 2) If already running (socket exists), we are done
-    if Path::new(SOCKET_PATH).exists() {
-        return Ok(());
+    // 3) Install/update files + start daemon (admin prompt)
+    if let Err(e) = install_or_update_files(&helper_src) {
+        return match outdated {
+            Some((Some(PROTOCOL_VERSION), _)) => Ok(()),
+            Some((_, what)) => Err(format!(
+                "{what}, and upgrading it failed: {e}. Reinstall Stellar VPN or try connecting again."
+            )),
+            None => Err(e),
+        };
     }
 
-    // 3) Install/update files + start daemon (admin prompt)
-    install_or_update_files(&helper_src)?;
+    // 4) Wait for the new helper to answer
+    wait_for_helper(Duration::from_secs(4))
+}
 
-    // 4) Wait for socket (daemon should create it)
-    wait_for_socket(Duration::from_secs(4))?;
+enum Installed {
+    /// Nothing answers on the socket.
+    Missing,
+    /// A helper answers, but not the one shipped with this app.
+    Outdated {
+        protocol: Option<u32>,
+        what: String,
+    },
+    Current,
+}
 
-    Ok(())
+fn installed_helper() -> Installed {
+    let resp = match helper_hello() {
+        Ok(resp) => resp,
+        Err(_) => return Installed::Missing,
+    };
+    if let Err(what) = resp.check_hello() {
+        return Installed::Outdated {
+            protocol: resp.protocol,
+            what,
+        };
+    }
+    match resp.helper_version.as_deref() {
+        Some(VERSION) => Installed::Current,
+        v => Installed::Outdated {
+            protocol: resp.protocol,
+            what: format!(
+                "The Stellar VPN helper is version {}, this app is {VERSION}",
+                v.unwrap_or("unknown")
+            ),
+        },
+    }
+}
+
+/// Send `hello` to the helper and return its answer.
+fn helper_hello() -> Result<Resp, String> {
+    let path = socket_path();
+    let mut s = UnixStream::connect(&path)
+        .map_err(|e| format!("connect {} failed: {e}", path.display()))?;
+    s.set_read_timeout(Some(Duration::from_secs(2)))
+        .map_err(|e| format!("set timeout failed: {e}"))?;
+
+    let mut line = serde_json::to_string(&Request::hello(1))
+        .map_err(|e| format!("json encode failed: {e}"))?;
+    line.push('\n');
+    s.write_all(line.as_bytes())
+        .map_err(|e| format!("socket write failed: {e}"))?;
+
+    let mut reply = String::new();
+    BufReader::new(s)
+        .read_line(&mut reply)
+        .map_err(|e| format!("socket read failed: {e}"))?;
+    serde_json::from_str(reply.trim()).map_err(|e| format!("json decode failed: {e}"))
 }
 
 /// Resolve helper binary shipped with the app.
@@ -115,7 +184,7 @@ fn build_plist() -> String {
 "#,
         label = LABEL,
         helper = HELPER_INSTALL_PATH,
-        socket = SOCKET_PATH,
+        socket = HELPER_SOCKET,
         stdout = STDOUT_LOG,
         stderr = STDERR_LOG
     )
@@ -179,19 +248,26 @@ exit 0
     run_admin(&cmd)
 }
 
-/// Wait for the helper socket to appear.
-fn wait_for_socket(timeout: Duration) -> Result<(), String> {
+/// Wait for the freshly installed helper to answer `hello`.
+fn wait_for_helper(timeout: Duration) -> Result<(), String> {
     let start = Instant::now();
-    while start.elapsed() < timeout {
-        if Path::new(SOCKET_PATH).exists() {
-            return Ok(());
+    loop {
+        match installed_helper() {
+            Installed::Current => return Ok(()),
+            Installed::Outdated { what, .. } if start.elapsed() >= timeout => {
+                return Err(format!(
+                    "{what} even after upgrading it. Check {STDERR_LOG}."
+                ));
+            }
+            Installed::Missing if start.elapsed() >= timeout => {
+                return Err(format!(
+                    "Helper did not start listening at {}. Check launchd logs and {STDERR_LOG}.",
+                    socket_path().display()
+                ));
+            }
+            _ => thread::sleep(Duration::from_millis(120)),
         }
-        thread::sleep(Duration::from_millis(120));
     }
-
-    Err(format!(
-        "Helper did not create socket at {SOCKET_PATH}. Check launchd logs and {STDERR_LOG}."
-    ))
 }
 
 /// Run an elevated shell script through AppleScript.
@@ -249,7 +325,7 @@ exit 0
         label = LABEL,
         plist_path = DAEMON_PLIST_PATH,
         helper_path = HELPER_INSTALL_PATH,
-        socket_path = SOCKET_PATH
+        socket_path = HELPER_SOCKET
     );
 
     run_admin(&cmd)
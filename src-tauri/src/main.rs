@@ -1,67 +1,271 @@
 // src-tauri/src/main.rs
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+#[cfg(target_os = "macos")]
+mod helper_protocol;
+
 #[cfg(target_os = "macos")]
 mod macos_helper;
 
 #[cfg(target_os = "macos")]
 mod macos_installer;
 
+#[cfg(target_os = "linux")]
+mod netmon;
+
+#[cfg(target_os = "linux")]
+mod daemon_protocol;
+
+#[cfg(target_os = "linux")]
+mod linux_daemon;
+
+mod killswitch_exemption;
+#[cfg(target_os = "linux")]
+mod killswitch_request;
+mod openvpn_config;
+mod openvpn_mgmt;
+
+use killswitch_exemption::{Exemption, KillSwitchExemption};
+#[cfg(target_os = "linux")]
+use killswitch_request::{Endpoint, EndpointProto, KillSwitchPlan, Request};
+use openvpn_config::OpenVpnConfig;
 use tauri::Wry;
 type RT = Wry;
 
 use std::{
+    collections::HashMap,
     fs,
+    net::IpAddr,
     path::{Path, PathBuf},
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
+#[cfg(not(target_os = "linux"))]
+use tauri::path::BaseDirectory;
 use tauri::{
     image::Image,
     menu::{Menu, MenuItem},
-    path::BaseDirectory,
     tray::{TrayIconBuilder, TrayIconEvent},
     AppHandle, Emitter, Manager, WindowEvent,
 };
 
-use tokio::{
-    io::{AsyncBufReadExt, BufReader},
-    process::Command,
-    sync::Mutex,
-    time,
-};
+#[cfg(not(target_os = "linux"))]
+use tokio::io::{AsyncBufReadExt, BufReader};
+#[cfg(not(target_os = "linux"))]
+use tokio::process::Command;
+use tokio::{sync::Mutex, time};
+
+#[cfg(target_os = "linux")]
+use daemon_protocol::DaemonEvent;
+#[cfg(not(target_os = "linux"))]
+use openvpn_mgmt::MgmtClient;
+use openvpn_mgmt::MgmtEvent;
 
 type SharedState = std::sync::Arc<Mutex<VpnInner>>;
 
 const CONNECT_WATCHDOG_MS: u64 = 10_000;
+#[cfg(not(target_os = "linux"))]
+const MGMT_CONNECT_TIMEOUT_MS: u64 = 5_000;
+#[cfg(not(target_os = "linux"))]
+const MGMT_BYTECOUNT_SECS: u32 = 1;
+const STOP_GRACE_MS: u64 = 3_000;
+const RESOLVE_TIMEOUT_MS: u64 = 5_000;
+const RECONNECT_MAX_ATTEMPTS: u32 = 6;
+const RECONNECT_BASE_DELAY_MS: u64 = 1_000;
+const RECONNECT_MAX_DELAY_MS: u64 = 30_000;
+#[cfg(target_os = "linux")]
+const KILL_SWITCH_WATCHDOG_SECS: u64 = 10;
 const TRAY_ID: &str = "stellar-vpn-tray";
 
 const TRAY_ICON_OFFLINE_BYTES: &[u8] = include_bytes!("../icons/tray-offline.png");
 const TRAY_ICON_ONLINE_BYTES: &[u8] = include_bytes!("../icons/tray-online.png");
 
-#[cfg(target_os = "linux")]
-const LINUX_HELPER_PATH: &str = "/usr/libexec/stellar-vpn/stellar-vpn-helper";
-
-// ✅ NEW: fixed socket path (do NOT use /tmp on macOS for root daemon socket)
-#[cfg(target_os = "macos")]
-const MACOS_HELPER_SOCKET: &str = "/var/run/stellar-vpn/stellar-vpn-helper.sock";
-
 // --- Status exposed to UI ---
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 enum UiStatus {
     Disconnected,
+    Resolving,
     Connecting,
+    Authenticating,
+    GettingConfig,
+    AssigningIp,
     Connected,
+    Reconnecting,
+    Disconnecting,
+    Failed(String),
 }
 
 impl UiStatus {
     fn as_str(&self) -> &'static str {
         match self {
             UiStatus::Disconnected => "disconnected",
+            UiStatus::Resolving => "resolving",
             UiStatus::Connecting => "connecting",
+            UiStatus::Authenticating => "authenticating",
+            UiStatus::GettingConfig => "getting_config",
+            UiStatus::AssigningIp => "assigning_ip",
             UiStatus::Connected => "connected",
+            UiStatus::Reconnecting => "reconnecting",
+            UiStatus::Disconnecting => "disconnecting",
+            UiStatus::Failed(_) => "failed",
         }
     }
+
+    /// Inverse of `as_str` for the non-failure states (OpenVPN / helper state names).
+    fn from_str(s: &str) -> Option<UiStatus> {
+        Some(match s {
+            "disconnected" => UiStatus::Disconnected,
+            "resolving" => UiStatus::Resolving,
+            "connecting" => UiStatus::Connecting,
+            "authenticating" => UiStatus::Authenticating,
+            "getting_config" => UiStatus::GettingConfig,
+            "assigning_ip" => UiStatus::AssigningIp,
+            "connected" => UiStatus::Connected,
+            "reconnecting" => UiStatus::Reconnecting,
+            "disconnecting" => UiStatus::Disconnecting,
+            _ => return None,
+        })
+    }
+
+    /// Human-readable label (tray tooltip, UI subtitle).
+    fn label(&self) -> &'static str {
+        match self {
+            UiStatus::Disconnected => "Disconnected",
+            UiStatus::Resolving => "Resolving server…",
+            UiStatus::Connecting => "Connecting…",
+            UiStatus::Authenticating => "Authenticating…",
+            UiStatus::GettingConfig => "Getting configuration…",
+            UiStatus::AssigningIp => "Assigning IP address…",
+            UiStatus::Connected => "Connected",
+            UiStatus::Reconnecting => "Reconnecting…",
+            UiStatus::Disconnecting => "Disconnecting…",
+            UiStatus::Failed(_) => "Connection failed",
+        }
+    }
+
+    /// Coarse disconnected/connecting/connected view of the state.
+    fn coarse(&self) -> &'static str {
+        match self {
+            UiStatus::Connected => "connected",
+            UiStatus::Disconnected | UiStatus::Disconnecting | UiStatus::Failed(_) => {
+                "disconnected"
+            }
+            _ => "connecting",
+        }
+    }
+
+    /// No tunnel and nothing in progress.
+    fn is_idle(&self) -> bool {
+        matches!(self, UiStatus::Disconnected | UiStatus::Failed(_))
+    }
+}
+
+/// Who we are (trying to be) connected to.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+struct ServerInfo {
+    /// Config URL or local path the session was started from.
+    config_source: Option<String>,
+    remote_ip: Option<String>,
+    remote_port: Option<u16>,
+    /// Tunnel IP assigned by the server.
+    local_ip: Option<String>,
+    /// Tunnel IPv6 address, if the server pushed one.
+    local_ipv6: Option<String>,
+    /// IPv6 handling once connected: "tunnel" or "blocked" (IPv4-only tunnel, IPv6 egress
+    /// dropped). A session whose IPv6 cannot be blocked is ended instead.
+    ipv6: Option<&'static str>,
+}
+
+/// Payload of the `vpn-status` event and the `vpn_status` command.
+#[derive(Debug, Clone, serde::Serialize)]
+struct StatusPayload {
+    state: &'static str,
+    /// Coarse disconnected/connecting/connected value.
+    status: &'static str,
+    label: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+    sid: Option<u64>,
+    server: Option<ServerInfo>,
+    /// When the current state was entered (unix ms).
+    since_ms: u64,
+    ts_ms: u64,
+}
+
+/// Payload of the `vpn-stats` event and the `vpn_stats` command.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+struct TrafficStats {
+    sid: Option<u64>,
+    bytes_in: u64,
+    bytes_out: u64,
+    /// Bytes per second over the last sample interval.
+    rate_in: u64,
+    rate_out: u64,
+    ts_ms: u64,
+    #[serde(skip)]
+    sampled_at: Option<time::Instant>,
+}
+
+/// Result of the `vpn_kill_switch_stats` command: what the kill switch has blocked, as
+/// reported by the helper's `stats` request.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+struct KillSwitchStats {
+    /// "nftables" or "iptables".
+    backend: String,
+    active: bool,
+    packets: u64,
+    bytes: u64,
+    /// Distinct destination/protocol/port combinations blocked in the last hour (not
+    /// available with iptables).
+    connections: Option<u64>,
+    /// Most blocked first.
+    top_destinations: Vec<BlockedDestination>,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+struct BlockedDestination {
+    address: String,
+    proto: String,
+    port: u16,
+    packets: u64,
+    bytes: u64,
+}
+
+/// Settings that must survive an app restart (`vpn-state.json` in the app config dir).
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+struct PersistedState {
+    kill_switch_enabled: bool,
+    kill_switch_allow_lan: bool,
+    kill_switch_exemptions: Vec<KillSwitchExemption>,
+    last_config_source: Option<String>,
+    last_config_path: Option<String>,
+}
+
+fn parse_exemptions(list: &[KillSwitchExemption]) -> Result<Vec<Exemption>, String> {
+    let parsed = list
+        .iter()
+        .map(KillSwitchExemption::parse)
+        .collect::<Result<Vec<_>, _>>()?;
+    killswitch_exemption::validate_all(&parsed)?;
+    Ok(parsed)
+}
+
+/// Kill switch settings beyond the config its VPN remotes come from.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+struct KillSwitchOpts {
+    allow_lan: bool,
+    /// Only this tunnel interface may carry traffic (none until OpenVPN opened it).
+    tun_dev: Option<String>,
+    /// Resolvers pushed by the server; the only DNS destinations allowed.
+    dns: Vec<IpAddr>,
+    /// Remote hostnames resolved by the app; the helper does no DNS itself.
+    resolved: Vec<(String, IpAddr)>,
+    /// User-defined destinations reachable outside the tunnel.
+    exemptions: Vec<Exemption>,
 }
 
 #[derive(Debug)]
@@ -73,8 +277,32 @@ struct Session {
 #[derive(Debug)]
 struct VpnInner {
     status: UiStatus,
+    status_since_ms: u64,
+    // Session the current status belongs to, and the server it targets.
+    status_sid: Option<u64>,
+    server: Option<ServerInfo>,
+    stats: TrafficStats,
     session: Option<Session>,
     kill_switch_enabled: bool,
+    // Exempt local network destinations from the kill switch.
+    kill_switch_allow_lan: bool,
+    // User-defined exemptions (validated).
+    kill_switch_exemptions: Vec<Exemption>,
+    // The firewall could not be asked at startup (daemon unreachable); kill_switch_enabled
+    // is then only the saved setting.
+    kill_switch_unknown: bool,
+    // Held while turning the kill switch on/off or re-applying it, so the watchdog never
+    // races a user toggle.
+    kill_switch_op: std::sync::Arc<Mutex<()>>,
+    // Tunnel device opened by the current session's OpenVPN (e.g. tun0), and the
+    // resolvers the server pushed.
+    tun_dev: Option<String>,
+    tun_dns: Vec<IpAddr>,
+    // Last successful lookups of config remote hostnames (DNS is blocked while the kill
+    // switch is on and the tunnel is down).
+    resolved_remotes: HashMap<String, Vec<IpAddr>>,
+    // IPv6 egress is blocked for an IPv4-only tunnel (linux helper, separate nft table).
+    ipv6_blocked: bool,
     disconnect_requested: bool,
     next_sid: u64,
 
@@ -88,8 +316,20 @@ impl Default for VpnInner {
     fn default() -> Self {
         Self {
             status: UiStatus::Disconnected,
+            status_since_ms: now_ms(),
+            status_sid: None,
+            server: None,
+            stats: TrafficStats::default(),
             session: None,
             kill_switch_enabled: false,
+            kill_switch_allow_lan: false,
+            kill_switch_exemptions: Vec::new(),
+            kill_switch_unknown: false,
+            kill_switch_op: std::sync::Arc::new(Mutex::new(())),
+            tun_dev: None,
+            tun_dns: Vec::new(),
+            resolved_remotes: HashMap::new(),
+            ipv6_blocked: false,
             disconnect_requested: false,
             next_sid: 1,
             last_config_path: None,
@@ -100,15 +340,82 @@ impl Default for VpnInner {
 
 // ---------------- UI Emits ----------------
 
-fn emit_status(app: &AppHandle<RT>, s: &str) {
-    let _ = app.emit("vpn-status", s.to_string());
+impl VpnInner {
+    fn kill_switch_opts(&self) -> KillSwitchOpts {
+        KillSwitchOpts {
+            allow_lan: self.kill_switch_allow_lan,
+            exemptions: self.kill_switch_exemptions.clone(),
+            tun_dev: self.tun_dev.clone(),
+            dns: self.tun_dns.clone(),
+            resolved: self
+                .resolved_remotes
+                .iter()
+                .flat_map(|(host, ips)| ips.iter().map(move |ip| (host.clone(), *ip)))
+                .collect(),
+        }
+    }
+
+    fn status_payload(&self) -> StatusPayload {
+        StatusPayload {
+            state: self.status.as_str(),
+            status: self.status.coarse(),
+            label: self.status.label(),
+            reason: match &self.status {
+                UiStatus::Failed(r) => Some(r.clone()),
+                _ => None,
+            },
+            sid: self.status_sid,
+            server: self.server.clone(),
+            since_ms: self.status_since_ms,
+            ts_ms: now_ms(),
+        }
+    }
+}
+
+fn emit_status(app: &AppHandle<RT>, payload: &StatusPayload) {
+    let _ = app.emit("vpn-status", payload.clone());
+}
+
+fn emit_stats(app: &AppHandle<RT>, stats: &TrafficStats) {
+    let _ = app.emit("vpn-stats", stats.clone());
+}
+
+/// Kill switch state as the UI shows it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+enum KillSwitchState {
+    On,
+    Off,
+    /// Whether rules are loaded could not be checked.
+    Unknown,
+}
+
+impl KillSwitchState {
+    fn of(g: &VpnInner) -> Self {
+        match (g.kill_switch_unknown, g.kill_switch_enabled) {
+            (true, _) => KillSwitchState::Unknown,
+            (false, true) => KillSwitchState::On,
+            (false, false) => KillSwitchState::Off,
+        }
+    }
+}
+
+fn emit_kill_switch(app: &AppHandle<RT>, st: KillSwitchState) {
+    let _ = app.emit("vpn-kill-switch-state", st);
+    if st != KillSwitchState::Unknown {
+        let _ = app.emit("vpn-kill-switch", st == KillSwitchState::On);
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn emit_kill_switch_warning(app: &AppHandle<RT>, msg: &str) {
+    let _ = app.emit("vpn-kill-switch-warning", msg.to_string());
 }
 
 fn emit_log(app: &AppHandle<RT>, line: &str) {
     let _ = app.emit("vpn-log", line.to_string());
 }
 
-#[allow(dead_code)]
 fn now_ms() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -125,25 +432,26 @@ struct TrayHandles {
     disconnect: MenuItem<RT>,
 }
 
-fn tray_icon_for_status(st: UiStatus) -> Option<Image<'static>> {
+fn tray_icon_for_status(st: &UiStatus) -> Option<Image<'static>> {
     let bytes = match st {
         UiStatus::Connected => TRAY_ICON_ONLINE_BYTES,
-        UiStatus::Connecting | UiStatus::Disconnected => TRAY_ICON_OFFLINE_BYTES,
+        _ => TRAY_ICON_OFFLINE_BYTES,
     };
     Image::from_bytes(bytes).ok()
 }
 
-fn update_tray_ui_inner(app: &AppHandle<RT>, st: UiStatus) {
+fn update_tray_ui_inner(app: &AppHandle<RT>, st: &UiStatus) {
     let handles = app.state::<TrayHandles>();
 
-    let can_connect = st == UiStatus::Disconnected;
-    let can_disconnect = st != UiStatus::Disconnected;
+    let can_connect = st.is_idle();
+    let can_disconnect = !st.is_idle();
 
     let _ = handles.connect.set_enabled(can_connect);
     let _ = handles.reconnect.set_enabled(can_connect);
     let _ = handles.disconnect.set_enabled(can_disconnect);
 
     if let Some(tray) = app.tray_by_id(TRAY_ID) {
+        let _ = tray.set_tooltip(Some(format!("Stellar VPN: {}", st.label())));
         if let Some(img) = tray_icon_for_status(st) {
             // Linux tray caching is annoying; poke None -> Some
             let _ = tray.set_icon(None);
@@ -157,14 +465,14 @@ fn update_tray_ui_inner(app: &AppHandle<RT>, st: UiStatus) {
 fn update_tray_ui(app: &AppHandle<RT>, st: UiStatus) {
     let app_for_call = app.clone();
     let app_for_closure = app.clone();
-    let st_copy = st;
+    let st_copy = st.clone();
 
     let res = app_for_call.run_on_main_thread(move || {
-        update_tray_ui_inner(&app_for_closure, st_copy);
+        update_tray_ui_inner(&app_for_closure, &st_copy);
     });
 
     if res.is_err() {
-        update_tray_ui_inner(app, st);
+        update_tray_ui_inner(app, &st);
     }
 }
 
@@ -253,6 +561,7 @@ fn ensure_temp_dir() -> Result<(), String> {
     Ok(())
 }
 
+#[cfg(not(target_os = "linux"))]
 fn write_auth_file(username: &str, password: &str, sid: u64) -> Result<PathBuf, String> {
     ensure_temp_dir()?;
     let p = temp_dir().join(format!("auth-{sid}.txt"));
@@ -269,6 +578,87 @@ fn write_auth_file(username: &str, password: &str, sid: u64) -> Result<PathBuf,
     Ok(p)
 }
 
+/// Resolve the config's remote hostnames here rather than leaving DNS open in the kill
+/// switch. A failed lookup keeps the previous answer, which is what we need while the kill
+/// switch itself is blocking DNS.
+async fn resolve_config_remotes(app: &AppHandle<RT>, state: &SharedState, cfg_path: &Path) {
+    let Ok(text) = tokio::fs::read_to_string(cfg_path).await else {
+        return;
+    };
+    let Ok(cfg) = OpenVpnConfig::parse(&text) else {
+        return;
+    };
+
+    let mut remotes = cfg.remotes();
+    remotes.retain(|r| r.host.parse::<IpAddr>().is_err());
+    remotes.dedup_by(|a, b| a.host == b.host);
+
+    for openvpn_config::Remote { host, port, .. } in remotes {
+        let lookup = time::timeout(
+            Duration::from_millis(RESOLVE_TIMEOUT_MS),
+            tokio::net::lookup_host((host.as_str(), port)),
+        )
+        .await
+        .map(|r| r.map(|addrs| addrs.map(|a| a.ip()).collect::<Vec<IpAddr>>()));
+        let err = match lookup {
+            Ok(Ok(mut ips)) => {
+                // Both families, in the resolver's preference order.
+                let mut seen = std::collections::HashSet::new();
+                ips.retain(|ip| seen.insert(*ip));
+                if !ips.is_empty() {
+                    state.lock().await.resolved_remotes.insert(host, ips);
+                    continue;
+                }
+                "no addresses".to_string()
+            }
+            Ok(Err(e)) => e.to_string(),
+            Err(_) => "timed out".to_string(),
+        };
+
+        let cached = state.lock().await.resolved_remotes.contains_key(&host);
+        let note = if cached {
+            "using the last known addresses"
+        } else {
+            "it will not be reachable with the kill switch on"
+        };
+        emit_log(
+            app,
+            &format!("[ui] Could not resolve {host} ({err}); {note}."),
+        );
+    }
+}
+
+/// Hand OpenVPN a copy of the config whose remotes point at the addresses resolved by
+/// `resolve_config_remotes`, the same list the kill switch allows, so the two always agree
+/// (and OpenVPN needs no DNS, which the kill switch blocks outside the tunnel).
+async fn write_pinned_config(
+    state: &SharedState,
+    cfg_path: &Path,
+    sid: u64,
+) -> Result<Option<PathBuf>, String> {
+    let resolved = { state.lock().await.resolved_remotes.clone() };
+
+    let text = tokio::fs::read_to_string(cfg_path)
+        .await
+        .map_err(|e| format!("Failed to read config: {e}"))?;
+    let mut cfg = OpenVpnConfig::parse(&text)?;
+    if !cfg.pin_remotes(|host| resolved.get(host).cloned()) {
+        return Ok(None);
+    }
+
+    ensure_temp_dir()?;
+    let p = temp_dir().join(format!("pinned-{sid}.ovpn"));
+    fs::write(&p, cfg.to_string()).map_err(|e| format!("Failed to write pinned config: {e}"))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let _ = fs::set_permissions(&p, fs::Permissions::from_mode(0o600));
+    }
+
+    Ok(Some(p))
+}
+
 async fn download_to_file(url: &str, sid: u64) -> Result<PathBuf, String> {
     ensure_temp_dir()?;
     let out = temp_dir().join(format!("config-{sid}.ovpn"));
@@ -311,22 +701,53 @@ fn looks_like_url(s: &str) -> bool {
     s.starts_with("http://") || s.starts_with("https://")
 }
 
+/// Local files are used through a private copy, so the config the kill switch was planned
+/// from cannot change under a running session.
+fn copy_config_to_temp(src: &Path, sid: u64) -> Result<PathBuf, String> {
+    if src.parent() == Some(temp_dir().as_path()) {
+        return Ok(src.to_path_buf());
+    }
+
+    ensure_temp_dir()?;
+    let out = temp_dir().join(format!("config-{sid}.ovpn"));
+    fs::copy(src, &out).map_err(|e| format!("Failed to copy config file: {e}"))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let _ = fs::set_permissions(&out, fs::Permissions::from_mode(0o600));
+    }
+
+    Ok(out)
+}
+
 async fn prepare_config(config_path: &str, sid: u64) -> Result<PathBuf, String> {
-    if looks_like_url(config_path) {
-        download_to_file(config_path, sid).await
+    let p = if looks_like_url(config_path) {
+        download_to_file(config_path, sid).await?
     } else {
         let p = PathBuf::from(config_path);
         if !p.exists() {
             return Err(format!("Config file not found: {}", p.display()));
         }
-        Ok(p)
-    }
+        copy_config_to_temp(&p, sid)?
+    };
+
+    let text = tokio::fs::read_to_string(&p)
+        .await
+        .map_err(|e| format!("Failed to read config: {e}"))?;
+    OpenVpnConfig::parse(&text)
+        .and_then(|c| {
+            c.check_allowed()?;
+            c.validate()
+        })
+        .map_err(|e| format!("Invalid OpenVPN config: {e}"))?;
+
+    Ok(p)
 }
 
 // ---------------- OpenVPN binary resolution ----------------
+// Not on Linux: stellar-vpn-daemon runs the packaged /usr/lib/stellar-vpn/openvpn.
 
-#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
-const OPENVPN_REL: &str = "bin/openvpn-x86_64-unknown-linux-gnu";
 #[cfg(all(target_os = "windows", target_arch = "x86_64"))]
 const OPENVPN_REL: &str = "bin/openvpn-x86_64-pc-windows-msvc.exe";
 #[cfg(all(target_os = "macos", target_arch = "aarch64"))]
@@ -335,22 +756,15 @@ const OPENVPN_REL: &str = "bin/openvpn-aarch64-apple-darwin";
 const OPENVPN_REL: &str = "bin/openvpn-x86_64-apple-darwin";
 
 #[cfg(not(any(
-    all(target_os = "linux", target_arch = "x86_64"),
+    target_os = "linux",
     all(target_os = "windows", target_arch = "x86_64"),
     all(target_os = "macos", target_arch = "aarch64"),
     all(target_os = "macos", target_arch = "x86_64"),
 )))]
 const OPENVPN_REL: &str = "openvpn";
 
+#[cfg(not(target_os = "linux"))]
 fn resolve_openvpn_binary(app: &AppHandle<RT>) -> Result<PathBuf, String> {
-    #[cfg(target_os = "linux")]
-    {
-        let installed = PathBuf::from("/usr/lib/stellar-vpn/openvpn");
-        if installed.exists() {
-            return Ok(installed);
-        }
-    }
-
     if OPENVPN_REL == "openvpn" {
         return Ok(PathBuf::from("openvpn"));
     }
@@ -369,225 +783,1072 @@ fn resolve_openvpn_binary(app: &AppHandle<RT>) -> Result<PathBuf, String> {
     Ok(PathBuf::from("openvpn"))
 }
 
-// ---------------- Kill switch helper invocations (linux) ----------------
+// ---------------- Helper invocations (linux) ----------------
 
+/// The endpoints the kill switch lets the VPN handshake reach: every config remote at the
+/// addresses `resolve_config_remotes` found (the same ones the pinned config uses), limited
+/// to the address family its proto allows. The helper gets only this, never the config.
 #[cfg(target_os = "linux")]
-async fn run_helper_direct(enable: bool, cfg: Option<&str>) -> Result<(), String> {
-    let helper = LINUX_HELPER_PATH;
-    if !Path::new(helper).exists() {
+fn killswitch_plan(cfg: &str, opts: &KillSwitchOpts) -> Result<KillSwitchPlan, String> {
+    let text = fs::read_to_string(cfg).map_err(|e| format!("Failed to read config: {e}"))?;
+    let parsed = OpenVpnConfig::parse(&text).map_err(|e| format!("Invalid OpenVPN config: {e}"))?;
+
+    let mut endpoints = Vec::new();
+    for remote in parsed.remotes() {
+        let ips = match remote.host.parse::<IpAddr>() {
+            Ok(ip) => vec![ip],
+            Err(_) => opts
+                .resolved
+                .iter()
+                .filter(|(h, _)| h.eq_ignore_ascii_case(&remote.host))
+                .map(|(_, ip)| *ip)
+                .collect(),
+        };
+        let proto = if remote.proto.is_tcp() {
+            EndpointProto::Tcp
+        } else {
+            EndpointProto::Udp
+        };
+
+        // `proto udp4`/`tcp6` pins the address family OpenVPN will use.
+        for ip in ips.into_iter().filter(|ip| remote.proto.allows(ip)) {
+            let e = Endpoint {
+                ip,
+                proto,
+                port: remote.port,
+            };
+            if !endpoints.contains(&e) {
+                endpoints.push(e);
+            }
+        }
+    }
+
+    // A host that could not be resolved is simply not allowed: OpenVPN gets the same pinned
+    // addresses, so it would not be contacting it either.
+    if endpoints.is_empty() {
         return Err(
-            "Kill switch helper missing: /usr/libexec/stellar-vpn/stellar-vpn-helper".to_string(),
+            "No VPN remotes could be allowed: the config has none, or none of its hostnames could be resolved.".to_string(),
         );
     }
 
-    let mut cmd = Command::new(helper);
-    cmd.arg("killswitch")
-        .arg(if enable { "enable" } else { "disable" });
+    let plan = KillSwitchPlan {
+        endpoints,
+        interface: opts.tun_dev.clone(),
+        dns: opts.dns.clone(),
+        allow_lan: opts.allow_lan,
+        log_drops: false,
+        exemptions: opts
+            .exemptions
+            .iter()
+            .map(KillSwitchExemption::from)
+            .collect(),
+    };
+    plan.validate()?;
+    Ok(plan)
+}
+
+/// Run a kill switch request through stellar-vpn-daemon, which runs the helper as root.
+#[cfg(target_os = "linux")]
+async fn run_helper(req: &Request) -> Result<serde_json::Value, String> {
+    linux_daemon::kill_switch(req).await
+}
 
+#[cfg(target_os = "linux")]
+async fn apply_kill_switch(
+    enable: bool,
+    config_path: Option<&str>,
+    opts: &KillSwitchOpts,
+) -> Result<(), String> {
     if enable {
-        let c =
-            cfg.ok_or_else(|| "config_path is required when enabling kill switch.".to_string())?;
-        cmd.arg("--config").arg(c);
+        let cfg = config_path
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .ok_or_else(|| "config_path is required when enabling kill switch.".to_string())?;
+
+        if !Path::new(cfg).exists() {
+            return Err(format!("config_path does not exist: {cfg}"));
+        }
+
+        let plan = killswitch_plan(cfg, opts)?;
+        return run_helper(&Request::Enable(plan)).await.map(drop);
     }
 
-    let out = cmd
-        .output()
+    run_helper(&Request::Disable).await.map(drop)
+}
+
+#[cfg(not(target_os = "linux"))]
+async fn apply_kill_switch(
+    _enable: bool,
+    _config_path: Option<&str>,
+    _opts: &KillSwitchOpts,
+) -> Result<(), String> {
+    Err("Kill switch requires admin/root on this platform.".to_string())
+}
+
+/// Fields of the helper's `status` result the app acts on.
+#[cfg(target_os = "linux")]
+#[derive(Debug, serde::Deserialize)]
+struct KillSwitchStatus {
+    /// "nftables", or "iptables" where nft is unavailable.
+    backend: String,
+    active: bool,
+    ipv6_blocked: bool,
+}
+
+/// The helper's `verify` result.
+#[cfg(target_os = "linux")]
+#[derive(Debug, serde::Deserialize)]
+struct KillSwitchVerify {
+    drift: Vec<String>,
+}
+
+/// Listing firewall rules needs CAP_NET_ADMIN, which the daemon has and the app does not,
+/// so there is no fallback when it cannot be asked.
+#[cfg(target_os = "linux")]
+async fn killswitch_status() -> Result<KillSwitchStatus, String> {
+    let v = run_helper(&Request::Status).await?;
+    serde_json::from_value(v).map_err(|e| format!("Unexpected kill switch status output: {e}"))
+}
+
+/// None when the daemon could not be asked.
+#[cfg(target_os = "linux")]
+async fn killswitch_table_exists() -> Option<bool> {
+    killswitch_status().await.ok().map(|st| st.active)
+}
+
+#[cfg(not(target_os = "linux"))]
+async fn killswitch_table_exists() -> Option<bool> {
+    Some(false)
+}
+
+/// How the live rules differ from what `enable` would load with `opts`. None when the helper
+/// cannot tell (daemon not running).
+#[cfg(target_os = "linux")]
+async fn killswitch_drift(cfg: &str, opts: &KillSwitchOpts) -> Option<Vec<String>> {
+    let plan = killswitch_plan(cfg, opts).ok()?;
+    let v = run_helper(&Request::Verify(plan)).await.ok()?;
+    serde_json::from_value::<KillSwitchVerify>(v)
+        .ok()
+        .map(|v| v.drift)
+}
+
+#[cfg(target_os = "linux")]
+async fn killswitch_stats() -> Result<KillSwitchStats, String> {
+    let v = run_helper(&Request::Stats)
         .await
-        .map_err(|e| format!("Failed to start helper: {e}"))?;
+        .map_err(|e| format!("Kill switch stats failed: {e}"))?;
+    serde_json::from_value(v).map_err(|e| format!("Unexpected kill switch stats output: {e}"))
+}
 
-    if out.status.success() {
-        return Ok(());
-    }
+#[cfg(not(target_os = "linux"))]
+async fn killswitch_stats() -> Result<KillSwitchStats, String> {
+    Err("Kill switch stats are not available on this platform.".to_string())
+}
 
-    let stdout = String::from_utf8_lossy(&out.stdout).to_string();
-    let stderr = String::from_utf8_lossy(&out.stderr).to_string();
-    Err(format!(
-        "Direct helper failed.\n{}\n{}",
-        if stdout.trim().is_empty() { "" } else { &stdout },
-        if stderr.trim().is_empty() { "" } else { &stderr }
-    ))
+/// ufw, firewalld, Docker or `nft flush ruleset` can remove or rewrite the kill switch
+/// rules while the UI still says it is on. Check them periodically and put them back.
+#[cfg(target_os = "linux")]
+async fn kill_switch_watchdog(app: AppHandle<RT>, state: SharedState) {
+    loop {
+        tokio::time::sleep(Duration::from_secs(KILL_SWITCH_WATCHDOG_SECS)).await;
+
+        let op = { state.lock().await.kill_switch_op.clone() };
+        let _op = op.lock().await;
+
+        let (enabled, cfg, opts) = {
+            let g = state.lock().await;
+            (
+                g.kill_switch_enabled,
+                g.last_config_path.clone(),
+                g.kill_switch_opts(),
+            )
+        };
+        let Some(cfg) = cfg.filter(|_| enabled) else {
+            continue;
+        };
+        let Some(drift) = killswitch_drift(&cfg, &opts).await else {
+            continue;
+        };
+        if drift.is_empty() {
+            continue;
+        }
+
+        let msg = format!(
+            "Kill switch rules were removed or changed outside the app ({}). Re-applying.",
+            drift.join("; ")
+        );
+        emit_log(&app, &format!("[ui] WARNING: {msg}"));
+        emit_kill_switch_warning(&app, &msg);
+
+        let res = match killswitch_plan(&cfg, &opts) {
+            Ok(plan) => run_helper(&Request::Enable(plan)).await,
+            Err(e) => Err(e),
+        };
+        match res {
+            Ok(_) => emit_log(&app, "[ui] Kill switch rules re-applied."),
+            Err(e) => {
+                let msg = format!("Could not re-apply kill switch: {e}");
+                emit_log(&app, &format!("[ui] WARNING: {msg}"));
+                emit_kill_switch_warning(&app, &msg);
+            }
+        }
+    }
 }
 
+/// The helper picks the firewall itself; say which one so iptables fallbacks show up in logs.
 #[cfg(target_os = "linux")]
-async fn run_helper_pkexec(enable: bool, cfg: Option<&str>) -> Result<(), String> {
-    let helper = LINUX_HELPER_PATH;
-    if !Path::new(helper).exists() {
-        return Err(
-            "Kill switch helper missing: /usr/libexec/stellar-vpn/stellar-vpn-helper".to_string(),
+async fn log_kill_switch_backend(app: &AppHandle<RT>) {
+    if let Ok(st) = killswitch_status().await {
+        emit_log(
+            app,
+            &format!("[ui] Kill switch firewall backend: {}", st.backend),
         );
     }
+}
 
-    let mut cmd = Command::new("pkexec");
-    cmd.arg(helper)
-        .arg("killswitch")
-        .arg(if enable { "enable" } else { "disable" });
+#[cfg(not(target_os = "linux"))]
+async fn log_kill_switch_backend(_app: &AppHandle<RT>) {}
 
-    if enable {
-        let c =
-            cfg.ok_or_else(|| "config_path is required when enabling kill switch.".to_string())?;
-        cmd.arg("--config").arg(c);
+#[cfg(target_os = "linux")]
+async fn cleanup_killswitch_when_disabled(app: &AppHandle<RT>, state: &SharedState) {
+    let ks = { state.lock().await.kill_switch_enabled };
+    if ks {
+        return;
+    }
+
+    let _ = run_helper(&Request::Disable).await;
+
+    match killswitch_table_exists().await {
+        Some(false) => {}
+        Some(true) => emit_log(app, "[ui] WARNING: kill switch rules still exist after disable attempt. Internet may remain blocked."),
+        None => emit_log(app, "[ui] WARNING: could not check that kill switch rules are gone. Internet may remain blocked."),
     }
+}
+
+#[cfg(not(target_os = "linux"))]
+async fn cleanup_killswitch_when_disabled(_app: &AppHandle<RT>, _state: &SharedState) {}
 
-    let out = cmd
-        .output()
+/// The server OpenVPN reported at CONNECTED, as the config pins it (the management
+/// interface does not say which protocol). None when it is not one of the config's remotes.
+#[cfg(target_os = "linux")]
+async fn active_remote(state: &SharedState) -> Option<Endpoint> {
+    let (cfg, opts, ip, port) = {
+        let g = state.lock().await;
+        let server = g.server.as_ref()?;
+        (
+            g.last_config_path.clone()?,
+            g.kill_switch_opts(),
+            server.remote_ip.as_deref()?.parse::<IpAddr>().ok()?,
+            server.remote_port?,
+        )
+    };
+    killswitch_plan(&cfg, &opts)
+        .ok()?
+        .endpoints
+        .into_iter()
+        .find(|e| e.ip == ip && e.port == port)
+}
+
+#[cfg(target_os = "linux")]
+async fn run_ipv6_block_helper(state: &SharedState, block: bool) -> Result<(), String> {
+    // An IPv6 transport carrying an IPv4-only tunnel must survive its own block.
+    let remote = if block {
+        active_remote(state).await
+    } else {
+        None
+    };
+    run_helper(&Request::Ipv6 { block, remote }).await.map(drop)
+}
+
+#[cfg(not(target_os = "linux"))]
+async fn run_ipv6_block_helper(_state: &SharedState, _block: bool) -> Result<(), String> {
+    Err("IPv6 cannot be blocked on this platform".to_string())
+}
+
+/// None when the daemon could not be asked.
+#[cfg(target_os = "linux")]
+async fn ipv6_block_table_exists() -> Option<bool> {
+    killswitch_status().await.ok().map(|st| st.ipv6_blocked)
+}
+
+#[cfg(not(target_os = "linux"))]
+async fn ipv6_block_table_exists() -> Option<bool> {
+    Some(false)
+}
+
+async fn set_ipv6_block(
+    app: &AppHandle<RT>,
+    state: &SharedState,
+    block: bool,
+) -> Result<(), String> {
+    let op = { state.lock().await.kill_switch_op.clone() };
+    let _op = op.lock().await;
+
+    match run_ipv6_block_helper(state, block).await {
+        Ok(()) => {
+            state.lock().await.ipv6_blocked = block;
+            emit_log(
+                app,
+                if block {
+                    "[ui] IPv6 egress blocked (tunnel is IPv4-only)"
+                } else {
+                    "[ui] IPv6 egress block removed"
+                },
+            );
+            Ok(())
+        }
+        Err(e) => {
+            emit_log(app, &format!("[ui] IPv6 block update failed: {e}"));
+            Err(e)
+        }
+    }
+}
+
+/// On CONNECTED: IPv6 either goes through the tunnel (`tunnel_ipv6`: the config redirects
+/// it and the server assigned an address) or is blocked so it cannot bypass an IPv4-only
+/// tunnel. Err when it can be neither; the caller must not stay connected then.
+async fn apply_ipv6_policy(
+    app: &AppHandle<RT>,
+    state: &SharedState,
+    tunnel_ipv6: bool,
+) -> Result<(), String> {
+    let blocked = { state.lock().await.ipv6_blocked };
+
+    let mode = if tunnel_ipv6 {
+        if blocked {
+            let _ = set_ipv6_block(app, state, false).await;
+        }
+        "tunnel"
+    } else {
+        if !blocked {
+            set_ipv6_block(app, state, true).await.map_err(|e| {
+                format!(
+                    "The tunnel is IPv4-only and IPv6 could not be blocked ({e}). Disconnected so IPv6 traffic cannot bypass the VPN."
+                )
+            })?;
+        }
+        "blocked"
+    };
+
+    let mut g = state.lock().await;
+    if let Some(server) = g.server.as_mut() {
+        server.ipv6 = Some(mode);
+    }
+    Ok(())
+}
+
+/// Whether OpenVPN is told to redirect IPv6 for this config (see
+/// `OpenVpnConfig::redirect_gateway_args`).
+async fn config_has_ipv6(cfg_path: &Path) -> bool {
+    tokio::fs::read_to_string(cfg_path)
         .await
-        .map_err(|e| format!("Failed to start pkexec: {e}"))?;
+        .ok()
+        .and_then(|text| OpenVpnConfig::parse(&text).ok())
+        .is_some_and(|cfg| cfg.has_ipv6())
+}
 
-    if out.status.success() {
-        return Ok(());
+/// The whole plan goes along, so backends that rebuild their rules for a new tunnel never
+/// depend on reading the old ones back.
+#[cfg(target_os = "linux")]
+async fn run_set_tunnel_helper(cfg: Option<&str>, opts: &KillSwitchOpts) -> Result<(), String> {
+    let cfg = cfg.ok_or_else(|| "No config to build the kill switch plan from".to_string())?;
+    let plan = killswitch_plan(cfg, opts)?;
+    run_helper(&Request::SetTunnel(plan)).await.map(drop)
+}
+
+#[cfg(not(target_os = "linux"))]
+async fn run_set_tunnel_helper(_cfg: Option<&str>, _opts: &KillSwitchOpts) -> Result<(), String> {
+    Ok(())
+}
+
+/// Remember the tunnel device OpenVPN opened (None once the session is over) and the
+/// resolvers the server pushed, and pin the kill switch to them, so other tun/tap users and
+/// other DNS servers cannot bypass it.
+async fn note_tunnel_device(
+    app: &AppHandle<RT>,
+    state: &SharedState,
+    dev: Option<&str>,
+    dns: Vec<IpAddr>,
+) {
+    // The watchdog must not see the new device in the plan before the rules have it.
+    let op = { state.lock().await.kill_switch_op.clone() };
+    let _op = op.lock().await;
+
+    let (ks_enabled, cfg, opts) = {
+        let mut g = state.lock().await;
+        if dev.is_none() && g.tun_dev.is_none() {
+            return;
+        }
+        g.tun_dev = dev.map(str::to_string);
+        g.tun_dns = dns;
+        (
+            g.kill_switch_enabled,
+            g.last_config_path.clone(),
+            g.kill_switch_opts(),
+        )
+    };
+
+    if !ks_enabled {
+        return;
+    }
+    let shown = dev.unwrap_or("none");
+    match run_set_tunnel_helper(cfg.as_deref(), &opts).await {
+        Ok(()) => emit_log(app, &format!("[ui] Kill switch tunnel interface: {shown}")),
+        Err(e) => emit_log(app, &format!("[ui] Kill switch tunnel update failed: {e}")),
+    }
+}
+
+async fn release_ipv6_block(app: &AppHandle<RT>, state: &SharedState) {
+    let blocked = { state.lock().await.ipv6_blocked };
+    if blocked {
+        let _ = set_ipv6_block(app, state, false).await;
+    }
+}
+
+// ---------------- Persisted state ----------------
+
+fn persisted_state_path(app: &AppHandle<RT>) -> Option<PathBuf> {
+    app.path()
+        .app_config_dir()
+        .ok()
+        .map(|d| d.join("vpn-state.json"))
+}
+
+fn load_persisted_state(app: &AppHandle<RT>) -> PersistedState {
+    let Some(p) = persisted_state_path(app) else {
+        return PersistedState::default();
+    };
+    fs::read(&p)
+        .ok()
+        .and_then(|b| serde_json::from_slice(&b).ok())
+        .unwrap_or_default()
+}
+
+/// Write kill switch preference and last config to disk (best effort).
+async fn save_persisted_state(app: &AppHandle<RT>, state: &SharedState) {
+    let snapshot = {
+        let g = state.lock().await;
+        PersistedState {
+            kill_switch_enabled: g.kill_switch_enabled,
+            kill_switch_allow_lan: g.kill_switch_allow_lan,
+            kill_switch_exemptions: g
+                .kill_switch_exemptions
+                .iter()
+                .map(KillSwitchExemption::from)
+                .collect(),
+            last_config_source: g.last_config_source.clone(),
+            last_config_path: g.last_config_path.clone(),
+        }
+    };
+
+    let Some(p) = persisted_state_path(app) else {
+        return;
+    };
+    let res = (|| -> Result<(), String> {
+        if let Some(dir) = p.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let json = serde_json::to_vec_pretty(&snapshot).map_err(|e| e.to_string())?;
+        let tmp = p.with_extension("json.tmp");
+        fs::write(&tmp, json).map_err(|e| e.to_string())?;
+        fs::rename(&tmp, &p).map_err(|e| e.to_string())
+    })();
+
+    if let Err(e) = res {
+        emit_log(app, &format!("[ui] Failed to save {}: {e}", p.display()));
+    }
+}
+
+/// Startup: firewall rules outlive the app, so bring our state back in line with them.
+async fn reconcile_firewall(app: AppHandle<RT>, state: SharedState) {
+    reconcile_kill_switch(&app, &state).await;
+
+    // The IPv6 block only makes sense while a session is up.
+    if ipv6_block_table_exists().await == Some(true)
+        && set_ipv6_block(&app, &state, false).await.is_ok()
+    {
+        emit_log(&app, "[ui] Removed IPv6 block left from a previous run.");
+    }
+}
+
+/// The kill switch table may still be blocking traffic while we think it is off.
+async fn reconcile_kill_switch(app: &AppHandle<RT>, state: &SharedState) {
+    let op = { state.lock().await.kill_switch_op.clone() };
+    let _op = op.lock().await;
+
+    let saved = load_persisted_state(app);
+
+    // Cached configs live in the temp dir and may not have survived a reboot.
+    let cfg_path = saved
+        .last_config_path
+        .clone()
+        .filter(|p| Path::new(p).exists());
+    // Entries the current rules reject are dropped rather than failing the whole restore.
+    let mut exemptions = Vec::new();
+    for e in &saved.kill_switch_exemptions {
+        let res = e.parse().and_then(|e| {
+            exemptions.push(e);
+            killswitch_exemption::validate_all(&exemptions).inspect_err(|_| {
+                exemptions.pop();
+            })
+        });
+        if let Err(err) = res {
+            emit_log(app, &format!("[ui] Ignoring saved exemption: {err}"));
+        }
+    }
+    {
+        let mut g = state.lock().await;
+        g.kill_switch_allow_lan = saved.kill_switch_allow_lan;
+        g.kill_switch_exemptions = exemptions;
+        g.last_config_source = saved.last_config_source.clone();
+        g.last_config_path = cfg_path.clone();
+    }
+
+    let Some(exists) = killswitch_table_exists().await else {
+        // Without the daemon there is no telling whether rules are loaded: keep the saved
+        // setting rather than persist a guess.
+        emit_log(
+            app,
+            "[ui] Kill switch state unknown: the Stellar VPN service is not reachable.",
+        );
+        {
+            let mut g = state.lock().await;
+            g.kill_switch_enabled = saved.kill_switch_enabled;
+            g.kill_switch_unknown = true;
+        }
+        emit_kill_switch(app, KillSwitchState::Unknown);
+        return;
+    };
+
+    let enabled = if exists {
+        if saved.kill_switch_enabled {
+            emit_log(app, "[ui] Kill switch restored (rules still active).");
+        } else {
+            emit_log(
+                app,
+                "[ui] Kill switch rules left from a previous run; keeping them.",
+            );
+        }
+        true
+    } else if !saved.kill_switch_enabled {
+        false
+    } else if let Some(cfg) = cfg_path.as_deref() {
+        resolve_config_remotes(app, state, Path::new(cfg)).await;
+        let opts = { state.lock().await.kill_switch_opts() };
+        match apply_kill_switch(true, Some(cfg), &opts).await {
+            Ok(()) => {
+                emit_log(app, "[ui] Kill switch re-applied from saved settings.");
+                log_kill_switch_backend(app).await;
+                true
+            }
+            Err(e) => {
+                emit_log(app, &format!("[ui] Could not re-apply kill switch: {e}"));
+                false
+            }
+        }
+    } else {
+        emit_log(
+            app,
+            "[ui] Kill switch was on, but its cached config is gone.",
+        );
+        false
+    };
+
+    let st = {
+        let mut g = state.lock().await;
+        g.kill_switch_enabled = enabled;
+        g.kill_switch_unknown = false;
+        KillSwitchState::of(&g)
+    };
+    if enabled != saved.kill_switch_enabled || cfg_path != saved.last_config_path {
+        save_persisted_state(app, state).await;
+    }
+    emit_kill_switch(app, st);
+}
+
+// ---------------- Session lifecycle ----------------
+
+fn apply_status(g: &mut VpnInner, st: UiStatus) -> StatusPayload {
+    if g.status != st {
+        g.status_since_ms = now_ms();
+    }
+    if st.is_idle() {
+        g.stats.rate_in = 0;
+        g.stats.rate_out = 0;
+        g.stats.sampled_at = None;
+    }
+    g.status = st;
+    g.status_payload()
+}
+
+async fn set_status(state: &SharedState, app: &AppHandle<RT>, st: UiStatus) {
+    let payload = {
+        let mut g = state.lock().await;
+        apply_status(&mut g, st.clone())
+    };
+    emit_status(app, &payload);
+    update_tray_ui(app, st);
+}
+
+/// Like `set_status`, but only while `sid` is still the registered session.
+/// Keeps a session that is being torn down from overwriting its successor's status.
+async fn set_session_status(state: &SharedState, app: &AppHandle<RT>, sid: u64, st: UiStatus) {
+    let payload = {
+        let mut g = state.lock().await;
+        if g.session.as_ref().map(|s| s.sid) != Some(sid) {
+            return;
+        }
+        apply_status(&mut g, st.clone())
+    };
+    emit_status(app, &payload);
+    update_tray_ui(app, st);
+}
+
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+async fn set_error_and_disconnect(state: &SharedState, app: &AppHandle<RT>, msg: String) {
+    set_status(state, app, UiStatus::Failed(msg)).await;
+}
+
+/// Record the endpoint OpenVPN reports in `>STATE:` lines.
+async fn note_server_endpoint(state: &SharedState, st: &openvpn_mgmt::StateInfo) {
+    let mut g = state.lock().await;
+    let server = g.server.get_or_insert_with(ServerInfo::default);
+    if st.remote_ip.is_some() {
+        server.remote_ip = st.remote_ip.clone();
+        server.remote_port = st.remote_port;
+    }
+    if st.local_ip.is_some() {
+        server.local_ip = st.local_ip.clone();
+    }
+    if st.is_connected() {
+        server.local_ipv6 = st.local_ipv6.clone();
+    }
+}
+
+/// Feed a byte counter sample (OpenVPN `>BYTECOUNT:`) into the stats and emit `vpn-stats`.
+async fn record_traffic(state: &SharedState, app: &AppHandle<RT>, bytes_in: u64, bytes_out: u64) {
+    let stats = {
+        let mut g = state.lock().await;
+        let now = time::Instant::now();
+        let st = &mut g.stats;
+
+        if let Some(prev) = st.sampled_at {
+            let secs = now.duration_since(prev).as_secs_f64();
+            if secs > 0.0 {
+                // Counters restart from zero when OpenVPN restarts the connection.
+                st.rate_in = (bytes_in.saturating_sub(st.bytes_in) as f64 / secs) as u64;
+                st.rate_out = (bytes_out.saturating_sub(st.bytes_out) as f64 / secs) as u64;
+            }
+        }
+
+        st.bytes_in = bytes_in;
+        st.bytes_out = bytes_out;
+        st.sampled_at = Some(now);
+        st.ts_ms = now_ms();
+        st.clone()
+    };
+    emit_stats(app, &stats);
+}
+
+async fn stop_current_session(app: &AppHandle<RT>, state: &SharedState) {
+    let sess = {
+        let mut g = state.lock().await;
+        g.disconnect_requested = true;
+        g.session.take()
+    };
+
+    if let Some(sess) = sess {
+        set_status(state, app, UiStatus::Disconnecting).await;
+        let _ = sess.stop_tx.send(true);
+        emit_log(app, "[ui] Stop requested");
+
+        // The session task drops its receiver once OpenVPN is gone.
+        let _ = time::timeout(
+            Duration::from_millis(STOP_GRACE_MS + 1_000),
+            sess.stop_tx.closed(),
+        )
+        .await;
     }
 
-    let stdout = String::from_utf8_lossy(&out.stdout).to_string();
-    let stderr = String::from_utf8_lossy(&out.stderr).to_string();
-    Err(format!(
-        "Kill switch helper failed.\n{}\n{}",
-        if stdout.trim().is_empty() { "" } else { &stdout },
-        if stderr.trim().is_empty() { "" } else { &stderr }
-    ))
+    set_status(state, app, UiStatus::Disconnected).await;
+
+    cleanup_killswitch_when_disabled(app, state).await;
 }
 
+/// Ask OpenVPN to exit via the management interface, then kill it if it does not comply.
+#[cfg(not(target_os = "linux"))]
+async fn stop_openvpn(child: &mut tokio::process::Child, mgmt: &mut MgmtClient) {
+    if mgmt.signal("SIGTERM").await.is_ok()
+        && time::timeout(Duration::from_millis(STOP_GRACE_MS), child.wait())
+            .await
+            .is_ok()
+    {
+        return;
+    }
+    let _ = child.kill().await;
+    let _ = child.wait().await;
+}
+
+async fn finish_session(state: &SharedState, sid: u64, cfg_path: &Path) {
+    let ks_enabled = { state.lock().await.kill_switch_enabled };
+    if cfg_path.starts_with(temp_dir()) && !ks_enabled {
+        let _ = tokio::fs::remove_file(cfg_path).await;
+    }
+
+    let mut g = state.lock().await;
+    if let Some(sess) = &g.session {
+        if sess.sid == sid {
+            g.session = None;
+        }
+    }
+}
+
+/// Watch the underlying network for the lifetime of a session (see netmon.rs).
 #[cfg(target_os = "linux")]
-async fn apply_kill_switch(enable: bool, config_path: Option<&str>) -> Result<(), String> {
-    if enable {
-        let cfg = config_path
-            .map(|s| s.trim())
-            .filter(|s| !s.is_empty())
-            .ok_or_else(|| "config_path is required when enabling kill switch.".to_string())?;
+fn spawn_network_monitor(app: &AppHandle<RT>) -> tokio::sync::mpsc::UnboundedReceiver<String> {
+    let log_app = app.clone();
+    let log = move |msg: String| emit_log(&log_app, &format!("[ui] Network monitor: {msg}"));
+    match netmon::spawn(log) {
+        Ok(rx) => rx,
+        Err(e) => {
+            emit_log(app, &format!("[ui] Network monitor unavailable: {e}"));
+            tokio::sync::mpsc::unbounded_channel().1
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn spawn_network_monitor(_app: &AppHandle<RT>) -> tokio::sync::mpsc::UnboundedReceiver<String> {
+    tokio::sync::mpsc::unbounded_channel().1
+}
+
+/// How a single OpenVPN run ended.
+enum SessionEnd {
+    /// Stop requested by the user/app.
+    Stopped,
+    /// Retrying will not help (bad credentials, missing binary, ...).
+    Fatal(String),
+    /// OpenVPN went away on its own; `was_connected` tells whether the tunnel had come up.
+    Lost { was_connected: bool, reason: String },
+}
+
+fn reconnect_delay(attempt: u32) -> Duration {
+    let ms = RECONNECT_BASE_DELAY_MS.saturating_mul(1 << attempt.saturating_sub(1).min(16));
+    Duration::from_millis(ms.min(RECONNECT_MAX_DELAY_MS))
+}
+
+/// Session supervisor: runs OpenVPN and, once a tunnel has been established, brings it back
+/// with exponential backoff when it drops. Kill switch rules stay in place between attempts.
+async fn run_openvpn_session(
+    app: AppHandle<RT>,
+    state: SharedState,
+    sid: u64,
+    cfg_path: PathBuf,
+    creds: Credentials,
+    mut stop_rx: tokio::sync::watch::Receiver<bool>,
+    watchdog_ms: u64,
+) {
+    let mut cfg_path = cfg_path;
+    let mut attempt: u32 = 0;
+    let mut net_rx = spawn_network_monitor(&app);
+
+    loop {
+        let end = run_openvpn_once(
+            &app,
+            &state,
+            sid,
+            &cfg_path,
+            &creds,
+            &mut stop_rx,
+            &mut net_rx,
+            watchdog_ms,
+        )
+        .await;
+
+        let reason = match end {
+            SessionEnd::Stopped => break,
+            SessionEnd::Fatal(reason) => {
+                set_session_status(&state, &app, sid, UiStatus::Failed(reason)).await;
+                break;
+            }
+            SessionEnd::Lost {
+                was_connected: true,
+                reason,
+            } => {
+                attempt = 0;
+                reason
+            }
+            SessionEnd::Lost { reason, .. } if attempt == 0 => {
+                // Never got a tunnel up: report instead of retrying.
+                set_session_status(&state, &app, sid, UiStatus::Failed(reason)).await;
+                break;
+            }
+            SessionEnd::Lost { reason, .. } => reason,
+        };
 
-        if !Path::new(cfg).exists() {
-            return Err(format!("config_path does not exist: {cfg}"));
+        attempt += 1;
+        if attempt > RECONNECT_MAX_ATTEMPTS {
+            set_session_status(
+                &state,
+                &app,
+                sid,
+                UiStatus::Failed(format!(
+                    "Connection lost; gave up after {RECONNECT_MAX_ATTEMPTS} reconnect attempts ({reason})"
+                )),
+            )
+            .await;
+            break;
         }
 
-        if let Ok(()) = run_helper_direct(true, Some(cfg)).await {
-            return Ok(());
-        }
+        let delay = reconnect_delay(attempt);
+        emit_log(
+            &app,
+            &format!(
+                "[ui] {reason} Reconnect attempt {attempt}/{RECONNECT_MAX_ATTEMPTS} in {}ms",
+                delay.as_millis()
+            ),
+        );
+        set_session_status(&state, &app, sid, UiStatus::Reconnecting).await;
 
-        return run_helper_pkexec(true, Some(cfg)).await;
-    }
+        tokio::select! {
+          _ = time::sleep(delay) => {}
+          res = stop_rx.changed() => {
+            if res.is_err() || *stop_rx.borrow() {
+              break;
+            }
+          }
+        }
 
-    if let Ok(()) = run_helper_direct(false, None).await {
-        return Ok(());
+        // Reuse the cached config (with the kill switch on we cannot download it again),
+        // unless it has since been replaced by a prefetch for another server.
+        let cached = {
+            let g = state.lock().await;
+            let same_source = g.server.as_ref().and_then(|s| s.config_source.as_ref())
+                == g.last_config_source.as_ref();
+            g.last_config_path.clone().filter(|_| same_source)
+        };
+        if let Some(p) = cached.map(PathBuf::from).filter(|p| p.exists()) {
+            cfg_path = p;
+        }
     }
-    run_helper_pkexec(false, None).await
-}
-
-#[cfg(not(target_os = "linux"))]
-async fn apply_kill_switch(_enable: bool, _config_path: Option<&str>) -> Result<(), String> {
-    Err("Kill switch requires admin/root on this platform.".to_string())
-}
 
-#[cfg(target_os = "linux")]
-async fn killswitch_table_exists() -> bool {
-    let out = Command::new("nft")
-        .args(["list", "table", "inet", "stellarkillswitch"])
-        .stdout(std::process::Stdio::null())
-        .stderr(std::process::Stdio::null())
-        .output()
-        .await;
-
-    matches!(out, Ok(o) if o.status.success())
+    release_ipv6_block(&app, &state).await;
+    note_tunnel_device(&app, &state, None, Vec::new()).await;
+    finish_session(&state, sid, &cfg_path).await;
 }
 
-#[cfg(not(target_os = "linux"))]
-async fn killswitch_table_exists() -> bool {
-    false
-}
+/// One OpenVPN process from spawn to exit.
+#[allow(clippy::too_many_arguments)]
+async fn run_openvpn_once(
+    app: &AppHandle<RT>,
+    state: &SharedState,
+    sid: u64,
+    cfg_path: &Path,
+    creds: &Credentials,
+    stop_rx: &mut tokio::sync::watch::Receiver<bool>,
+    net_rx: &mut tokio::sync::mpsc::UnboundedReceiver<String>,
+    watchdog_ms: u64,
+) -> SessionEnd {
+    emit_log(app, &format!("[ui] Starting OpenVPN (sid={sid})"));
+    emit_log(
+        app,
+        &format!("[ui] Using config file: {}", cfg_path.display()),
+    );
 
-#[cfg(target_os = "linux")]
-async fn cleanup_killswitch_when_disabled(app: &AppHandle<RT>, state: &SharedState) {
-    let ks = { state.lock().await.kill_switch_enabled };
-    if ks {
-        return;
+    let pinned = match write_pinned_config(state, cfg_path, sid).await {
+        Ok(p) => p,
+        Err(e) => return SessionEnd::Fatal(e),
+    };
+    if pinned.is_some() {
+        emit_log(
+            app,
+            "[ui] Using pre-resolved remotes (same addresses as the kill switch).",
+        );
     }
-
-    let _ = run_helper_direct(false, None).await;
-
-    if killswitch_table_exists().await {
-        emit_log(app, "[ui] WARNING: kill switch nft table still exists after disable attempt. Internet may remain blocked.");
+    let end = run_openvpn_process(
+        app,
+        state,
+        sid,
+        pinned.as_deref().unwrap_or(cfg_path),
+        creds,
+        stop_rx,
+        net_rx,
+        watchdog_ms,
+    )
+    .await;
+    if let Some(p) = pinned {
+        let _ = tokio::fs::remove_file(p).await;
     }
+    end
 }
 
-#[cfg(not(target_os = "linux"))]
-async fn cleanup_killswitch_when_disabled(_app: &AppHandle<RT>, _state: &SharedState) {}
+/// Account credentials for `--auth-user-pass`.
+struct Credentials {
+    username: String,
+    password: String,
+}
 
-// ---------------- Session lifecycle ----------------
+/// What a running OpenVPN reports to the session loop.
+enum ProcEvent {
+    Mgmt(MgmtEvent),
+    /// Output from before the management interface attached (startup/config errors).
+    #[cfg(target_os = "linux")]
+    Log(String),
+    Exited(i32),
+}
 
-async fn set_status(state: &SharedState, app: &AppHandle<RT>, st: UiStatus) {
-    let mut g = state.lock().await;
-    g.status = st;
-    emit_status(app, st.as_str());
-    update_tray_ui(app, st);
+/// A running OpenVPN. On Linux stellar-vpn-daemon runs it as root and streams its events;
+/// elsewhere it is a child of the app.
+enum OpenVpnProc {
+    #[cfg(target_os = "linux")]
+    Daemon(linux_daemon::DaemonRun),
+    #[cfg(not(target_os = "linux"))]
+    Local {
+        child: tokio::process::Child,
+        mgmt: MgmtClient,
+        mgmt_rx: tokio::sync::mpsc::UnboundedReceiver<MgmtEvent>,
+        line_rx: tokio::sync::mpsc::UnboundedReceiver<String>,
+        mgmt_sock: PathBuf,
+        auth_path: PathBuf,
+    },
 }
 
-async fn set_error_and_disconnect(state: &SharedState, app: &AppHandle<RT>, msg: String) {
-    {
-        let mut g = state.lock().await;
-        g.status = UiStatus::Disconnected;
+impl OpenVpnProc {
+    /// Cancel safe, so it can sit in a select loop.
+    async fn next(&mut self) -> ProcEvent {
+        match self {
+            #[cfg(target_os = "linux")]
+            OpenVpnProc::Daemon(run) => loop {
+                match run.next_event().await {
+                    Some(DaemonEvent::Mgmt { event }) => return ProcEvent::Mgmt(event),
+                    Some(DaemonEvent::Log { line }) => return ProcEvent::Log(line),
+                    Some(DaemonEvent::Exited { code }) => return ProcEvent::Exited(code),
+                    // The session derives its status from OpenVPN's states itself.
+                    Some(DaemonEvent::Status { .. }) => {}
+                    None => return ProcEvent::Exited(-1),
+                }
+            },
+            #[cfg(not(target_os = "linux"))]
+            OpenVpnProc::Local {
+                child,
+                mgmt_rx,
+                line_rx,
+                ..
+            } => loop {
+                tokio::select! {
+                  Some(ev) = mgmt_rx.recv() => return ProcEvent::Mgmt(ev),
+
+                  // Logs come from >LOG now; stdout is only drained.
+                  Some(_line) = line_rx.recv() => {}
+
+                  res = child.wait() => {
+                    return ProcEvent::Exited(res.ok().and_then(|s| s.code()).unwrap_or(-1));
+                  }
+                }
+            },
+        }
     }
-    emit_status(app, &format!("error: {msg}"));
-    emit_status(app, UiStatus::Disconnected.as_str());
-    update_tray_ui(app, UiStatus::Disconnected);
-}
 
-async fn stop_current_session(app: &AppHandle<RT>, state: &SharedState) {
-    {
-        let mut g = state.lock().await;
-        g.disconnect_requested = true;
+    /// Restarts (e.g. ping-restart) re-enter the hold state. The daemon releases those itself.
+    async fn hold_release(&mut self) {
+        match self {
+            #[cfg(target_os = "linux")]
+            OpenVpnProc::Daemon(_) => {}
+            #[cfg(not(target_os = "linux"))]
+            OpenVpnProc::Local { mgmt, .. } => {
+                let _ = mgmt.hold_release().await;
+            }
+        }
+    }
 
-        if let Some(sess) = g.session.take() {
-            let _ = sess.stop_tx.send(true);
-            emit_log(app, "[ui] Stop requested");
+    async fn stop(&mut self) {
+        match self {
+            #[cfg(target_os = "linux")]
+            OpenVpnProc::Daemon(_) => {
+                // Returns once OpenVPN is gone; dropping the run would stop it as well.
+                let _ = linux_daemon::disconnect().await;
+            }
+            #[cfg(not(target_os = "linux"))]
+            OpenVpnProc::Local { child, mgmt, .. } => stop_openvpn(child, mgmt).await,
         }
+    }
 
-        g.status = UiStatus::Disconnected;
+    fn cleanup(self) {
+        match self {
+            #[cfg(target_os = "linux")]
+            OpenVpnProc::Daemon(_) => {}
+            #[cfg(not(target_os = "linux"))]
+            OpenVpnProc::Local {
+                mgmt_sock,
+                auth_path,
+                ..
+            } => {
+                let _ = fs::remove_file(mgmt_sock);
+                let _ = fs::remove_file(auth_path);
+            }
+        }
     }
+}
+
+#[cfg(target_os = "linux")]
+async fn start_openvpn(
+    app: &AppHandle<RT>,
+    _sid: u64,
+    cfg_path: &Path,
+    creds: &Credentials,
+) -> Result<OpenVpnProc, SessionEnd> {
+    let config = tokio::fs::read_to_string(cfg_path)
+        .await
+        .map_err(|e| SessionEnd::Fatal(format!("Failed to read config: {e}")))?;
 
-    emit_status(app, UiStatus::Disconnected.as_str());
-    update_tray_ui(app, UiStatus::Disconnected);
+    let run = linux_daemon::connect(config, creds.username.clone(), creds.password.clone())
+        .await
+        .map_err(|e| SessionEnd::Lost {
+            was_connected: false,
+            reason: e,
+        })?;
 
-    cleanup_killswitch_when_disabled(app, state).await;
+    emit_log(app, "[ui] OpenVPN started by stellar-vpn-daemon");
+    Ok(OpenVpnProc::Daemon(run))
 }
 
-async fn run_openvpn_session(
-    app: AppHandle<RT>,
-    state: SharedState,
+#[cfg(not(target_os = "linux"))]
+async fn start_openvpn(
+    app: &AppHandle<RT>,
     sid: u64,
-    cfg_path: PathBuf,
-    auth_path: PathBuf,
-    mut stop_rx: tokio::sync::watch::Receiver<bool>,
-    watchdog_ms: u64,
-) {
-    emit_log(&app, &format!("[ui] Starting OpenVPN (sid={sid})"));
-    emit_log(
-        &app,
-        &format!("[ui] Using config file: {}", cfg_path.display()),
-    );
-
-    let openvpn_bin = match resolve_openvpn_binary(&app) {
-        Ok(p) => p,
-        Err(e) => {
-            let _ = fs::remove_file(&auth_path);
-            set_error_and_disconnect(&state, &app, e).await;
-            return;
-        }
-    };
+    cfg_path: &Path,
+    creds: &Credentials,
+) -> Result<OpenVpnProc, SessionEnd> {
+    let openvpn_bin = resolve_openvpn_binary(app).map_err(SessionEnd::Fatal)?;
 
     emit_log(
-        &app,
+        app,
         &format!("[ui] OpenVPN binary: {}", openvpn_bin.display()),
     );
 
+    let config = tokio::fs::read_to_string(cfg_path)
+        .await
+        .map_err(|e| SessionEnd::Fatal(format!("Failed to read config: {e}")))?;
+    let redirect_gateway = OpenVpnConfig::parse(&config)
+        .map_err(SessionEnd::Fatal)?
+        .redirect_gateway_args();
+
+    let auth_path =
+        write_auth_file(&creds.username, &creds.password, sid).map_err(SessionEnd::Fatal)?;
+    let mgmt_sock = temp_dir().join(format!("mgmt-{sid}.sock"));
+    let _ = fs::remove_file(&mgmt_sock);
+
     let mut cmd = Command::new(&openvpn_bin);
     cmd.kill_on_drop(true)
         .arg("--config")
-        .arg(&cfg_path)
+        .arg(cfg_path)
         .arg("--auth-user-pass")
         .arg(&auth_path)
         .arg("--auth-nocache")
-        .arg("--redirect-gateway")
-        .arg("def1")
+        .args(redirect_gateway)
         .arg("--verb")
         .arg("3")
+        .args(openvpn_mgmt::openvpn_args(&mgmt_sock))
         .stdout(std::process::Stdio::piped())
         .stderr(std::process::Stdio::piped());
 
@@ -595,121 +1856,219 @@ async fn run_openvpn_session(
         Ok(c) => c,
         Err(e) => {
             let _ = fs::remove_file(&auth_path);
-            set_error_and_disconnect(&state, &app, format!("Failed to start openvpn: {e}")).await;
-            return;
+            return Err(SessionEnd::Fatal(format!("Failed to start openvpn: {e}")));
         }
     };
 
-    let stdout = child.stdout.take();
-    let stderr = child.stderr.take();
-
     let (line_tx, mut line_rx) = tokio::sync::mpsc::unbounded_channel::<String>();
 
-    let stdout_task = if let Some(out) = stdout {
+    if let Some(out) = child.stdout.take() {
         let tx = line_tx.clone();
         tokio::spawn(async move {
             let mut r = BufReader::new(out).lines();
             while let Ok(Some(line)) = r.next_line().await {
                 let _ = tx.send(line);
             }
-        })
-    } else {
-        tokio::spawn(async {})
-    };
-
-    let stderr_task = if let Some(err) = stderr {
-        let tx = line_tx.clone();
+        });
+    }
+    if let Some(err) = child.stderr.take() {
+        let tx = line_tx;
         tokio::spawn(async move {
             let mut r = BufReader::new(err).lines();
             while let Ok(Some(line)) = r.next_line().await {
                 let _ = tx.send(line);
             }
-        })
-    } else {
-        tokio::spawn(async {})
+        });
+    }
+
+    // Attach to the management socket. OpenVPN is on hold until we release it, so nothing
+    // is missed; stdout is only forwarded until then (startup/config errors).
+    let attached: Result<_, String> = {
+        let connect =
+            MgmtClient::connect(&mgmt_sock, Duration::from_millis(MGMT_CONNECT_TIMEOUT_MS));
+        tokio::pin!(connect);
+
+        loop {
+            tokio::select! {
+              r = &mut connect => break r,
+
+              Some(line) = line_rx.recv() => emit_log(app, &line),
+
+              res = child.wait() => {
+                let code = res.ok().and_then(|s| s.code()).unwrap_or(-1);
+                break Err(format!("OpenVPN exited before opening its management interface (code={code})."));
+              }
+            }
+        }
     };
 
+    let (mut mgmt, mgmt_rx) = match attached {
+        Ok(m) => m,
+        Err(e) => {
+            let _ = child.kill().await;
+            let _ = child.wait().await;
+            while let Ok(line) = line_rx.try_recv() {
+                emit_log(app, &line);
+            }
+            let _ = fs::remove_file(&mgmt_sock);
+            let _ = fs::remove_file(&auth_path);
+            return Err(SessionEnd::Lost {
+                was_connected: false,
+                reason: e,
+            });
+        }
+    };
+
+    emit_log(app, "[ui] Attached to OpenVPN management interface");
+
+    if let Err(e) = mgmt.subscribe(MGMT_BYTECOUNT_SECS).await {
+        emit_log(app, &format!("[ui] {e}"));
+    }
+    if let Err(e) = mgmt.hold_release().await {
+        emit_log(app, &format!("[ui] {e}"));
+    }
+
+    Ok(OpenVpnProc::Local {
+        child,
+        mgmt,
+        mgmt_rx,
+        line_rx,
+        mgmt_sock,
+        auth_path,
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn run_openvpn_process(
+    app: &AppHandle<RT>,
+    state: &SharedState,
+    sid: u64,
+    cfg_path: &Path,
+    creds: &Credentials,
+    stop_rx: &mut tokio::sync::watch::Receiver<bool>,
+    net_rx: &mut tokio::sync::mpsc::UnboundedReceiver<String>,
+    watchdog_ms: u64,
+) -> SessionEnd {
+    let mut proc = match start_openvpn(app, sid, cfg_path, creds).await {
+        Ok(p) => p,
+        Err(end) => return end,
+    };
+    let routes_ipv6 = config_has_ipv6(cfg_path).await;
+
     let watchdog_deadline = time::Instant::now() + Duration::from_millis(watchdog_ms);
     let mut init_done = false;
 
-    loop {
+    let end = loop {
         tokio::select! {
-          _ = stop_rx.changed() => {
-            if *stop_rx.borrow() {
-              emit_log(&app, "[ui] Stop signal received, killing OpenVPN...");
-              let _ = child.kill().await;
-              let _ = child.wait().await;
-              set_status(&state, &app, UiStatus::Disconnected).await;
-              break;
+          res = stop_rx.changed() => {
+            if res.is_err() || *stop_rx.borrow() {
+              // stop_current_session reports Disconnected once we are gone.
+              emit_log(app, "[ui] Stop signal received, stopping OpenVPN...");
+              proc.stop().await;
+              break SessionEnd::Stopped;
             }
           }
 
-          Some(line) = line_rx.recv() => {
-            emit_log(&app, &line);
-
-            if !init_done && line.contains("Initialization Sequence Completed") {
-              init_done = true;
-              emit_log(&app, "[ui] OpenVPN reports Initialization Sequence Completed");
-              set_status(&state, &app, UiStatus::Connected).await;
+          ev = proc.next() => match ev {
+            #[cfg(target_os = "linux")]
+            ProcEvent::Log(line) => emit_log(app, &line),
+
+            ProcEvent::Mgmt(ev) => {
+              if ev.is_auth_failure() {
+                emit_log(app, "[ui] Auth failed, stopping...");
+                proc.stop().await;
+                break SessionEnd::Fatal("OpenVPN authentication failed (AUTH_FAILED).".to_string());
+              }
+
+              match ev {
+                MgmtEvent::Log { message, .. } => {
+                  // Pin the device as soon as it exists; the resolvers follow with UPDOWN.
+                  if let Some(dev) = openvpn_mgmt::tun_device_from_log(&message) {
+                    note_tunnel_device(app, state, Some(dev), Vec::new()).await;
+                  }
+                  emit_log(app, &message);
+                }
+                MgmtEvent::UpDown { up: true, env } => {
+                  if let Some(dev) = openvpn_mgmt::env_var(&env, "dev") {
+                    note_tunnel_device(app, state, Some(dev), openvpn_mgmt::pushed_dns(&env)).await;
+                  }
+                }
+                MgmtEvent::State(st) => {
+                  emit_log(app, &format!("[ui] OpenVPN state: {} {}", st.name, st.desc));
+                  note_server_endpoint(state, &st).await;
+                  if st.is_connected() {
+                    init_done = true;
+                    let tunnel_ipv6 = routes_ipv6 && st.local_ipv6.is_some();
+                    if let Err(e) = apply_ipv6_policy(app, state, tunnel_ipv6).await {
+                      emit_log(app, &format!("[ui] {e}"));
+                      proc.stop().await;
+                      break SessionEnd::Fatal(e);
+                    }
+                  }
+                  if let Some(ui) = openvpn_mgmt::ui_state_name(&st.name).and_then(UiStatus::from_str) {
+                    set_session_status(state, app, sid, ui).await;
+                  }
+                }
+                MgmtEvent::Hold(_) => proc.hold_release().await,
+                MgmtEvent::ByteCount { bytes_in, bytes_out } => {
+                  record_traffic(state, app, bytes_in, bytes_out).await;
+                }
+                MgmtEvent::Fatal(msg) => emit_log(app, &format!("[ui] OpenVPN fatal: {msg}")),
+                MgmtEvent::Error(msg) => emit_log(app, &format!("[ui] Management error: {msg}")),
+                _ => {}
+              }
             }
 
-            if line.contains("AUTH_FAILED") || line.contains("auth-failure") {
-              emit_log(&app, "[ui] Auth failed, stopping...");
-              let _ = child.kill().await;
-              let _ = child.wait().await;
-              set_error_and_disconnect(&state, &app, "OpenVPN authentication failed (AUTH_FAILED).".to_string()).await;
-              break;
+            ProcEvent::Exited(code) => {
+              emit_log(app, &format!("[ui] OpenVPN exited (code={code})"));
+
+              let manual = {
+                let g = state.lock().await;
+                g.disconnect_requested
+              };
+
+              if manual {
+                break SessionEnd::Stopped;
+              }
+              if init_done {
+                break SessionEnd::Lost {
+                  was_connected: true,
+                  reason: format!("OpenVPN exited unexpectedly (code={code})."),
+                };
+              }
+              break SessionEnd::Lost {
+                was_connected: false,
+                reason: format!("OpenVPN exited before connection was established (code={code})."),
+              };
+            }
+          },
+
+          // Underlying network changed: restart now instead of waiting for ping-restart.
+          // Changes before CONNECTED are ignored; OpenVPN is still retrying anyway.
+          Some(change) = net_rx.recv() => {
+            if init_done {
+              emit_log(app, &format!("[ui] Network changed ({change}), restarting tunnel"));
+              proc.stop().await;
+              break SessionEnd::Lost {
+                was_connected: true,
+                reason: format!("Network changed ({change})."),
+              };
             }
           }
 
           _ = time::sleep_until(watchdog_deadline), if !init_done => {
-            emit_log(&app, &format!("[ui] Connect watchdog fired after {watchdog_ms}ms"));
-            let _ = child.kill().await;
-            let _ = child.wait().await;
-            set_error_and_disconnect(&state, &app, format!("Connect timed out after {watchdog_ms}ms (no Initialization Sequence Completed).")).await;
-            break;
-          }
-
-          res = child.wait() => {
-            let code = match res {
-              Ok(s) => s.code().unwrap_or(-1),
-              Err(_) => -1,
-            };
-
-            emit_log(&app, &format!("[ui] OpenVPN exited (code={code})"));
-
-            let manual = {
-              let g = state.lock().await;
-              g.disconnect_requested
+            emit_log(app, &format!("[ui] Connect watchdog fired after {watchdog_ms}ms"));
+            proc.stop().await;
+            break SessionEnd::Lost {
+              was_connected: false,
+              reason: format!("Connect timed out after {watchdog_ms}ms (OpenVPN never reported CONNECTED)."),
             };
-
-            if !manual && !init_done {
-              set_error_and_disconnect(&state, &app, format!("OpenVPN exited before connection was established (code={code}).")).await;
-            } else {
-              set_status(&state, &app, UiStatus::Disconnected).await;
-            }
-
-            break;
           }
         }
-    }
-
-    let _ = stdout_task.await;
-    let _ = stderr_task.await;
-
-    let _ = fs::remove_file(&auth_path);
-
-    let ks_enabled = { state.lock().await.kill_switch_enabled };
-    if cfg_path.starts_with(temp_dir()) && !ks_enabled {
-        let _ = tokio::fs::remove_file(&cfg_path).await;
-    }
+    };
 
-    let mut g = state.lock().await;
-    if let Some(sess) = &g.session {
-        if sess.sid == sid {
-            g.session = None;
-        }
-    }
+    proc.cleanup();
+    end
 }
 
 // ---------------- Commands ----------------
@@ -804,7 +2163,7 @@ async fn vpn_prefetch_config(
 
     let (ks, st) = {
         let g = state.lock().await;
-        (g.kill_switch_enabled, g.status)
+        (g.kill_switch_enabled, g.status.clone())
     };
 
     if ks && st != UiStatus::Connected {
@@ -826,6 +2185,7 @@ async fn vpn_prefetch_config(
         g.last_config_path = Some(p_str.clone());
         g.last_config_source = Some(cfg);
     }
+    save_persisted_state(&app, &state).await;
 
     emit_log(&app, &format!("[ui] Prefetched config => {}", p_str));
     Ok(p_str)
@@ -851,7 +2211,7 @@ async fn vpn_connect(
         let g = state.lock().await;
         (
             g.kill_switch_enabled,
-            g.status,
+            g.status.clone(),
             g.last_config_source.clone(),
             g.last_config_path.clone(),
         )
@@ -876,6 +2236,16 @@ async fn vpn_connect(
     {
         let mut g = state.lock().await;
         g.disconnect_requested = false;
+        g.status_sid = Some(sid);
+        g.server = Some(ServerInfo {
+            config_source: Some(cfg_source.clone()),
+            ..ServerInfo::default()
+        });
+        g.stats = TrafficStats {
+            sid: Some(sid),
+            ts_ms: now_ms(),
+            ..TrafficStats::default()
+        };
     }
 
     set_status(state.inner(), &app, UiStatus::Connecting).await;
@@ -910,35 +2280,41 @@ async fn vpn_connect(
         prepare_config(cfg_source.as_str(), sid).await?
     };
 
-    let auth_path = write_auth_file(&username, &password, sid)?;
-
     {
         let mut g = state.lock().await;
         g.last_config_path = Some(cfg_path.to_string_lossy().to_string());
         g.last_config_source = Some(cfg_source.clone());
     }
+    save_persisted_state(&app, &state).await;
 
-    let ks_enabled_now = { state.lock().await.kill_switch_enabled };
-    if ks_enabled_now {
-        let cfg_str = cfg_path.to_string_lossy().to_string();
-        emit_log(
-            &app,
-            &format!("[ui] Kill switch enabled: applying for config {}", cfg_str),
-        );
-        apply_kill_switch(true, Some(cfg_str.as_str()))
-            .await
-            .map_err(|e| {
-                emit_log(&app, &format!("[ui] Kill switch apply failed: {e}"));
-                e
-            })?;
+    // Remotes are part of the kill switch plan: hold off the watchdog until they are applied.
+    {
+        let op = { state.lock().await.kill_switch_op.clone() };
+        let _op = op.lock().await;
+
+        resolve_config_remotes(&app, &state, &cfg_path).await;
+        let (ks_enabled_now, ks_opts) = {
+            let g = state.lock().await;
+            (g.kill_switch_enabled, g.kill_switch_opts())
+        };
+        if ks_enabled_now {
+            let cfg_str = cfg_path.to_string_lossy().to_string();
+            emit_log(
+                &app,
+                &format!("[ui] Kill switch enabled: applying for config {}", cfg_str),
+            );
+            apply_kill_switch(true, Some(cfg_str.as_str()), &ks_opts)
+                .await
+                .map_err(|e| {
+                    emit_log(&app, &format!("[ui] Kill switch apply failed: {e}"));
+                    e
+                })?;
+        }
     }
 
     // --- macOS: delegate to privileged helper (does NOT break Linux) ---
     #[cfg(target_os = "macos")]
     {
-        // ✅ NEW: force correct socket for BOTH subscriber + connect paths
-        std::env::set_var("STELLAR_VPN_HELPER_SOCKET", MACOS_HELPER_SOCKET);
-
         if let Err(e) = macos_installer::ensure_root_helper_installed(&app) {
             set_error_and_disconnect(state.inner(), &app, e.clone()).await;
             return Err(format!("Failed to install/start helper: {e}"));
@@ -977,7 +2353,10 @@ async fn vpn_connect(
             state.inner().clone(),
             sid,
             cfg_path,
-            auth_path,
+            Credentials {
+                username: username.to_string(),
+                password: password.to_string(),
+            },
             stop_rx,
             CONNECT_WATCHDOG_MS,
         ));
@@ -1009,9 +2388,15 @@ async fn vpn_disconnect(
 }
 
 #[tauri::command]
-async fn vpn_status(state: tauri::State<'_, SharedState>) -> Result<String, String> {
+async fn vpn_status(state: tauri::State<'_, SharedState>) -> Result<StatusPayload, String> {
+    let g = state.lock().await;
+    Ok(g.status_payload())
+}
+
+#[tauri::command]
+async fn vpn_stats(state: tauri::State<'_, SharedState>) -> Result<TrafficStats, String> {
     let g = state.lock().await;
-    Ok(g.status.as_str().to_string())
+    Ok(g.stats.clone())
 }
 
 #[derive(serde::Deserialize)]
@@ -1019,6 +2404,9 @@ struct KillSwitchArgs {
     enabled: bool,
     #[serde(alias = "configPath", alias = "config_path")]
     config_path: Option<String>,
+    /// Replaces the saved exemptions when given.
+    #[serde(default)]
+    exemptions: Option<Vec<KillSwitchExemption>>,
 }
 
 #[tauri::command]
@@ -1027,6 +2415,17 @@ async fn vpn_set_kill_switch(
     state: tauri::State<'_, SharedState>,
     args: KillSwitchArgs,
 ) -> Result<(), String> {
+    let op = { state.lock().await.kill_switch_op.clone() };
+    let _op = op.lock().await;
+
+    let exemptions = match &args.exemptions {
+        Some(list) => Some(parse_exemptions(list).map_err(|e| {
+            emit_log(&app, &format!("[ui] Invalid kill switch exemptions: {e}"));
+            e
+        })?),
+        None => None,
+    };
+
     if args.enabled {
         let cfg_in: String = if let Some(s) = args
             .config_path
@@ -1051,14 +2450,19 @@ async fn vpn_set_kill_switch(
 
         let cfg_path = prepare_config(&cfg_in, sid).await?;
         let cfg_str = cfg_path.to_string_lossy().to_string();
+        resolve_config_remotes(&app, &state, &cfg_path).await;
 
-        {
+        let mut ks_opts = {
             let mut g = state.lock().await;
             g.last_config_path = Some(cfg_str.clone());
             g.last_config_source = Some(cfg_in.clone());
+            g.kill_switch_opts()
+        };
+        if let Some(list) = &exemptions {
+            ks_opts.exemptions = list.clone();
         }
 
-        apply_kill_switch(true, Some(cfg_str.as_str()))
+        apply_kill_switch(true, Some(cfg_str.as_str()), &ks_opts)
             .await
             .map_err(|e| {
                 emit_log(&app, &format!("[ui] Kill switch enable failed: {e}"));
@@ -1068,39 +2472,130 @@ async fn vpn_set_kill_switch(
         {
             let mut g = state.lock().await;
             g.kill_switch_enabled = true;
+            g.kill_switch_unknown = false;
+            g.kill_switch_exemptions = ks_opts.exemptions;
         }
+        save_persisted_state(&app, &state).await;
+        emit_kill_switch(&app, KillSwitchState::On);
 
         emit_log(&app, "[ui] Kill switch set: true");
+        log_kill_switch_backend(&app).await;
         return Ok(());
     }
 
-    apply_kill_switch(false, None).await.map_err(|e| {
-        emit_log(&app, &format!("[ui] Kill switch disable failed: {e}"));
-        e
-    })?;
+    apply_kill_switch(false, None, &KillSwitchOpts::default())
+        .await
+        .map_err(|e| {
+            emit_log(&app, &format!("[ui] Kill switch disable failed: {e}"));
+            e
+        })?;
 
     #[cfg(target_os = "linux")]
     {
-        if killswitch_table_exists().await {
-            return Err("Kill switch disable returned success, but nft table still exists (inet/stellarkillswitch). Refusing to lie.".to_string());
+        let st = killswitch_status()
+            .await
+            .map_err(|e| format!("Kill switch disable could not be verified: {e}"))?;
+        if st.active {
+            return Err("Kill switch disable returned success, but its firewall rules still exist. Refusing to lie.".to_string());
         }
     }
 
     {
         let mut g = state.lock().await;
         g.kill_switch_enabled = false;
+        g.kill_switch_unknown = false;
+        if let Some(list) = exemptions {
+            g.kill_switch_exemptions = list;
+        }
     }
+    save_persisted_state(&app, &state).await;
+    emit_kill_switch(&app, KillSwitchState::Off);
 
     emit_log(&app, "[ui] Kill switch set: false");
     Ok(())
 }
 
+/// Blocked-traffic counters, so users and support can see what the kill switch is doing.
+#[tauri::command]
+async fn vpn_kill_switch_stats() -> Result<KillSwitchStats, String> {
+    killswitch_stats().await
+}
+
 #[tauri::command]
 async fn vpn_kill_switch_enabled(state: tauri::State<'_, SharedState>) -> Result<bool, String> {
     let g = state.lock().await;
     Ok(g.kill_switch_enabled)
 }
 
+/// Like `vpn_kill_switch_enabled`, but says when the firewall could not be checked.
+#[tauri::command]
+async fn vpn_kill_switch_state(
+    state: tauri::State<'_, SharedState>,
+) -> Result<KillSwitchState, String> {
+    let g = state.lock().await;
+    Ok(KillSwitchState::of(&g))
+}
+
+#[tauri::command]
+async fn vpn_set_kill_switch_allow_lan(
+    app: AppHandle<RT>,
+    state: tauri::State<'_, SharedState>,
+    allow: bool,
+) -> Result<(), String> {
+    let op = { state.lock().await.kill_switch_op.clone() };
+    let _op = op.lock().await;
+
+    let (ks_enabled, cfg, prev, mut ks_opts) = {
+        let g = state.lock().await;
+        (
+            g.kill_switch_enabled,
+            g.last_config_path.clone(),
+            g.kill_switch_allow_lan,
+            g.kill_switch_opts(),
+        )
+    };
+
+    // Rules are built once, so an active kill switch has to be re-applied.
+    if ks_enabled && prev != allow {
+        let cfg = cfg.ok_or_else(|| {
+            "Kill switch is ON but no cached config is available to re-apply it.".to_string()
+        })?;
+        ks_opts.allow_lan = allow;
+        apply_kill_switch(true, Some(cfg.as_str()), &ks_opts)
+            .await
+            .map_err(|e| {
+                emit_log(&app, &format!("[ui] Kill switch re-apply failed: {e}"));
+                e
+            })?;
+    }
+
+    {
+        let mut g = state.lock().await;
+        g.kill_switch_allow_lan = allow;
+    }
+    save_persisted_state(&app, &state).await;
+
+    emit_log(&app, &format!("[ui] Kill switch allow LAN set: {allow}"));
+    Ok(())
+}
+
+#[tauri::command]
+async fn vpn_kill_switch_exemptions(
+    state: tauri::State<'_, SharedState>,
+) -> Result<Vec<KillSwitchExemption>, String> {
+    let g = state.lock().await;
+    Ok(g.kill_switch_exemptions
+        .iter()
+        .map(KillSwitchExemption::from)
+        .collect())
+}
+
+#[tauri::command]
+async fn vpn_kill_switch_allow_lan(state: tauri::State<'_, SharedState>) -> Result<bool, String> {
+    let g = state.lock().await;
+    Ok(g.kill_switch_allow_lan)
+}
+
 // ---------------- Main ----------------
 fn main() {
     let _ = fix_path_env::fix();
@@ -1124,18 +2619,19 @@ fn main() {
 
             update_tray_ui(&app.handle(), UiStatus::Disconnected);
 
+            tauri::async_runtime::spawn(reconcile_firewall(app.handle().clone(), state.clone()));
+            #[cfg(target_os = "linux")]
+            tauri::async_runtime::spawn(kill_switch_watchdog(app.handle().clone(), state.clone()));
+
             #[cfg(target_os = "macos")]
             {
-                // ✅ NEW: force correct socket path for subscriber too
-                std::env::set_var("STELLAR_VPN_HELPER_SOCKET", MACOS_HELPER_SOCKET);
-
                 // Install/start helper first (may prompt)
                 let handle = app.handle().clone();
                 if let Err(e) = macos_installer::ensure_root_helper_installed(&handle) {
                     eprintln!("[macos] ensure_root_helper_installed failed: {e}");
                 }
 
-                // Then start subscriber (it will read STELLAR_VPN_HELPER_SOCKET)
+                // Then start subscriber
                 let app_handle = app.handle().clone();
                 macos_helper::spawn_helper_subscriber(app_handle, state.clone());
             }
@@ -1169,8 +2665,14 @@ fn main() {
             vpn_connect,
             vpn_disconnect,
             vpn_status,
+            vpn_stats,
             vpn_set_kill_switch,
-            vpn_kill_switch_enabled
+            vpn_kill_switch_enabled,
+            vpn_kill_switch_state,
+            vpn_kill_switch_stats,
+            vpn_set_kill_switch_allow_lan,
+            vpn_kill_switch_allow_lan,
+            vpn_kill_switch_exemptions
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,624 @@
+// src-tauri/src/netmon.rs
+//
+// Linux network change monitor (rtnetlink, NETLINK_ROUTE).
+//
+// Subscribes to link and IPv4/IPv6 route notifications and reports changes of the
+// underlying network while a tunnel is up:
+// - a default route (main table, non-tunnel device) is added or removed
+// - a device that carries a default route goes down or disappears
+//
+// Our own tun/tap device and the routes OpenVPN installs (def1 uses /1 routes) are ignored,
+// so starting or stopping OpenVPN does not trigger a restart.
+//
+// If the socket fails, the monitor logs it, opens a new one and takes a fresh snapshot;
+// default routes that changed in between are reported as one change.
+//
+// Manual check: run the app in a network namespace with a veth pair as its uplink,
+// then `ip link set <veth> down` or replace the default route from the host side.
+
+use std::{
+    collections::{HashMap, HashSet},
+    io, mem,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    time::Duration,
+};
+
+use tokio::sync::mpsc;
+
+// <linux/netlink.h>, <linux/rtnetlink.h>, <linux/if_link.h>
+const NLMSG_HDRLEN: usize = 16;
+const NLMSG_ERROR: u16 = 2;
+const NLMSG_DONE: u16 = 3;
+const NLM_F_REQUEST: u16 = 0x1;
+const NLM_F_DUMP: u16 = 0x300;
+
+const RTM_NEWLINK: u16 = 16;
+const RTM_DELLINK: u16 = 17;
+const RTM_GETLINK: u16 = 18;
+const RTM_NEWROUTE: u16 = 24;
+const RTM_DELROUTE: u16 = 25;
+const RTM_GETROUTE: u16 = 26;
+
+const RTMGRP_LINK: u32 = 0x1;
+const RTMGRP_IPV4_ROUTE: u32 = 0x40;
+const RTMGRP_IPV6_ROUTE: u32 = 0x400;
+
+const IFINFOMSG_LEN: usize = 16;
+const RTMSG_LEN: usize = 12;
+
+const IFLA_IFNAME: u16 = 3;
+const RTA_GATEWAY: u16 = 5;
+const RTA_OIF: u16 = 4;
+const RTA_PRIORITY: u16 = 6;
+const RTA_TABLE: u16 = 15;
+
+const RT_TABLE_MAIN: u32 = 254;
+const RTN_UNICAST: u8 = 1;
+
+const IFF_UP: u32 = 0x1;
+const IFF_LOWER_UP: u32 = 0x10000;
+
+/// How often the reader wakes up to check whether the session is still listening.
+const RECV_TIMEOUT_SECS: libc::time_t = 1;
+/// Receive timeouts a dump may take before the snapshot is given up and retried.
+const DUMP_TIMEOUTS: u32 = 5;
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct DefaultRoute {
+    family: u8,
+    gateway: Option<IpAddr>,
+    oif: u32,
+    metric: u32,
+}
+
+#[derive(Debug, Clone)]
+struct Link {
+    name: String,
+    up: bool,
+}
+
+#[derive(Default)]
+struct NetState {
+    links: HashMap<u32, Link>,
+    defaults: HashSet<DefaultRoute>,
+}
+
+impl NetState {
+    fn dev_name(&self, index: u32) -> String {
+        match self.links.get(&index) {
+            Some(l) => l.name.clone(),
+            None => format!("if{index}"),
+        }
+    }
+
+    fn is_tunnel(&self, index: u32) -> bool {
+        let name = self.dev_name(index);
+        name.starts_with("tun") || name.starts_with("tap")
+    }
+
+    fn describe(&self, r: &DefaultRoute) -> String {
+        let dev = self.dev_name(r.oif);
+        match r.gateway {
+            Some(gw) => format!("default route via {gw} dev {dev}"),
+            None => format!("default route dev {dev}"),
+        }
+    }
+
+    /// Apply one netlink message. Returns a description if it changes the underlying network.
+    fn apply(&mut self, msg_type: u16, payload: &[u8]) -> Option<String> {
+        match msg_type {
+            RTM_NEWLINK | RTM_DELLINK => self.apply_link(msg_type == RTM_DELLINK, payload),
+            RTM_NEWROUTE | RTM_DELROUTE => self.apply_route(msg_type == RTM_DELROUTE, payload),
+            _ => None,
+        }
+    }
+
+    fn apply_link(&mut self, deleted: bool, payload: &[u8]) -> Option<String> {
+        if payload.len() < IFINFOMSG_LEN {
+            return None;
+        }
+        let index = u32::from_ne_bytes(payload[4..8].try_into().ok()?);
+        let flags = u32::from_ne_bytes(payload[8..12].try_into().ok()?);
+
+        let mut name = None;
+        for (ty, data) in attrs(&payload[IFINFOMSG_LEN..]) {
+            if ty == IFLA_IFNAME {
+                let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+                name = Some(String::from_utf8_lossy(&data[..end]).into_owned());
+            }
+        }
+
+        let carries_default = self.defaults.iter().any(|r| r.oif == index);
+        let was_up = self.links.get(&index).map(|l| l.up);
+
+        if deleted {
+            // The kernel does not always send RTM_DELROUTE for the routes of a removed link.
+            self.defaults.retain(|r| r.oif != index);
+            let link = self.links.remove(&index)?;
+            return carries_default.then(|| format!("link {} removed", link.name));
+        }
+
+        let up = flags & IFF_UP != 0 && flags & IFF_LOWER_UP != 0;
+        let name = name.unwrap_or_else(|| self.dev_name(index));
+        self.links.insert(
+            index,
+            Link {
+                name: name.clone(),
+                up,
+            },
+        );
+
+        (carries_default && was_up == Some(true) && !up).then(|| format!("link {name} went down"))
+    }
+
+    fn apply_route(&mut self, deleted: bool, payload: &[u8]) -> Option<String> {
+        if payload.len() < RTMSG_LEN {
+            return None;
+        }
+        let family = payload[0];
+        let dst_len = payload[1];
+        let mut table = payload[4] as u32;
+        let rtype = payload[7];
+
+        if dst_len != 0 || rtype != RTN_UNICAST {
+            return None;
+        }
+
+        let mut route = DefaultRoute {
+            family,
+            gateway: None,
+            oif: 0,
+            metric: 0,
+        };
+        for (ty, data) in attrs(&payload[RTMSG_LEN..]) {
+            match ty {
+                RTA_TABLE if data.len() >= 4 => {
+                    table = u32::from_ne_bytes(data[..4].try_into().ok()?)
+                }
+                RTA_OIF if data.len() >= 4 => {
+                    route.oif = u32::from_ne_bytes(data[..4].try_into().ok()?)
+                }
+                RTA_PRIORITY if data.len() >= 4 => {
+                    route.metric = u32::from_ne_bytes(data[..4].try_into().ok()?)
+                }
+                RTA_GATEWAY => {
+                    route.gateway = match data.len() {
+                        4 => Some(IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(data).ok()?))),
+                        16 => Some(IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(data).ok()?))),
+                        _ => None,
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if table != RT_TABLE_MAIN || self.is_tunnel(route.oif) {
+            return None;
+        }
+
+        let desc = self.describe(&route);
+        if deleted {
+            self.defaults
+                .remove(&route)
+                .then(|| format!("{desc} removed"))
+        } else {
+            self.defaults.insert(route).then(|| format!("{desc} added"))
+        }
+    }
+}
+
+/// Iterate rtattrs: (type, data).
+fn attrs(mut buf: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    std::iter::from_fn(move || {
+        if buf.len() < 4 {
+            return None;
+        }
+        let len = u16::from_ne_bytes([buf[0], buf[1]]) as usize;
+        let ty = u16::from_ne_bytes([buf[2], buf[3]]);
+        if len < 4 || len > buf.len() {
+            return None;
+        }
+        let data = &buf[4..len];
+        buf = &buf[align4(len).min(buf.len())..];
+        Some((ty, data))
+    })
+}
+
+fn align4(n: usize) -> usize {
+    (n + 3) & !3
+}
+
+// The hand-rolled netlink code below needs only libc (already a dependency): two dumps and
+// three message types do not justify pulling in the rtnetlink/netlink-packet crates.
+
+fn open_socket() -> io::Result<OwnedFd> {
+    // SAFETY: plain socket(2) call with constant arguments; no pointers involved.
+    let fd = unsafe {
+        libc::socket(
+            libc::AF_NETLINK,
+            libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+            libc::NETLINK_ROUTE,
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: `fd` was just returned by socket(2), is valid and owned by nothing else.
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    // SAFETY: sockaddr_nl is a plain C struct for which all-zero bytes are a valid value.
+    let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
+    addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+    addr.nl_groups = RTMGRP_LINK | RTMGRP_IPV4_ROUTE | RTMGRP_IPV6_ROUTE;
+    // SAFETY: `addr` is a live sockaddr_nl and the length passed is its size.
+    let rc = unsafe {
+        libc::bind(
+            fd.as_raw_fd(),
+            &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+            mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+        )
+    };
+    if rc < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let tv = libc::timeval {
+        tv_sec: RECV_TIMEOUT_SECS,
+        tv_usec: 0,
+    };
+    // SAFETY: `tv` is a live timeval and the length passed is its size.
+    let rc = unsafe {
+        libc::setsockopt(
+            fd.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_RCVTIMEO,
+            &tv as *const libc::timeval as *const libc::c_void,
+            mem::size_of::<libc::timeval>() as libc::socklen_t,
+        )
+    };
+    if rc < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(fd)
+}
+
+fn send_dump(fd: &OwnedFd, msg_type: u16, seq: u32) -> io::Result<()> {
+    // Header + an all-zero ifinfomsg/rtmsg (family AF_UNSPEC = everything).
+    let body_len = if msg_type == RTM_GETLINK {
+        IFINFOMSG_LEN
+    } else {
+        RTMSG_LEN
+    };
+    let total = NLMSG_HDRLEN + body_len;
+
+    let mut buf = vec![0u8; total];
+    buf[0..4].copy_from_slice(&(total as u32).to_ne_bytes());
+    buf[4..6].copy_from_slice(&msg_type.to_ne_bytes());
+    buf[6..8].copy_from_slice(&(NLM_F_REQUEST | NLM_F_DUMP).to_ne_bytes());
+    buf[8..12].copy_from_slice(&seq.to_ne_bytes());
+
+    // SAFETY: `buf` is valid for reads of `total` bytes (its length).
+    let n = unsafe {
+        libc::send(
+            fd.as_raw_fd(),
+            buf.as_ptr() as *const libc::c_void,
+            total,
+            0,
+        )
+    };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Read one datagram and apply every message in it.
+/// Returns whether the dump with sequence `dump_seq` has finished.
+fn read_batch(
+    fd: &OwnedFd,
+    buf: &mut [u8],
+    st: &mut NetState,
+    dump_seq: Option<u32>,
+    changes: &mut Vec<String>,
+) -> io::Result<bool> {
+    // SAFETY: `buf` is valid for writes of `buf.len()` bytes, and recv writes at most that.
+    let n = unsafe {
+        libc::recv(
+            fd.as_raw_fd(),
+            buf.as_mut_ptr() as *mut libc::c_void,
+            buf.len(),
+            0,
+        )
+    };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut rest = &buf[..n as usize];
+    let mut dump_done = false;
+
+    while rest.len() >= NLMSG_HDRLEN {
+        let len = u32::from_ne_bytes(rest[0..4].try_into().unwrap()) as usize;
+        let msg_type = u16::from_ne_bytes([rest[4], rest[5]]);
+        let seq = u32::from_ne_bytes(rest[8..12].try_into().unwrap());
+        if len < NLMSG_HDRLEN || len > rest.len() {
+            break;
+        }
+
+        match msg_type {
+            NLMSG_DONE | NLMSG_ERROR if Some(seq) == dump_seq => dump_done = true,
+            _ => {
+                if let Some(c) = st.apply(msg_type, &rest[NLMSG_HDRLEN..len]) {
+                    changes.push(c);
+                }
+            }
+        }
+
+        rest = &rest[align4(len).min(rest.len())..];
+    }
+
+    Ok(dump_done)
+}
+
+fn is_timeout(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::Interrupted
+    )
+}
+
+/// Links first (for device names), then routes. Nothing is reported for the snapshot itself.
+fn snapshot(fd: &OwnedFd, buf: &mut [u8]) -> io::Result<NetState> {
+    let mut st = NetState::default();
+    let mut changes = Vec::new();
+
+    for (seq, msg_type) in [(1, RTM_GETLINK), (2, RTM_GETROUTE)] {
+        send_dump(fd, msg_type, seq)?;
+        let mut timeouts = 0;
+        loop {
+            match read_batch(fd, buf, &mut st, Some(seq), &mut changes) {
+                Ok(true) => break,
+                Ok(false) => {}
+                Err(e) if is_timeout(&e) && timeouts < DUMP_TIMEOUTS => timeouts += 1,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+    Ok(st)
+}
+
+/// Start the monitor thread. Each received item describes one change of the underlying
+/// network; `log` gets socket errors and restarts. The thread exits once the receiver is
+/// dropped.
+pub fn spawn(
+    log: impl Fn(String) + Send + 'static,
+) -> Result<mpsc::UnboundedReceiver<String>, String> {
+    let fd = open_socket().map_err(|e| format!("Failed to open netlink socket: {e}"))?;
+    let (tx, rx) = mpsc::unbounded_channel::<String>();
+
+    std::thread::Builder::new()
+        .name("netmon".into())
+        .spawn(move || {
+            let mut buf = vec![0u8; 64 * 1024];
+            let mut changes = Vec::new();
+
+            // None while the socket is being reopened; `lost` holds the default routes
+            // known when it failed, to compare with the next snapshot.
+            let mut conn = match snapshot(&fd, &mut buf) {
+                Ok(st) => Some((fd, st)),
+                Err(e) => {
+                    log(format!("snapshot failed ({e}), retrying"));
+                    None
+                }
+            };
+            let mut lost: Option<HashSet<DefaultRoute>> = None;
+            let mut retry_logged = false;
+
+            while !tx.is_closed() {
+                match conn.as_mut() {
+                    Some((fd, st)) => {
+                        let failed = match read_batch(fd, &mut buf, st, None, &mut changes) {
+                            Ok(_) => None,
+                            Err(e) if is_timeout(&e) => None,
+                            // ENOBUFS: the kernel dropped notifications, so what we have may
+                            // be stale. Take a fresh snapshot and compare, as after a restart.
+                            Err(e) if e.raw_os_error() == Some(libc::ENOBUFS) => {
+                                match snapshot(fd, &mut buf) {
+                                    Ok(fresh) => {
+                                        if fresh.defaults != st.defaults {
+                                            changes.push("default routes changed".to_string());
+                                        }
+                                        *st = fresh;
+                                        None
+                                    }
+                                    Err(e) => Some(format!(
+                                        "resync after lost notifications failed ({e})"
+                                    )),
+                                }
+                            }
+                            Err(e) => Some(format!("read failed ({e})")),
+                        };
+                        if let Some(why) = failed {
+                            log(format!("{why}, restarting"));
+                            lost = Some(mem::take(&mut st.defaults));
+                            conn = None;
+                            retry_logged = false;
+                        }
+                    }
+                    None => {
+                        std::thread::sleep(RESTART_DELAY);
+                        let reopened = open_socket().and_then(|fd| {
+                            let st = snapshot(&fd, &mut buf)?;
+                            Ok((fd, st))
+                        });
+                        match reopened {
+                            Ok((fd, st)) => {
+                                log("restarted".to_string());
+                                if lost.take().is_some_and(|old| old != st.defaults) {
+                                    changes.push("default routes changed".to_string());
+                                }
+                                conn = Some((fd, st));
+                            }
+                            Err(e) if !retry_logged => {
+                                log(format!("restart failed ({e}), retrying"));
+                                retry_logged = true;
+                            }
+                            Err(_) => {}
+                        }
+                    }
+                }
+
+                for c in changes.drain(..) {
+                    if tx.send(c).is_err() {
+                        return;
+                    }
+                }
+            }
+        })
+        .map_err(|e| format!("Failed to start network monitor: {e}"))?;
+
+    Ok(rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AF_INET: u8 = 2;
+    const ETH0: u32 = 2;
+    const TUN0: u32 = 5;
+
+    fn attr(ty: u16, data: &[u8]) -> Vec<u8> {
+        let len = 4 + data.len();
+        let mut out = Vec::with_capacity(align4(len));
+        out.extend_from_slice(&(len as u16).to_ne_bytes());
+        out.extend_from_slice(&ty.to_ne_bytes());
+        out.extend_from_slice(data);
+        out.resize(align4(len), 0);
+        out
+    }
+
+    /// ifinfomsg + IFLA_IFNAME.
+    fn link(index: u32, up: bool, name: &str) -> Vec<u8> {
+        let flags = if up { IFF_UP | IFF_LOWER_UP } else { IFF_UP };
+        let mut p = vec![0u8; IFINFOMSG_LEN];
+        p[4..8].copy_from_slice(&index.to_ne_bytes());
+        p[8..12].copy_from_slice(&flags.to_ne_bytes());
+        p.extend(attr(IFLA_IFNAME, format!("{name}\0").as_bytes()));
+        p
+    }
+
+    /// IPv4 rtmsg with the given prefix length and table, gateway 192.0.2.1.
+    fn route(dst_len: u8, table: u32, oif: u32) -> Vec<u8> {
+        let mut p = vec![0u8; RTMSG_LEN];
+        p[0] = AF_INET;
+        p[1] = dst_len;
+        p[4] = table.min(255) as u8;
+        p[7] = RTN_UNICAST;
+        p.extend(attr(RTA_TABLE, &table.to_ne_bytes()));
+        p.extend(attr(RTA_GATEWAY, &[192, 0, 2, 1]));
+        p.extend(attr(RTA_OIF, &oif.to_ne_bytes()));
+        p.extend(attr(RTA_PRIORITY, &100u32.to_ne_bytes()));
+        p
+    }
+
+    fn default_via(oif: u32) -> Vec<u8> {
+        route(0, RT_TABLE_MAIN, oif)
+    }
+
+    /// eth0 up with a default route, tun0 up.
+    fn connected() -> NetState {
+        let mut st = NetState::default();
+        st.apply(RTM_NEWLINK, &link(ETH0, true, "eth0"));
+        st.apply(RTM_NEWLINK, &link(TUN0, true, "tun0"));
+        st.apply(RTM_NEWROUTE, &default_via(ETH0));
+        st
+    }
+
+    #[test]
+    fn reports_default_route_changes_once() {
+        let mut st = NetState::default();
+        st.apply(RTM_NEWLINK, &link(ETH0, true, "eth0"));
+        assert_eq!(
+            st.apply(RTM_NEWROUTE, &default_via(ETH0)).as_deref(),
+            Some("default route via 192.0.2.1 dev eth0 added")
+        );
+        assert_eq!(st.apply(RTM_NEWROUTE, &default_via(ETH0)), None);
+        assert_eq!(
+            st.apply(RTM_DELROUTE, &default_via(ETH0)).as_deref(),
+            Some("default route via 192.0.2.1 dev eth0 removed")
+        );
+        assert_eq!(st.apply(RTM_DELROUTE, &default_via(ETH0)), None);
+    }
+
+    #[test]
+    fn ignores_tunnel_and_non_default_routes() {
+        let mut st = connected();
+        // OpenVPN's def1 routes and anything on the tunnel device.
+        assert_eq!(st.apply(RTM_NEWROUTE, &route(1, RT_TABLE_MAIN, TUN0)), None);
+        assert_eq!(st.apply(RTM_NEWROUTE, &default_via(TUN0)), None);
+        assert_eq!(
+            st.apply(RTM_NEWROUTE, &route(24, RT_TABLE_MAIN, ETH0)),
+            None
+        );
+        // Policy routing tables other than main.
+        assert_eq!(st.apply(RTM_NEWROUTE, &route(0, 1000, ETH0)), None);
+        assert_eq!(st.defaults.len(), 1);
+        // Unrelated message types and truncated payloads.
+        assert_eq!(st.apply(RTM_GETROUTE, &default_via(ETH0)), None);
+        assert_eq!(st.apply(RTM_NEWROUTE, &[0u8; 4]), None);
+        assert_eq!(st.apply(RTM_NEWLINK, &[0u8; 4]), None);
+    }
+
+    #[test]
+    fn reports_the_uplink_going_down() {
+        let mut st = connected();
+        assert_eq!(st.apply(RTM_NEWLINK, &link(ETH0, true, "eth0")), None);
+        assert_eq!(
+            st.apply(RTM_NEWLINK, &link(ETH0, false, "eth0")).as_deref(),
+            Some("link eth0 went down")
+        );
+        // Down again, or a link without a default route: nothing new.
+        assert_eq!(st.apply(RTM_NEWLINK, &link(ETH0, false, "eth0")), None);
+        assert_eq!(st.apply(RTM_NEWLINK, &link(TUN0, false, "tun0")), None);
+    }
+
+    #[test]
+    fn removed_link_drops_its_default_routes() {
+        let mut st = connected();
+        assert_eq!(
+            st.apply(RTM_DELLINK, &link(ETH0, false, "eth0")).as_deref(),
+            Some("link eth0 removed")
+        );
+        assert!(st.defaults.is_empty());
+        assert!(!st.links.contains_key(&ETH0));
+
+        // The kernel's late DELROUTE is not a second change; a new uplink with the same
+        // index is a new default route.
+        assert_eq!(st.apply(RTM_DELROUTE, &default_via(ETH0)), None);
+        st.apply(RTM_NEWLINK, &link(ETH0, true, "eth1"));
+        assert_eq!(
+            st.apply(RTM_NEWROUTE, &default_via(ETH0)).as_deref(),
+            Some("default route via 192.0.2.1 dev eth1 added")
+        );
+    }
+
+    #[test]
+    fn removed_link_without_default_route_is_not_a_change() {
+        let mut st = connected();
+        assert_eq!(st.apply(RTM_DELLINK, &link(TUN0, false, "tun0")), None);
+        assert_eq!(st.apply(RTM_DELLINK, &link(42, false, "veth9")), None);
+        assert_eq!(st.defaults.len(), 1);
+    }
+
+    #[test]
+    fn attrs_stop_at_malformed_lengths() {
+        let mut buf = attr(IFLA_IFNAME, b"eth0\0");
+        buf.extend_from_slice(&[200, 0, 3, 0, 1, 2]);
+        let got: Vec<_> = attrs(&buf).collect();
+        assert_eq!(got, [(IFLA_IFNAME, &b"eth0\0"[..])]);
+    }
+}
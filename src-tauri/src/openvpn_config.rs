@@ -0,0 +1,965 @@
+// src-tauri/src/openvpn_config.rs
+//
+// OpenVPN config file model, shared by the app and the privileged helpers (the helpers
+// include this file via #[path], so it must stay std-only).
+//
+// - Tokenizes lines the way OpenVPN does (quotes, backslash escapes, comments).
+// - Keeps inline files (<ca>, <cert>, <tls-crypt>, ...) and <connection> blocks.
+// - Round-trips: unchanged lines are written back as they were read, so a config can be
+//   rewritten (e.g. remotes pinned to IPs) without disturbing anything else.
+
+use std::{fmt, net::IpAddr};
+
+pub const DEFAULT_PORT: u16 = 1194;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    Udp,
+    Tcp,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddrFamily {
+    Any,
+    V4,
+    V6,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Proto {
+    pub transport: Transport,
+    pub family: AddrFamily,
+}
+
+impl Default for Proto {
+    fn default() -> Self {
+        Proto {
+            transport: Transport::Udp,
+            family: AddrFamily::Any,
+        }
+    }
+}
+
+impl Proto {
+    /// `udp`, `tcp`, `udp4`, `tcp6`, `tcp-client`, `tcp4-client`, ...
+    pub fn parse(s: &str) -> Option<Proto> {
+        let s = s.to_ascii_lowercase();
+        let base = s
+            .strip_suffix("-client")
+            .or_else(|| s.strip_suffix("-server"))
+            .unwrap_or(&s);
+
+        let (transport, rest) = if let Some(r) = base.strip_prefix("udp") {
+            (Transport::Udp, r)
+        } else if let Some(r) = base.strip_prefix("tcp") {
+            (Transport::Tcp, r)
+        } else {
+            return None;
+        };
+        let family = match rest {
+            "" => AddrFamily::Any,
+            "4" => AddrFamily::V4,
+            "6" => AddrFamily::V6,
+            _ => return None,
+        };
+        Some(Proto { transport, family })
+    }
+
+    /// Used by the app's kill switch plan.
+    #[allow(dead_code)]
+    pub fn is_tcp(&self) -> bool {
+        self.transport == Transport::Tcp
+    }
+
+    /// Whether OpenVPN would use this address with this proto (`udp4` never talks to a v6 address).
+    pub fn allows(&self, ip: &IpAddr) -> bool {
+        match self.family {
+            AddrFamily::Any => true,
+            AddrFamily::V4 => ip.is_ipv4(),
+            AddrFamily::V6 => ip.is_ipv6(),
+        }
+    }
+}
+
+/// One server OpenVPN may connect to, with the port/proto it will actually use.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Remote {
+    pub host: String,
+    pub port: u16,
+    pub proto: Proto,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Directive {
+    /// Option name without a leading `--`.
+    pub name: String,
+    pub args: Vec<String>,
+    /// 1-based line in the parsed text (0 for directives added later).
+    pub line: usize,
+    // Line as read; None once the directive has been changed.
+    raw: Option<String>,
+}
+
+impl Directive {
+    pub fn new(name: &str, args: Vec<String>) -> Self {
+        Directive {
+            name: name.to_string(),
+            args,
+            line: 0,
+            raw: None,
+        }
+    }
+
+    pub fn arg(&self, i: usize) -> Option<&str> {
+        self.args.get(i).map(String::as_str)
+    }
+
+    fn render(&self) -> String {
+        if let Some(raw) = &self.raw {
+            return raw.clone();
+        }
+        let mut s = self.name.clone();
+        for a in &self.args {
+            s.push(' ');
+            s.push_str(&quote_arg(a));
+        }
+        s
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Item {
+    /// Blank line or comment, kept verbatim.
+    Text(String),
+    Directive(Directive),
+    /// Inline file such as `<ca>...</ca>`; `body` holds the lines in between.
+    Inline {
+        tag: String,
+        body: String,
+    },
+    /// `<connection>` block: a remote with its own transport options.
+    Connection(Vec<Item>),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OpenVpnConfig {
+    pub items: Vec<Item>,
+}
+
+impl OpenVpnConfig {
+    pub fn parse(text: &str) -> Result<OpenVpnConfig, String> {
+        let mut lines = text.lines().enumerate();
+        let mut top: Vec<Item> = Vec::new();
+        let mut connection: Option<Vec<Item>> = None;
+
+        while let Some((n, line)) = lines.next() {
+            let lineno = n + 1;
+            let t = line.trim();
+
+            if let Some(tag) = block_tag(t, false) {
+                if tag == "connection" {
+                    if connection.is_some() {
+                        return Err(format!("line {lineno}: nested <connection>"));
+                    }
+                    connection = Some(Vec::new());
+                    continue;
+                }
+
+                let mut body = String::new();
+                let mut closed = false;
+                for (_, l) in lines.by_ref() {
+                    if block_tag(l.trim(), true) == Some(tag) {
+                        closed = true;
+                        break;
+                    }
+                    body.push_str(l);
+                    body.push('\n');
+                }
+                if !closed {
+                    return Err(format!("line {lineno}: <{tag}> is not closed"));
+                }
+                let item = Item::Inline {
+                    tag: tag.to_string(),
+                    body,
+                };
+                connection.as_mut().unwrap_or(&mut top).push(item);
+                continue;
+            }
+
+            if let Some(tag) = block_tag(t, true) {
+                match connection.take() {
+                    Some(items) if tag == "connection" => top.push(Item::Connection(items)),
+                    _ => return Err(format!("line {lineno}: unexpected </{tag}>")),
+                }
+                continue;
+            }
+
+            let tokens = tokenize(line).map_err(|e| format!("line {lineno}: {e}"))?;
+            let item = match tokens.split_first() {
+                None => Item::Text(line.to_string()),
+                Some((name, args)) => Item::Directive(Directive {
+                    name: name.strip_prefix("--").unwrap_or(name).to_string(),
+                    args: args.to_vec(),
+                    line: lineno,
+                    raw: Some(line.to_string()),
+                }),
+            };
+            connection.as_mut().unwrap_or(&mut top).push(item);
+        }
+
+        if connection.is_some() {
+            return Err("<connection> is not closed".to_string());
+        }
+        Ok(OpenVpnConfig { items: top })
+    }
+
+    /// Something OpenVPN can connect with: at least one usable remote.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.remotes().is_empty() {
+            return Err("No usable 'remote' entries found in config".to_string());
+        }
+        Ok(())
+    }
+
+    /// Enforce the directive allowlist. OpenVPN runs as root (macOS helper, Linux daemon), so
+    /// a config must not be able to run scripts, load plugins, write files or make root read
+    /// files: key material is only accepted inline (`<ca>...</ca>`), never as a path.
+    pub fn check_allowed(&self) -> Result<(), String> {
+        let nested = self.items.iter().flat_map(|item| match item {
+            Item::Connection(inner) => inner.as_slice(),
+            _ => &[],
+        });
+        for item in self.items.iter().chain(nested) {
+            if let Item::Inline { tag, .. } = item {
+                if !ALLOWED_INLINE.contains(&tag.as_str()) {
+                    return Err(format!("inline <{tag}> block is not allowed"));
+                }
+            }
+        }
+
+        for d in self.directives() {
+            // `setenv opt X ...` is OpenVPN's "optional X", so X itself has to pass.
+            let (name, args) = match (d.name.as_str(), d.arg(0)) {
+                ("setenv", Some("opt")) => match d.args.get(1) {
+                    Some(n) => (n.as_str(), &d.args[2..]),
+                    None => continue,
+                },
+                (n, _) => (n, &d.args[..]),
+            };
+
+            if ALLOWED_INLINE.contains(&name) && name != "auth-user-pass" {
+                return Err(format!(
+                    "line {}: '{name}' must be an inline <{name}> block, not a file: {}",
+                    d.line,
+                    d.render().trim()
+                ));
+            }
+            let allowed = ALLOWED_DIRECTIVES.contains(&name)
+                // A file argument would have root read it and send it to the server.
+                && (name != "auth-user-pass" || args.is_empty());
+            if !allowed {
+                return Err(format!(
+                    "line {}: '{name}' is not allowed: {}",
+                    d.line,
+                    d.render().trim()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// All directives, including those inside <connection> blocks.
+    pub fn directives(&self) -> Vec<&Directive> {
+        let mut out = Vec::new();
+        for item in &self.items {
+            match item {
+                Item::Directive(d) => out.push(d),
+                Item::Connection(inner) => out.extend(inner.iter().filter_map(as_directive)),
+                _ => {}
+            }
+        }
+        out
+    }
+
+    /// Whether the config itself sets up IPv6 in the tunnel (`tun-ipv6`, `ifconfig-ipv6`,
+    /// `route-ipv6` or `redirect-gateway ... ipv6`, also inside <connection> blocks).
+    pub fn has_ipv6(&self) -> bool {
+        self.directives().iter().any(|d| match d.name.as_str() {
+            "tun-ipv6" | "ifconfig-ipv6" | "route-ipv6" => true,
+            "redirect-gateway" => d.args.iter().any(|a| a == "ipv6"),
+            _ => false,
+        })
+    }
+
+    /// `--redirect-gateway` for our command line. IPv6 is only redirected when the config
+    /// sets it up; a tunnel without it gets the IPv6 block instead, even if the server
+    /// pushes an address later. Used wherever OpenVPN is started (not by the Linux app).
+    #[allow(dead_code)]
+    pub fn redirect_gateway_args(&self) -> Vec<&'static str> {
+        let mut args = vec!["--redirect-gateway", "def1"];
+        if self.has_ipv6() {
+            args.push("ipv6");
+        }
+        args
+    }
+
+    /// Every remote, in config order, with the port and proto OpenVPN resolves for it:
+    /// the remote's own arguments, then its <connection> block, then the global options.
+    pub fn remotes(&self) -> Vec<Remote> {
+        let global = Defaults::default().overlay(&self.items);
+        let mut out = Vec::new();
+        for item in &self.items {
+            match item {
+                Item::Directive(d) if d.name == "remote" => out.extend(global.remote(d)),
+                Item::Connection(inner) => {
+                    let conn = global.overlay(inner);
+                    out.extend(
+                        inner
+                            .iter()
+                            .filter_map(as_directive)
+                            .filter(|d| d.name == "remote")
+                            .filter_map(|d| conn.remote(d)),
+                    );
+                }
+                _ => {}
+            }
+        }
+        out
+    }
+
+    /// Replace hostname remotes by the addresses `lookup` returns for them (one remote, or one
+    /// <connection> block, per address usable with the remote's proto, keeping port and
+    /// proto). Returns whether anything changed; hosts `lookup` knows nothing about are left
+    /// alone. Only the app pins remotes, before handing the config on.
+    #[allow(dead_code)]
+    pub fn pin_remotes(&mut self, lookup: impl Fn(&str) -> Option<Vec<IpAddr>>) -> bool {
+        let global = Defaults::default().overlay(&self.items);
+        let pinned = |d: &Directive, defaults: &Defaults| -> Option<Vec<Directive>> {
+            if d.name != "remote" {
+                return None;
+            }
+            let remote = defaults.remote(d)?;
+            if remote.host.parse::<IpAddr>().is_ok() {
+                return None;
+            }
+            let mut ips = lookup(&remote.host)?;
+            ips.retain(|ip| remote.proto.allows(ip));
+            if ips.is_empty() {
+                return None;
+            }
+            Some(
+                ips.iter()
+                    .map(|ip| {
+                        let mut args = d.args.clone();
+                        args[0] = ip.to_string();
+                        Directive::new("remote", args)
+                    })
+                    .collect(),
+            )
+        };
+
+        let mut changed = false;
+        let mut out = Vec::with_capacity(self.items.len());
+        for item in self.items.drain(..) {
+            match item {
+                Item::Directive(d) => match pinned(&d, &global) {
+                    Some(ds) => {
+                        changed = true;
+                        out.extend(ds.into_iter().map(Item::Directive));
+                    }
+                    None => out.push(Item::Directive(d)),
+                },
+                Item::Connection(inner) => {
+                    // A <connection> holds exactly one remote, so clone the block per address.
+                    let conn = global.overlay(&inner);
+                    let found = inner.iter().enumerate().find_map(|(i, item)| {
+                        as_directive(item)
+                            .and_then(|d| pinned(d, &conn))
+                            .map(|ds| (i, ds))
+                    });
+                    match found {
+                        Some((i, ds)) => {
+                            changed = true;
+                            for d in ds {
+                                let mut block = inner.clone();
+                                block[i] = Item::Directive(d);
+                                out.push(Item::Connection(block));
+                            }
+                        }
+                        None => out.push(Item::Connection(inner)),
+                    }
+                }
+                other => out.push(other),
+            }
+        }
+        self.items = out;
+        changed
+    }
+}
+
+impl fmt::Display for OpenVpnConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn write_items(f: &mut fmt::Formatter<'_>, items: &[Item]) -> fmt::Result {
+            for item in items {
+                match item {
+                    Item::Text(t) => writeln!(f, "{t}")?,
+                    Item::Directive(d) => writeln!(f, "{}", d.render())?,
+                    Item::Inline { tag, body } => write!(f, "<{tag}>\n{body}</{tag}>\n")?,
+                    Item::Connection(inner) => {
+                        writeln!(f, "<connection>")?;
+                        write_items(f, inner)?;
+                        writeln!(f, "</connection>")?;
+                    }
+                }
+            }
+            Ok(())
+        }
+        write_items(f, &self.items)
+    }
+}
+
+/// Client options a provider config may use. Everything else is rejected, notably scripts
+/// (`up`, `down`, `route-up`, `tls-verify`, `learn-address`, `script-security`, ...),
+/// `plugin`, `engine`/`providers`, includes (`config`, `cd`), the management interface and
+/// anything that writes files (`log`, `status`, `writepid`).
+const ALLOWED_DIRECTIVES: &[&str] = &[
+    // connection
+    "client",
+    "pull",
+    "tls-client",
+    "dev",
+    "dev-type",
+    "proto",
+    "proto-force",
+    "remote",
+    "remote-random",
+    "remote-random-hostname",
+    "port",
+    "rport",
+    "lport",
+    "bind",
+    "nobind",
+    "float",
+    "resolv-retry",
+    "connect-retry",
+    "connect-retry-max",
+    "connect-timeout",
+    "server-poll-timeout",
+    "explicit-exit-notify",
+    "persist-key",
+    "persist-tun",
+    "persist-local-ip",
+    "persist-remote-ip",
+    "keepalive",
+    "ping",
+    "ping-restart",
+    "ping-exit",
+    "inactive",
+    "reneg-sec",
+    "reneg-bytes",
+    "reneg-pkts",
+    "hand-window",
+    "tran-window",
+    "replay-window",
+    "mute-replay-warnings",
+    "push-peer-info",
+    "ignore-unknown-option",
+    "setenv",
+    "setenv-safe",
+    // crypto (key files only inline, see ALLOWED_INLINE)
+    "key-direction",
+    "remote-cert-tls",
+    "remote-cert-eku",
+    "remote-cert-ku",
+    "ns-cert-type",
+    "verify-x509-name",
+    "verify-hash",
+    "tls-version-min",
+    "tls-version-max",
+    "tls-cipher",
+    "tls-ciphersuites",
+    "tls-groups",
+    "tls-cert-profile",
+    "tls-timeout",
+    "ecdh-curve",
+    "cipher",
+    "data-ciphers",
+    "data-ciphers-fallback",
+    "ncp-ciphers",
+    "ncp-disable",
+    "auth",
+    "auth-user-pass",
+    "auth-nocache",
+    "auth-retry",
+    // compression / MTU
+    "comp-lzo",
+    "compress",
+    "allow-compression",
+    "tun-mtu",
+    "tun-mtu-extra",
+    "link-mtu",
+    "mssfix",
+    "fragment",
+    "mtu-disc",
+    "sndbuf",
+    "rcvbuf",
+    "txqueuelen",
+    "fast-io",
+    // routing
+    "redirect-gateway",
+    "redirect-private",
+    "route",
+    "route-ipv6",
+    "route-gateway",
+    "route-metric",
+    "route-delay",
+    "route-method",
+    "route-nopull",
+    "pull-filter",
+    "allow-pull-fqdn",
+    "allow-recursive-routing",
+    "topology",
+    "tun-ipv6",
+    "block-ipv6",
+    "dhcp-option",
+    "block-outside-dns",
+    "register-dns",
+    "ip-win32",
+    "windows-driver",
+    // logging
+    "verb",
+    "mute",
+    "machine-readable-output",
+];
+
+/// Inline files that only carry key material or credentials. As directives these all take a
+/// file name (`ca /etc/shadow`), so they are only accepted in this form.
+const ALLOWED_INLINE: &[&str] = &[
+    "ca",
+    "cert",
+    "key",
+    "pkcs12",
+    "extra-certs",
+    "tls-auth",
+    "tls-crypt",
+    "tls-crypt-v2",
+    "secret",
+    "auth-user-pass",
+];
+
+fn as_directive(item: &Item) -> Option<&Directive> {
+    match item {
+        Item::Directive(d) => Some(d),
+        _ => None,
+    }
+}
+
+/// Port/proto in effect at some level of the config (global or a <connection> block).
+#[derive(Debug, Clone, Copy, Default)]
+struct Defaults {
+    port: Option<u16>,
+    proto: Proto,
+}
+
+impl Defaults {
+    fn overlay(self, items: &[Item]) -> Defaults {
+        let mut d = self;
+        for dir in items.iter().filter_map(as_directive) {
+            match (dir.name.as_str(), dir.arg(0)) {
+                ("proto", Some(p)) => d.proto = Proto::parse(p).unwrap_or(d.proto),
+                ("port" | "rport", Some(p)) => d.port = p.parse().ok().or(d.port),
+                _ => {}
+            }
+        }
+        d
+    }
+
+    fn remote(&self, d: &Directive) -> Option<Remote> {
+        let host = d.arg(0)?.to_string();
+        let port = match d.arg(1) {
+            Some(p) => p.parse().ok()?,
+            None => self.port.unwrap_or(DEFAULT_PORT),
+        };
+        let proto = match d.arg(2) {
+            Some(p) => Proto::parse(p)?,
+            None => self.proto,
+        };
+        Some(Remote { host, port, proto })
+    }
+}
+
+/// `<tag>` (or `</tag>` when `closing`) on a line of its own.
+fn block_tag(line: &str, closing: bool) -> Option<&str> {
+    let inner = line.strip_prefix('<')?.strip_suffix('>')?;
+    let tag = if closing {
+        inner.strip_prefix('/')?
+    } else {
+        inner
+    };
+    let valid = !tag.is_empty()
+        && tag
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'));
+    valid.then_some(tag)
+}
+
+/// Split a config line into tokens like OpenVPN's parse_line(): whitespace separates,
+/// "..." groups with backslash escapes, '...' groups literally, and a token starting with
+/// `#` or `;` begins a comment.
+fn tokenize(line: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        match chars.peek() {
+            None | Some('#') | Some(';') => break,
+            _ => {}
+        }
+
+        let mut tok = String::new();
+        while let Some(c) = chars.next() {
+            match c {
+                c if c.is_whitespace() => break,
+                '\\' => tok.push(chars.next().ok_or("trailing backslash")?),
+                '"' => loop {
+                    match chars.next().ok_or("unterminated double quote")? {
+                        '"' => break,
+                        '\\' => tok.push(chars.next().ok_or("unterminated double quote")?),
+                        c => tok.push(c),
+                    }
+                },
+                '\'' => loop {
+                    match chars.next().ok_or("unterminated single quote")? {
+                        '\'' => break,
+                        c => tok.push(c),
+                    }
+                },
+                c => tok.push(c),
+            }
+        }
+        tokens.push(tok);
+    }
+
+    Ok(tokens)
+}
+
+fn quote_arg(a: &str) -> String {
+    let plain = !a.is_empty()
+        && !a.starts_with(['#', ';'])
+        && !a
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, '"' | '\'' | '\\'));
+    if plain {
+        return a.to_string();
+    }
+    let mut s = String::from("\"");
+    for c in a.chars() {
+        if matches!(c, '"' | '\\') {
+            s.push('\\');
+        }
+        s.push(c);
+    }
+    s.push('"');
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "\
+# provider config
+client
+dev tun
+proto udp
+remote vpn.example.com 1194
+remote 198.51.100.7 443 tcp
+verb 3 ; trailing comment
+
+<ca>
+-----BEGIN CERTIFICATE-----
+MIIB
+-----END CERTIFICATE-----
+</ca>
+<connection>
+remote backup.example.com
+proto tcp-client
+port 8443
+</connection>
+key-direction 1
+<tls-auth>
+-----BEGIN OpenVPN Static key V1-----
+abcd
+-----END OpenVPN Static key V1-----
+</tls-auth>
+";
+
+    fn parse(text: &str) -> OpenVpnConfig {
+        OpenVpnConfig::parse(text).unwrap()
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    /// Last top-level occurrence of an option.
+    fn global<'a>(cfg: &'a OpenVpnConfig, name: &str) -> Option<&'a Directive> {
+        cfg.items
+            .iter()
+            .filter_map(as_directive)
+            .rfind(|d| d.name == name)
+    }
+
+    fn inline<'a>(cfg: &'a OpenVpnConfig, tag: &str) -> Option<&'a str> {
+        cfg.items.iter().find_map(|item| match item {
+            Item::Inline { tag: t, body } if t == tag => Some(body.as_str()),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn round_trips_unchanged() {
+        let cfg = parse(CONFIG);
+        assert_eq!(cfg.to_string(), CONFIG);
+        assert_eq!(parse(&cfg.to_string()), cfg);
+    }
+
+    #[test]
+    fn round_trips_changed_directives() {
+        let mut cfg = parse(CONFIG);
+        cfg.items
+            .retain(|item| !matches!(item, Item::Directive(d) if d.name == "verb"));
+        cfg.items.push(Item::Directive(Directive::new(
+            "setenv",
+            vec!["X".into(), "a b\"c".into()],
+        )));
+        let text = cfg.to_string();
+        assert!(text.contains("setenv X \"a b\\\"c\"\n"), "{text}");
+        assert!(!text.contains("verb"));
+
+        // Line numbers differ after a rewrite, nothing else does.
+        let strip = |c: &OpenVpnConfig| {
+            c.directives()
+                .iter()
+                .map(|d| (d.name.clone(), d.args.clone()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(strip(&parse(&text)), strip(&cfg));
+    }
+
+    #[test]
+    fn keeps_inline_blocks() {
+        let cfg = parse(CONFIG);
+        assert_eq!(
+            inline(&cfg, "ca"),
+            Some("-----BEGIN CERTIFICATE-----\nMIIB\n-----END CERTIFICATE-----\n")
+        );
+        assert!(inline(&cfg, "tls-auth").unwrap().contains("abcd"));
+        assert_eq!(inline(&cfg, "cert"), None);
+        // Directive-looking lines inside a block are not directives.
+        assert!(!cfg.directives().iter().any(|d| d.name.starts_with("-----")));
+    }
+
+    #[test]
+    fn rejects_broken_blocks() {
+        assert!(OpenVpnConfig::parse("<ca>\nabc\n").is_err());
+        assert!(OpenVpnConfig::parse("</connection>\n").is_err());
+        assert!(OpenVpnConfig::parse("<connection>\nremote a\n").is_err());
+        assert!(OpenVpnConfig::parse("<connection>\n<connection>\n").is_err());
+    }
+
+    #[test]
+    fn connection_blocks_hold_their_own_options() {
+        let cfg = parse(CONFIG);
+        let Some(Item::Connection(inner)) =
+            cfg.items.iter().find(|i| matches!(i, Item::Connection(_)))
+        else {
+            panic!("no <connection> block");
+        };
+        assert_eq!(inner.len(), 3);
+        assert!(
+            global(&cfg, "port").is_none(),
+            "block options are not global"
+        );
+        assert_eq!(global(&cfg, "proto").and_then(|d| d.arg(0)), Some("udp"));
+    }
+
+    #[test]
+    fn tokenizes_like_openvpn() {
+        let t = |s: &str| tokenize(s).unwrap();
+        assert_eq!(t("remote  host   1194"), ["remote", "host", "1194"]);
+        assert_eq!(t("setenv A \"two words\""), ["setenv", "A", "two words"]);
+        assert_eq!(t(r#"x "a \"q\" \\ b""#), ["x", r#"a "q" \ b"#]);
+        assert_eq!(t(r"x 'no \escapes'"), ["x", r"no \escapes"]);
+        assert_eq!(t(r"x a\ b"), ["x", "a b"]);
+        assert_eq!(t("x y # comment"), ["x", "y"]);
+        assert_eq!(t("x y ; comment"), ["x", "y"]);
+        assert_eq!(t("x y#z"), ["x", "y#z"]);
+        assert!(t("   # only a comment").is_empty());
+        assert!(tokenize("x \"open").is_err());
+        assert!(tokenize("x 'open").is_err());
+        assert!(tokenize("x \\").is_err());
+    }
+
+    #[test]
+    fn quote_arg_round_trips() {
+        for a in [
+            "plain",
+            "two words",
+            "q\"uote",
+            "back\\slash",
+            "#hash",
+            "",
+            "it's",
+        ] {
+            let line = format!("x {}", quote_arg(a));
+            assert_eq!(tokenize(&line).unwrap(), ["x", a], "{line}");
+        }
+    }
+
+    #[test]
+    fn resolves_remotes_with_their_port_and_proto() {
+        let cfg = parse(CONFIG);
+        let udp = Proto::parse("udp").unwrap();
+        let tcp = Proto::parse("tcp").unwrap();
+        assert_eq!(
+            cfg.remotes(),
+            [
+                Remote {
+                    host: "vpn.example.com".into(),
+                    port: 1194,
+                    proto: udp
+                },
+                Remote {
+                    host: "198.51.100.7".into(),
+                    port: 443,
+                    proto: tcp
+                },
+                Remote {
+                    host: "backup.example.com".into(),
+                    port: 8443,
+                    proto: tcp
+                },
+            ]
+        );
+        assert!(cfg.validate().is_ok());
+        assert!(parse("client\n").validate().is_err());
+    }
+
+    #[test]
+    fn global_port_and_proto_apply_to_bare_remotes() {
+        let cfg = parse("port 53\nproto tcp4\nremote a\nremote b 80 udp6\n");
+        let r = cfg.remotes();
+        assert_eq!((r[0].port, r[0].proto), (53, Proto::parse("tcp4").unwrap()));
+        assert_eq!((r[1].port, r[1].proto), (80, Proto::parse("udp6").unwrap()));
+    }
+
+    #[test]
+    fn redirects_ipv6_only_when_the_config_sets_it_up() {
+        let args = |text: &str| parse(text).redirect_gateway_args();
+        assert_eq!(args(CONFIG), ["--redirect-gateway", "def1"]);
+        assert_eq!(
+            args("remote a\nredirect-gateway def1\n"),
+            ["--redirect-gateway", "def1"]
+        );
+        for text in [
+            "remote a\ntun-ipv6\n",
+            "remote a\nroute-ipv6 2000::/3\n",
+            "remote a\nredirect-gateway def1 ipv6\n",
+            "<connection>\nremote a\ntun-ipv6\n</connection>\n",
+        ] {
+            assert_eq!(args(text), ["--redirect-gateway", "def1", "ipv6"], "{text}");
+        }
+    }
+
+    #[test]
+    fn pins_hostnames_to_addresses_the_proto_can_use() {
+        let mut cfg =
+            parse("proto udp4\nremote vpn.example.com 1194\nremote unknown.example.com\n");
+        let changed = cfg.pin_remotes(|host| {
+            (host == "vpn.example.com")
+                .then(|| vec![ip("192.0.2.1"), ip("2001:db8::1"), ip("192.0.2.2")])
+        });
+        assert!(changed);
+        let hosts: Vec<String> = cfg.remotes().into_iter().map(|r| r.host).collect();
+        // udp4 never talks to the v6 address; unknown hosts stay as they are.
+        assert_eq!(hosts, ["192.0.2.1", "192.0.2.2", "unknown.example.com"]);
+        assert!(cfg.to_string().contains("remote 192.0.2.1 1194\n"));
+    }
+
+    #[test]
+    fn pins_connection_blocks_by_cloning_them() {
+        let mut cfg = parse("<connection>\nremote vpn.example.com 443 tcp\n</connection>\n");
+        assert!(cfg.pin_remotes(|_| Some(vec![ip("192.0.2.1"), ip("192.0.2.2")])));
+        let blocks = cfg
+            .items
+            .iter()
+            .filter(|i| matches!(i, Item::Connection(_)))
+            .count();
+        assert_eq!(blocks, 2);
+        assert!(cfg
+            .remotes()
+            .iter()
+            .all(|r| r.port == 443 && r.proto.is_tcp()));
+
+        // Addresses are left alone.
+        let mut pinned = cfg.clone();
+        assert!(!pinned.pin_remotes(|_| Some(vec![ip("203.0.113.9")])));
+        assert_eq!(pinned, cfg);
+    }
+
+    #[test]
+    fn allows_a_plain_client_config() {
+        assert_eq!(parse(CONFIG).check_allowed(), Ok(()));
+        assert_eq!(
+            parse("auth-user-pass\n<auth-user-pass>\nu\np\n</auth-user-pass>\n").check_allowed(),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn rejects_disallowed_directives() {
+        let rejected = [
+            "up /tmp/x.sh",
+            "script-security 2",
+            "plugin /tmp/evil.so",
+            "config /etc/other.conf",
+            "log /etc/passwd",
+            "management 127.0.0.1 7505",
+            "auth-user-pass /root/secret",
+            "setenv opt up /tmp/x.sh",
+            "--route-up /tmp/x.sh",
+        ];
+        for line in rejected {
+            let err = parse(&format!("client\n{line}\n"))
+                .check_allowed()
+                .unwrap_err();
+            assert!(err.starts_with("line 2: "), "{line}: {err}");
+        }
+    }
+
+    #[test]
+    fn rejects_key_files_and_unknown_blocks() {
+        for name in [
+            "ca",
+            "cert",
+            "key",
+            "pkcs12",
+            "extra-certs",
+            "tls-auth",
+            "tls-crypt",
+            "tls-crypt-v2",
+            "secret",
+        ] {
+            let err = parse(&format!("{name} /etc/shadow\n"))
+                .check_allowed()
+                .unwrap_err();
+            assert!(err.contains("inline"), "{name}: {err}");
+        }
+        let err = parse("<connection>\n<up>\nx\n</up>\n</connection>\n")
+            .check_allowed()
+            .unwrap_err();
+        assert_eq!(err, "inline <up> block is not allowed");
+    }
+}
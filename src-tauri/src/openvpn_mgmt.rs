@@ -0,0 +1,569 @@
+// src-tauri/src/openvpn_mgmt.rs
+//
+// Minimal client for the OpenVPN management interface.
+// Shared by the app (Windows session), the Linux daemon and the macOS root helper. Each
+// uses part of it: the processes that run OpenVPN drive it, the app interprets its events.
+//
+// OpenVPN is started with `--management <sock> unix --management-hold --management-up-down`,
+// so it waits for us before connecting. We then enable the notifications we care about and
+// release the hold:
+// - >STATE:     connection state machine (CONNECTED, RECONNECTING, EXITING, ...)
+// - >BYTECOUNT: traffic counters
+// - >PASSWORD:  auth prompts / "Verification Failed"
+// - >LOG:       log lines (replaces scraping stdout)
+// - >UPDOWN:    tunnel up/down with the script environment (device, pushed options)
+//
+// This keeps connection detection independent from human-readable log wording.
+
+use std::{ffi::OsString, net::IpAddr, path::Path, time::Duration};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{unix::OwnedWriteHalf, UnixStream},
+    sync::mpsc,
+    time,
+};
+
+/// Parsed `>STATE:` notification.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct StateInfo {
+    pub time: u64,
+    /// OpenVPN state name, e.g. CONNECTING, WAIT, AUTH, GET_CONFIG, ASSIGN_IP, CONNECTED, EXITING.
+    pub name: String,
+    /// Optional description, e.g. SUCCESS, auth-failure, ping-restart.
+    pub desc: String,
+    pub local_ip: Option<String>,
+    pub remote_ip: Option<String>,
+    pub remote_port: Option<u16>,
+    /// Tunnel IPv6 address (only present when the server pushed IPv6).
+    pub local_ipv6: Option<String>,
+}
+
+impl StateInfo {
+    pub fn is_connected(&self) -> bool {
+        self.name == "CONNECTED"
+    }
+
+    pub fn is_auth_failure(&self) -> bool {
+        self.desc == "auth-failure"
+    }
+}
+
+/// One line received from the management socket. Serializable so stellar-vpn-daemon can
+/// forward it to the app.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MgmtEvent {
+    State(StateInfo),
+    ByteCount {
+        bytes_in: u64,
+        bytes_out: u64,
+    },
+    /// Raw text after `>PASSWORD:`.
+    Password(String),
+    Log {
+        flags: String,
+        message: String,
+    },
+    Hold(String),
+    /// `>UPDOWN:UP`/`DOWN` with the `>UPDOWN:ENV,name=value` lines that follow it.
+    UpDown {
+        up: bool,
+        env: Vec<(String, String)>,
+    },
+    Fatal(String),
+    Info(String),
+    /// Command reply `SUCCESS: ...`
+    Success(String),
+    /// Command reply `ERROR: ...`
+    Error(String),
+    /// Anything else (multi-line command output, unknown notifications).
+    Other(String),
+}
+
+impl MgmtEvent {
+    pub fn parse(line: &str) -> MgmtEvent {
+        let line = line.trim_end_matches(['\r', '\n']);
+
+        if let Some(rest) = line.strip_prefix(">STATE:") {
+            return MgmtEvent::State(parse_state(rest));
+        }
+        if let Some(rest) = line.strip_prefix(">BYTECOUNT:") {
+            let mut it = rest.split(',');
+            let bytes_in = it.next().and_then(|s| s.trim().parse().ok()).unwrap_or(0);
+            let bytes_out = it.next().and_then(|s| s.trim().parse().ok()).unwrap_or(0);
+            return MgmtEvent::ByteCount {
+                bytes_in,
+                bytes_out,
+            };
+        }
+        if let Some(rest) = line.strip_prefix(">PASSWORD:") {
+            return MgmtEvent::Password(rest.to_string());
+        }
+        if let Some(rest) = line.strip_prefix(">LOG:") {
+            // >LOG:{time},{flags},{message}
+            let mut it = rest.splitn(3, ',');
+            let _time = it.next();
+            let flags = it.next().unwrap_or("").to_string();
+            let message = it.next().unwrap_or("").to_string();
+            return MgmtEvent::Log { flags, message };
+        }
+        if let Some(rest) = line.strip_prefix(">HOLD:") {
+            return MgmtEvent::Hold(rest.to_string());
+        }
+        if let Some(rest) = line.strip_prefix(">FATAL:") {
+            return MgmtEvent::Fatal(rest.to_string());
+        }
+        if let Some(rest) = line.strip_prefix(">INFO:") {
+            return MgmtEvent::Info(rest.to_string());
+        }
+        if let Some(rest) = line.strip_prefix("SUCCESS:") {
+            return MgmtEvent::Success(rest.trim().to_string());
+        }
+        if let Some(rest) = line.strip_prefix("ERROR:") {
+            return MgmtEvent::Error(rest.trim().to_string());
+        }
+
+        MgmtEvent::Other(line.to_string())
+    }
+
+    /// True for `>PASSWORD:Verification Failed: ...` and `>STATE:...,EXITING,auth-failure`.
+    #[allow(dead_code)]
+    pub fn is_auth_failure(&self) -> bool {
+        match self {
+            MgmtEvent::Password(p) => p.starts_with("Verification Failed"),
+            MgmtEvent::State(s) => s.is_auth_failure(),
+            _ => false,
+        }
+    }
+}
+
+fn parse_state(rest: &str) -> StateInfo {
+    // >STATE:{time},{state},{desc},{local tun ip},{remote ip},{remote port},{local addr},{local port},{local ipv6}
+    let f: Vec<&str> = rest.split(',').collect();
+    let opt = |i: usize| {
+        f.get(i)
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+    };
+
+    StateInfo {
+        time: f.first().and_then(|s| s.trim().parse().ok()).unwrap_or(0),
+        name: opt(1).unwrap_or_default(),
+        desc: opt(2).unwrap_or_default(),
+        local_ip: opt(3),
+        remote_ip: opt(4),
+        remote_port: opt(5).and_then(|s| s.parse().ok()),
+        local_ipv6: opt(8),
+    }
+}
+
+/// Map an OpenVPN `>STATE:` name to the state name used in `vpn-status` payloads.
+#[allow(dead_code)]
+pub fn ui_state_name(openvpn_state: &str) -> Option<&'static str> {
+    Some(match openvpn_state {
+        "RESOLVE" => "resolving",
+        "CONNECTING" | "WAIT" | "TCP_CONNECT" => "connecting",
+        "AUTH" | "AUTH_PENDING" => "authenticating",
+        "GET_CONFIG" => "getting_config",
+        "ASSIGN_IP" | "ADD_ROUTES" => "assigning_ip",
+        "CONNECTED" => "connected",
+        "RECONNECTING" => "reconnecting",
+        "EXITING" => "disconnecting",
+        _ => return None,
+    })
+}
+
+/// Tunnel device name from OpenVPN's "device opened" log line
+/// (`TUN/TAP device tun0 opened`, `DCO device tun0 opened`, `Opened utun device utun3`).
+#[allow(dead_code)]
+pub fn tun_device_from_log(message: &str) -> Option<&str> {
+    let name = if let Some(rest) = message.strip_prefix("Opened utun device ") {
+        rest
+    } else {
+        message
+            .strip_prefix("TUN/TAP device ")
+            .or_else(|| message.strip_prefix("DCO device "))?
+            .strip_suffix(" opened")?
+    };
+    let name = name.trim();
+    (!name.is_empty() && !name.contains(char::is_whitespace)).then_some(name)
+}
+
+/// Value of `name` in an `>UPDOWN:` environment.
+#[allow(dead_code)]
+pub fn env_var<'a>(env: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    env.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str())
+}
+
+/// Resolvers pushed by the server, from an `>UPDOWN:UP` environment: `dhcp-option DNS`/`DNS6`
+/// (as `foreign_option_N`) and the 2.6 `dns server` option (as `dns_server_N_address_M`).
+#[allow(dead_code)]
+pub fn pushed_dns(env: &[(String, String)]) -> Vec<IpAddr> {
+    let mut out: Vec<IpAddr> = Vec::new();
+    for (name, value) in env {
+        let ip = if name.starts_with("foreign_option_") {
+            let mut it = value.split_whitespace();
+            match (it.next(), it.next(), it.next()) {
+                (Some("dhcp-option"), Some("DNS" | "DNS6"), Some(ip)) => ip.parse().ok(),
+                _ => None,
+            }
+        } else if name.starts_with("dns_server_") && name.contains("_address_") {
+            value.parse().ok()
+        } else {
+            None
+        };
+        if let Some(ip) = ip.filter(|ip| !out.contains(ip)) {
+            out.push(ip);
+        }
+    }
+    out
+}
+
+/// Extra OpenVPN arguments that open the management socket at `sock`, keep OpenVPN on hold
+/// until we release it and report tunnel up/down through it.
+#[allow(dead_code)]
+pub fn openvpn_args(sock: &Path) -> Vec<OsString> {
+    vec![
+        "--management".into(),
+        sock.as_os_str().to_owned(),
+        "unix".into(),
+        "--management-hold".into(),
+        "--management-up-down".into(),
+    ]
+}
+
+/// Turns management lines into events, folding the multi-line `>UPDOWN:` notification into
+/// one [`MgmtEvent::UpDown`].
+#[derive(Debug, Default)]
+pub struct EventReader {
+    updown: Option<(bool, Vec<(String, String)>)>,
+}
+
+impl EventReader {
+    /// Feed one line; None while an `>UPDOWN:` environment is still being collected.
+    pub fn feed(&mut self, line: &str) -> Option<MgmtEvent> {
+        let line = line.trim_end_matches(['\r', '\n']);
+        let Some(rest) = line.strip_prefix(">UPDOWN:") else {
+            return Some(MgmtEvent::parse(line));
+        };
+
+        match rest.strip_prefix("ENV,") {
+            Some("END") => {
+                let (up, env) = self.updown.take()?;
+                Some(MgmtEvent::UpDown { up, env })
+            }
+            Some(var) => {
+                if let (Some((_, env)), Some((k, v))) = (self.updown.as_mut(), var.split_once('='))
+                {
+                    env.push((k.to_string(), v.to_string()));
+                }
+                None
+            }
+            None => {
+                self.updown = Some((rest == "UP", Vec::new()));
+                None
+            }
+        }
+    }
+}
+
+/// Writer side of a management connection. Notifications are delivered through the
+/// receiver returned by [`MgmtClient::connect`]. Not used by the Linux app, whose OpenVPN the
+/// daemon runs.
+#[derive(Debug)]
+#[allow(dead_code)]
+pub struct MgmtClient {
+    writer: OwnedWriteHalf,
+}
+
+#[allow(dead_code)]
+impl MgmtClient {
+    /// Connect to the management socket, retrying until OpenVPN has created it.
+    pub async fn connect(
+        sock: &Path,
+        timeout: Duration,
+    ) -> Result<(MgmtClient, mpsc::UnboundedReceiver<MgmtEvent>), String> {
+        let deadline = time::Instant::now() + timeout;
+
+        let stream = loop {
+            match UnixStream::connect(sock).await {
+                Ok(s) => break s,
+                Err(e) => {
+                    if time::Instant::now() >= deadline {
+                        return Err(format!(
+                            "Failed to connect to OpenVPN management socket {}: {e}",
+                            sock.display()
+                        ));
+                    }
+                    time::sleep(Duration::from_millis(100)).await;
+                }
+            }
+        };
+
+        let (read_half, writer) = stream.into_split();
+        let (tx, rx) = mpsc::unbounded_channel::<MgmtEvent>();
+
+        tokio::spawn(async move {
+            let mut r = BufReader::new(read_half).lines();
+            let mut events = EventReader::default();
+            while let Ok(Some(line)) = r.next_line().await {
+                let Some(ev) = events.feed(&line) else {
+                    continue;
+                };
+                if tx.send(ev).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok((MgmtClient { writer }, rx))
+    }
+
+    pub async fn command(&mut self, cmd: &str) -> Result<(), String> {
+        self.writer
+            .write_all(format!("{cmd}\n").as_bytes())
+            .await
+            .map_err(|e| format!("management write failed ({cmd}): {e}"))
+    }
+
+    /// Enable state/log notifications and byte counters (every `bytecount_secs`, 0 = off).
+    pub async fn subscribe(&mut self, bytecount_secs: u32) -> Result<(), String> {
+        self.command("state on").await?;
+        self.command("log on").await?;
+        self.command(&format!("bytecount {bytecount_secs}")).await
+    }
+
+    pub async fn hold_release(&mut self) -> Result<(), String> {
+        self.command("hold release").await
+    }
+
+    /// Send a signal through the management interface (SIGTERM, SIGHUP, SIGUSR1, ...).
+    pub async fn signal(&mut self, sig: &str) -> Result<(), String> {
+        self.command(&format!("signal {sig}")).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(message: &str) -> MgmtEvent {
+        MgmtEvent::Log {
+            flags: "I".to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn parses_state_lines() {
+        let ev = MgmtEvent::parse(
+            ">STATE:1700000000,CONNECTED,SUCCESS,10.8.0.6,198.51.100.7,1194,192.168.1.20,43122,fd00:8::1000\r\n",
+        );
+        assert_eq!(
+            ev,
+            MgmtEvent::State(StateInfo {
+                time: 1700000000,
+                name: "CONNECTED".to_string(),
+                desc: "SUCCESS".to_string(),
+                local_ip: Some("10.8.0.6".to_string()),
+                remote_ip: Some("198.51.100.7".to_string()),
+                remote_port: Some(1194),
+                local_ipv6: Some("fd00:8::1000".to_string()),
+            })
+        );
+
+        // IPv4-only tunnel: the IPv6 field is empty.
+        let MgmtEvent::State(st) =
+            MgmtEvent::parse(">STATE:1700000000,CONNECTED,SUCCESS,10.8.0.6,198.51.100.7,1194,,,")
+        else {
+            panic!("not a state");
+        };
+        assert!(st.is_connected());
+        assert_eq!(st.local_ipv6, None);
+
+        let MgmtEvent::State(st) = MgmtEvent::parse(">STATE:1700000001,WAIT,,,,,,") else {
+            panic!("not a state");
+        };
+        assert_eq!(
+            (st.name.as_str(), st.local_ip, st.remote_port),
+            ("WAIT", None, None)
+        );
+
+        let ev = MgmtEvent::parse(">STATE:1700000002,EXITING,auth-failure,,,,,");
+        assert!(ev.is_auth_failure());
+    }
+
+    #[test]
+    fn parses_notifications_and_replies() {
+        let cases = [
+            (
+                ">BYTECOUNT:123456,7890",
+                MgmtEvent::ByteCount {
+                    bytes_in: 123456,
+                    bytes_out: 7890,
+                },
+            ),
+            (
+                ">HOLD:Waiting for hold release:0",
+                MgmtEvent::Hold("Waiting for hold release:0".to_string()),
+            ),
+            (
+                ">LOG:1700000000,I,TUN/TAP device tun0 opened",
+                log("TUN/TAP device tun0 opened"),
+            ),
+            (
+                ">LOG:1700000000,I,PUSH: Received control message: 'PUSH_REPLY,route-gateway 10.8.0.1,ifconfig 10.8.0.6 255.255.255.0,dhcp-option DNS 10.8.0.1'",
+                log("PUSH: Received control message: 'PUSH_REPLY,route-gateway 10.8.0.1,ifconfig 10.8.0.6 255.255.255.0,dhcp-option DNS 10.8.0.1'"),
+            ),
+            (
+                ">PASSWORD:Need 'Auth' username/password",
+                MgmtEvent::Password("Need 'Auth' username/password".to_string()),
+            ),
+            (
+                ">FATAL:Cannot open TUN/TAP dev /dev/net/tun: No such file or directory (errno=2)",
+                MgmtEvent::Fatal(
+                    "Cannot open TUN/TAP dev /dev/net/tun: No such file or directory (errno=2)"
+                        .to_string(),
+                ),
+            ),
+            (
+                ">INFO:OpenVPN Management Interface Version 5 -- type 'help' for more info",
+                MgmtEvent::Info(
+                    "OpenVPN Management Interface Version 5 -- type 'help' for more info"
+                        .to_string(),
+                ),
+            ),
+            (
+                "SUCCESS: hold release succeeded",
+                MgmtEvent::Success("hold release succeeded".to_string()),
+            ),
+            (
+                "ERROR: unknown command, enter 'help' for more options",
+                MgmtEvent::Error("unknown command, enter 'help' for more options".to_string()),
+            ),
+            ("END", MgmtEvent::Other("END".to_string())),
+        ];
+        for (line, want) in cases {
+            assert_eq!(MgmtEvent::parse(line), want, "{line}");
+        }
+    }
+
+    #[test]
+    fn detects_auth_failures() {
+        assert!(MgmtEvent::parse(">PASSWORD:Verification Failed: 'Auth'").is_auth_failure());
+        assert!(!MgmtEvent::parse(">PASSWORD:Need 'Auth' username/password").is_auth_failure());
+        assert!(!MgmtEvent::parse(">STATE:1,RECONNECTING,ping-restart,,,,,").is_auth_failure());
+    }
+
+    #[test]
+    fn finds_the_tunnel_device_in_log_lines() {
+        let cases = [
+            ("TUN/TAP device tun0 opened", Some("tun0")),
+            ("TUN/TAP device tap-stellar opened", Some("tap-stellar")),
+            ("DCO device tun1 opened", Some("tun1")),
+            ("Opened utun device utun3", Some("utun3")),
+            (
+                "DCO device tun1 already exists, won't be destroyed at shutdown",
+                None,
+            ),
+            (
+                "Cannot open DCO device tun0: Operation not permitted (1)",
+                None,
+            ),
+            ("TUN/TAP device  opened", None),
+            ("TUN/TAP device tun0 opened extra", None),
+            (
+                "PUSH: Received control message: 'PUSH_REPLY,dhcp-option DNS 10.8.0.1'",
+                None,
+            ),
+            ("Initialization Sequence Completed", None),
+        ];
+        for (message, want) in cases {
+            assert_eq!(tun_device_from_log(message), want, "{message}");
+        }
+    }
+
+    fn feed(lines: &[&str]) -> Vec<MgmtEvent> {
+        let mut r = EventReader::default();
+        lines.iter().filter_map(|l| r.feed(l)).collect()
+    }
+
+    fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn folds_updown_environment_into_one_event() {
+        let events = feed(&[
+            ">LOG:1700000000,I,TUN/TAP device tun0 opened",
+            ">UPDOWN:UP\r\n",
+            ">UPDOWN:ENV,dev=tun0",
+            ">UPDOWN:ENV,foreign_option_1=dhcp-option DNS 10.8.0.1",
+            ">UPDOWN:ENV,foreign_option_2=dhcp-option DOMAIN vpn.example",
+            ">UPDOWN:ENV,script_type=up",
+            ">UPDOWN:ENV,END",
+            ">STATE:1700000001,CONNECTED,SUCCESS,10.8.0.6,198.51.100.7,1194,,,",
+        ]);
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0], log("TUN/TAP device tun0 opened"));
+        assert_eq!(
+            events[1],
+            MgmtEvent::UpDown {
+                up: true,
+                env: env(&[
+                    ("dev", "tun0"),
+                    ("foreign_option_1", "dhcp-option DNS 10.8.0.1"),
+                    ("foreign_option_2", "dhcp-option DOMAIN vpn.example"),
+                    ("script_type", "up"),
+                ]),
+            }
+        );
+        assert!(matches!(events[2], MgmtEvent::State(_)));
+    }
+
+    #[test]
+    fn updown_down_and_stray_env_lines() {
+        let events = feed(&[
+            ">UPDOWN:ENV,dev=tun0",
+            ">UPDOWN:ENV,END",
+            ">UPDOWN:DOWN",
+            ">UPDOWN:ENV,dev=tun0",
+            ">UPDOWN:ENV,END",
+        ]);
+        assert_eq!(
+            events,
+            [MgmtEvent::UpDown {
+                up: false,
+                env: env(&[("dev", "tun0")]),
+            }]
+        );
+    }
+
+    #[test]
+    fn pushed_dns_from_updown_environment() {
+        let vars = env(&[
+            ("dev", "tun0"),
+            ("foreign_option_1", "dhcp-option DNS 10.8.0.1"),
+            ("foreign_option_2", "dhcp-option DNS6 fd00:8::1"),
+            ("foreign_option_3", "dhcp-option DOMAIN vpn.example"),
+            ("foreign_option_4", "dhcp-option DNS not-an-ip"),
+            ("dns_server_0_address_1", "10.8.0.1"),
+            ("dns_server_0_address_2", "10.8.0.2"),
+            ("dns_server_0_port_1", "53"),
+            ("dns_search_domain_1", "vpn.example"),
+            ("ifconfig_local", "10.8.0.6"),
+        ]);
+        let want: Vec<IpAddr> = ["10.8.0.1", "fd00:8::1", "10.8.0.2"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        assert_eq!(pushed_dns(&vars), want);
+        assert_eq!(env_var(&vars, "dev"), Some("tun0"));
+        assert_eq!(env_var(&vars, "tun"), None);
+        assert!(pushed_dns(&env(&[("dev", "tun0")])).is_empty());
+    }
+}
@@ -0,0 +1,212 @@
+// src-tauri/src/peer_auth.rs
+//
+// Who may use a root helper socket. The sockets are mode 0666 so the app can reach them
+// without setup; the peer credentials the kernel reports for each connection (SO_PEERCRED
+// on Linux, getpeereid on macOS) decide instead. Shared by stellar-vpn-daemon and the macOS
+// root helper (included via #[path]).
+//
+// Allowed: root, the user at the console, and members of the group given with
+// `--allow-group`. A session belongs to the uid that started it; other users cannot stop,
+// replace or watch it.
+
+use std::fmt;
+
+use nix::unistd::{Group, Uid, User};
+use tokio::net::UnixStream;
+
+/// Credentials of the process on the other end of a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Peer {
+    pub uid: u32,
+    pub gid: u32,
+    pub pid: Option<i32>,
+}
+
+impl Peer {
+    pub fn of(stream: &UnixStream) -> std::io::Result<Self> {
+        let cred = stream.peer_cred()?;
+        Ok(Peer {
+            uid: cred.uid(),
+            gid: cred.gid(),
+            pid: cred.pid(),
+        })
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.uid == 0
+    }
+
+    /// Whether this peer may act on a session started by `owner`.
+    pub fn may_control(&self, owner: u32) -> bool {
+        self.is_root() || self.uid == owner
+    }
+}
+
+impl fmt::Display for Peer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "uid={} gid={}", self.uid, self.gid)?;
+        if let Some(pid) = self.pid {
+            write!(f, " pid={pid}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PeerPolicy {
+    /// Members of this group are allowed besides the console user.
+    pub allow_group: Option<String>,
+}
+
+impl PeerPolicy {
+    pub fn new(allow_group: Option<String>) -> Self {
+        PeerPolicy { allow_group }
+    }
+
+    /// Ok when `peer` may use the socket; Err says why not, for the log.
+    pub fn check(&self, peer: &Peer) -> Result<(), String> {
+        self.check_with(peer, console_uid())
+    }
+
+    fn check_with(&self, peer: &Peer, console: Option<u32>) -> Result<(), String> {
+        if peer.is_root() || console == Some(peer.uid) {
+            return Ok(());
+        }
+        match &self.allow_group {
+            Some(group) if in_group(peer, group) => Ok(()),
+            Some(group) => Err(format!("not the console user and not in group '{group}'")),
+            None => Err("not the console user".to_string()),
+        }
+    }
+}
+
+/// Looked up per connection, so membership changes apply without a restart.
+fn in_group(peer: &Peer, group: &str) -> bool {
+    let Ok(Some(g)) = Group::from_name(group) else {
+        return false;
+    };
+    if g.gid.as_raw() == peer.gid {
+        return true;
+    }
+    match User::from_uid(Uid::from_raw(peer.uid)) {
+        Ok(Some(u)) => u.gid == g.gid || g.mem.contains(&u.name),
+        _ => false,
+    }
+}
+
+/// The user logged in at the screen: the owner of /dev/console (root at the login window).
+#[cfg(target_os = "macos")]
+pub fn console_uid() -> Option<u32> {
+    use std::os::unix::fs::MetadataExt;
+
+    let uid = std::fs::metadata("/dev/console").ok()?.uid();
+    (uid != 0).then_some(uid)
+}
+
+/// The user of the active session on seat0, as systemd-logind records it.
+#[cfg(not(target_os = "macos"))]
+pub fn console_uid() -> Option<u32> {
+    let seat = std::fs::read_to_string("/run/systemd/seats/seat0").ok()?;
+    seat.lines()
+        .find_map(|l| l.strip_prefix("ACTIVE_UID="))?
+        .trim()
+        .parse()
+        .ok()
+        .filter(|&uid| uid != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nix::unistd::Gid;
+
+    // Nobody on a test machine has these.
+    const STRANGER_UID: u32 = 0x7fff_fff0;
+    const STRANGER_GID: u32 = 0x7fff_fff1;
+
+    fn user(uid: u32) -> Peer {
+        Peer {
+            uid,
+            gid: STRANGER_GID,
+            pid: None,
+        }
+    }
+
+    fn current_group() -> String {
+        Group::from_gid(Gid::current())
+            .unwrap()
+            .expect("current gid has a group entry")
+            .name
+    }
+
+    #[tokio::test]
+    async fn peer_credentials_of_a_socket_pair() {
+        let (a, _b) = UnixStream::pair().unwrap();
+        let peer = Peer::of(&a).unwrap();
+        assert_eq!(peer.uid, Uid::current().as_raw());
+        assert_eq!(peer.gid, Gid::current().as_raw());
+        assert_eq!(peer.pid, Some(std::process::id() as i32));
+
+        // Allowed through the group of the current process, whoever runs the tests.
+        let policy = PeerPolicy::new(Some(current_group()));
+        assert_eq!(policy.check_with(&peer, None), Ok(()));
+
+        // Same socket, claimed by someone else: not root, not at the console, not in the group.
+        let stranger = Peer {
+            uid: STRANGER_UID,
+            gid: STRANGER_GID,
+            ..peer
+        };
+        let err = policy.check_with(&stranger, None).unwrap_err();
+        assert!(err.contains(&current_group()), "{err}");
+    }
+
+    #[test]
+    fn root_is_always_allowed() {
+        let root = Peer {
+            uid: 0,
+            gid: 0,
+            pid: None,
+        };
+        assert_eq!(PeerPolicy::default().check_with(&root, None), Ok(()));
+        assert_eq!(PeerPolicy::default().check_with(&root, Some(1000)), Ok(()));
+    }
+
+    #[test]
+    fn console_user_is_allowed() {
+        let policy = PeerPolicy::default();
+        assert_eq!(policy.check_with(&user(1000), Some(1000)), Ok(()));
+        assert_eq!(
+            policy.check_with(&user(1001), Some(1000)),
+            Err("not the console user".to_string())
+        );
+        assert_eq!(
+            policy.check_with(&user(1000), None),
+            Err("not the console user".to_string())
+        );
+    }
+
+    #[test]
+    fn allow_group_admits_members_only() {
+        let member = Peer {
+            gid: Gid::current().as_raw(),
+            ..user(STRANGER_UID)
+        };
+        let policy = PeerPolicy::new(Some(current_group()));
+        assert_eq!(policy.check_with(&member, None), Ok(()));
+        assert!(policy.check_with(&user(STRANGER_UID), None).is_err());
+
+        let missing = PeerPolicy::new(Some("stellar-no-such-group".to_string()));
+        assert_eq!(
+            missing.check_with(&member, None),
+            Err("not the console user and not in group 'stellar-no-such-group'".to_string())
+        );
+    }
+
+    #[test]
+    fn only_root_and_the_owner_control_a_session() {
+        assert!(user(1000).may_control(1000));
+        assert!(!user(1001).may_control(1000));
+        assert!(user(0).may_control(1000));
+    }
+}
@@ -186,8 +186,8 @@ export const ChangeLocation: React.FC = () => {
     const ks = await invoke<boolean>("vpn_kill_switch_enabled").catch(() => false);
     if (!ks) return trimmed;
 
-    const st = await invoke<string>("vpn_status").catch(() => "unknown");
-    if (st !== "connected") {
+    const st = await invoke<{ status: string }>("vpn_status").catch(() => null);
+    if (st?.status !== "connected") {
       throw new Error(
           "Kill switch er slået til, og VPN er ikke forbundet. Du kan kun skifte server mens VPN er forbundet (så vi kan hente config via tunnelen), ellers skal du slå kill switch fra midlertidigt."
       );
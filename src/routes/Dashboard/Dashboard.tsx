@@ -29,12 +29,34 @@ const CONNECT_TIMEOUT_MS = 10_000;
 
 type UiStatus = "disconnected" | "connecting" | "connected";
 
+// Payload of the backend "vpn-status" event and the vpn_status command
+type VpnStatusPayload = {
+  state: string;
+  status: UiStatus;
+  label: string;
+  reason?: string;
+  sid: number | null;
+  server?: {
+    // "tunnel" | "blocked" (IPv4-only tunnel)
+    ipv6?: string | null;
+  } | null;
+  since_ms: number;
+  ts_ms: number;
+};
+
+const isStatusPayload = (s: unknown): s is VpnStatusPayload =>
+    typeof s === "object" && s !== null && "state" in s && "status" in s;
+
 const normalizeStatus = (s: unknown): UiStatus | null => {
+  if (isStatusPayload(s)) return normalizeStatus(s.status);
   if (typeof s !== "string") return null;
   if (s === "connected" || s === "connecting" || s === "disconnected") return s;
   return null;
 };
 
+const failureReason = (s: unknown): string | null =>
+    isStatusPayload(s) && s.state === "failed" ? s.reason ?? "Connection failed" : null;
+
 // Local persisted flags (preferences, not secrets)
 const LS_MANUAL_DISABLED = "vpn_manual_disabled";
 const LS_HAS_CONNECTED_ONCE = "vpn_has_connected_once";
@@ -108,6 +130,8 @@ export const Dashboard: React.FC = () => {
   const [vpnLogs, setVpnLogs] = useState<string[]>([]);
   const [showLogs, setShowLogs] = useState(false);
   const [connectError, setConnectError] = useState<string | null>(null);
+  const [statusLabel, setStatusLabel] = useState<string | null>(null);
+  const [ipv6Mode, setIpv6Mode] = useState<string | null>(null);
   const [listenersReady, setListenersReady] = useState(false);
 
   const [showExpiredModal, setShowExpiredModal] = useState(false);
@@ -245,19 +269,22 @@ export const Dashboard: React.FC = () => {
     if (!isTauri()) return;
 
     try {
-      const s = await invoke<string>("vpn_status");
-      const ui = normalizeStatus(s);
+      const s = await invoke<VpnStatusPayload>("vpn_status");
+      const reason = failureReason(s);
 
-      if (ui) {
-        setStatus(ui);
+      if (reason) {
+        console.error("VPN backend error:", reason);
+        setConnectError(reason);
+        setShowLogs(true);
+        setStatus("disconnected");
         return;
       }
 
-      if (typeof s === "string" && s.startsWith("error")) {
-        console.error("VPN backend error:", s);
-        setConnectError(s);
-        setShowLogs(true);
-        setStatus("disconnected");
+      const ui = normalizeStatus(s);
+      if (ui) {
+        setStatusLabel(s.label);
+        setIpv6Mode(s.server?.ipv6 ?? null);
+        setStatus(ui);
       }
     } catch (e) {
       console.warn("vpn_status sync failed:", e);
@@ -454,11 +481,22 @@ export const Dashboard: React.FC = () => {
     let unlistenLog: (() => void) | undefined;
 
     (async () => {
-      unlistenStatus = await listen<string>("vpn-status", (event) => {
+      unlistenStatus = await listen<VpnStatusPayload>("vpn-status", (event) => {
         const s = event.payload;
 
+        const reason = failureReason(s);
+        if (reason) {
+          console.error("VPN error:", reason);
+          setConnectError(reason);
+          setShowLogs(true);
+          setStatus("disconnected");
+          return;
+        }
+
         const ui = normalizeStatus(s);
         if (ui) {
+          setStatusLabel(s.label);
+          setIpv6Mode(s.server?.ipv6 ?? null);
           setStatus(ui);
 
           if (ui === "connected") {
@@ -466,15 +504,6 @@ export const Dashboard: React.FC = () => {
             setHasConnectedOnce(true);
             setManualDisabled(false);
           }
-
-          return;
-        }
-
-        if (typeof s === "string" && s.startsWith("error")) {
-          console.error("VPN error:", s);
-          setConnectError(s);
-          setShowLogs(true);
-          setStatus("disconnected");
         }
       });
 
@@ -644,7 +673,7 @@ export const Dashboard: React.FC = () => {
 
         if (isExpired) return;
 
-        const backend = await invoke<string>("vpn_status").catch(() => "");
+        const backend = await invoke<VpnStatusPayload>("vpn_status").catch(() => null);
         const backendUi = normalizeStatus(backend);
         const current = backendUi ?? statusRef.current;
 
@@ -686,7 +715,7 @@ export const Dashboard: React.FC = () => {
 
         if (isExpired) return;
 
-        const backend = await invoke<string>("vpn_status").catch(() => "");
+        const backend = await invoke<VpnStatusPayload>("vpn_status").catch(() => null);
         const backendUi = normalizeStatus(backend) ?? statusRef.current;
         if (backendUi !== "disconnected") return;
 
@@ -908,7 +937,7 @@ export const Dashboard: React.FC = () => {
                     <span>Secured connection</span>
                   </>
               ) : isConnecting ? (
-                  <span>Connecting...</span>
+                  <span>{statusLabel ?? "Connecting..."}</span>
               ) : (
                   <>
                     <img
@@ -920,6 +949,11 @@ export const Dashboard: React.FC = () => {
                   </>
               )}
             </div>
+            {isConnected && ipv6Mode === "blocked" && (
+                <div className="mt-2 text-[11px] text-white/80">
+                  IPv4-only tunnel · IPv6 blocked
+                </div>
+            )}
           </div>
 
           {/* Center circle */}
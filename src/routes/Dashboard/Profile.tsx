@@ -11,6 +11,7 @@ import {
   getSelectedServer,
 } from "../../services/api";
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 
 const isTauri = () =>
     typeof window !== "undefined" && "__TAURI_INTERNALS__" in window;
@@ -60,6 +61,9 @@ export const Profile: React.FC = () => {
 
   const [autoConnect, setAutoConnectState] = useState(true);
   const [killSwitch, setKillSwitch] = useState(false);
+  // Backend could not check the firewall (service not running); killSwitch is the saved setting.
+  const [killSwitchUnknown, setKillSwitchUnknown] = useState(false);
+  const [allowLan, setAllowLan] = useState(false);
 
   const [accountNumber, setAccountNumber] = useState<string | null>(null);
   const [deviceName, setDeviceName] = useState<string | null>(null);
@@ -92,6 +96,10 @@ export const Profile: React.FC = () => {
         try {
           const ks = await invoke<boolean>("vpn_kill_switch_enabled");
           setKillSwitch(Boolean(ks));
+          const ksState = await invoke<string>("vpn_kill_switch_state");
+          setKillSwitchUnknown(ksState === "unknown");
+          const lan = await invoke<boolean>("vpn_kill_switch_allow_lan");
+          setAllowLan(Boolean(lan));
         } catch {
           // ignore
         }
@@ -101,6 +109,28 @@ export const Profile: React.FC = () => {
     loadData();
   }, []);
 
+  // Backend reconciles the kill switch with the firewall at startup and may change it.
+  useEffect(() => {
+    if (!isTauri()) return;
+
+    let unlisten: (() => void) | undefined;
+    let unlistenState: (() => void) | undefined;
+
+    (async () => {
+      unlisten = await listen<boolean>("vpn-kill-switch", (e) => {
+        setKillSwitch(Boolean(e.payload));
+      });
+      unlistenState = await listen<string>("vpn-kill-switch-state", (e) => {
+        setKillSwitchUnknown(e.payload === "unknown");
+      });
+    })();
+
+    return () => {
+      if (unlisten) unlisten();
+      if (unlistenState) unlistenState();
+    };
+  }, []);
+
   const expiresDays = subscription?.days_remaining;
   const expiresStatus: ExpiresStatus =
       expiresDays === undefined || expiresDays === null
@@ -203,6 +233,21 @@ export const Profile: React.FC = () => {
     }
   };
 
+  const toggleAllowLan = async () => {
+    const next = !allowLan;
+    setAllowLan(next);
+
+    if (!isTauri()) return;
+
+    try {
+      await invoke("vpn_set_kill_switch_allow_lan", { allow: next });
+    } catch (e: any) {
+      console.error("Allow LAN error (raw):", e);
+      setAllowLan(!next);
+      alert(`Allow local network failed:\n\n${typeof e === "string" ? e : e?.message ?? String(e)}`);
+    }
+  };
+
   return (
       <AuthShell title="Profile" onBack={() => navigate("/dashboard")}>
         <div className="space-y-4 flex-1 flex flex-col">
@@ -325,6 +370,11 @@ export const Profile: React.FC = () => {
                 <span className="text-[11px] text-[#62626A] mt-1">
                 Blocks internet when VPN is down.
               </span>
+                {killSwitchUnknown && (
+                    <span className="text-[11px] text-[#D14343] mt-1">
+                    State unknown: the Stellar VPN service is not running.
+                  </span>
+                )}
               </div>
 
               <button
@@ -346,6 +396,36 @@ export const Profile: React.FC = () => {
               </button>
             </div>
 
+            <div className="flex items-center justify-between text-sm mb-6 pb-6 border-b border-[#EAEAF0]">
+              <div className="flex flex-col">
+              <span className="text-[14px] font-semibold text-[#0B0C19] flex items-center gap-2">
+                <img src="/icons/devices.svg" alt="Allow local network" className="w-11 h-11" />
+                Allow local network
+              </span>
+                <span className="text-[11px] text-[#62626A] mt-1">
+                Printers, NAS and casting stay reachable with the kill switch on.
+              </span>
+              </div>
+
+              <button
+                  type="button"
+                  onClick={toggleAllowLan}
+                  className={`w-[42px] h-[26px] rounded-full flex items-center px-1 transition-colors ${
+                      allowLan ? "bg-[#2761FC]" : "bg-gray-300"
+                  }`}
+              >
+              <span
+                  className={`w-[20px] h-[20px] rounded-full bg-white flex items-center justify-center transition-transform ${
+                      allowLan ? "translate-x-4" : "translate-x-0"
+                  }`}
+              >
+                {allowLan && (
+                    <img src="/icons/blue-tick.svg" alt="Tick" className="w-4 h-4" />
+                )}
+              </span>
+              </button>
+            </div>
+
             <button
                 type="button"
                 onClick={() => setShowLogout(true)}